@@ -0,0 +1,22 @@
+//! The author recorded on new versions, taken from the settings or looked up from the system.
+
+use crate::env::Env;
+use crate::hostname::hostname;
+use crate::repository_data::Author;
+use std::env;
+
+/// None if no author name is set and the login name cannot be determined.
+pub fn current_author(env: &Env) -> Option<Author> {
+    let name = env.author_name.clone().or_else(login_name)?;
+
+    Some(Author {
+        name,
+        email: env.author_email.clone(),
+        hostname: if env.record_hostname { hostname() } else { None },
+    })
+}
+
+fn login_name() -> Option<String> {
+    let variable = if cfg!(windows) { "USERNAME" } else { "USER" };
+    env::var(variable).ok().filter(|n| !n.is_empty())
+}
@@ -65,14 +65,12 @@ pub fn error<T>(message: impl Into<String>) -> BiverResult<T> {
     Err(BiverError {
         error_message: message.into(),
         severity: BiverErrorSeverity::Error,
-    }
-    .into())
+    })
 }
 
 pub fn warning<T>(message: impl Into<String>) -> BiverResult<T> {
     Err(BiverError {
         error_message: message.into(),
         severity: BiverErrorSeverity::Warning,
-    }
-    .into())
+    })
 }
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+const BLOCK_SIZE: usize = 16;
+const MAX_INDEXED_BLOCKS: usize = 1 << 22;
+
+const HASH_BASE: u32 = 257;
+
+pub struct BlockMatch {
+    pub target_start: usize,
+    pub source_start: usize,
+    pub length: usize,
+}
+
+/// Finds non-overlapping exact matches of the target in the source using a rolling hash over fixed-size blocks of the source.
+/// Matches are returned in target order.
+pub fn find_matches(source: &[u8], target: &[u8]) -> Vec<BlockMatch> {
+    let mut matches = Vec::new();
+
+    if source.len() < BLOCK_SIZE || target.len() < BLOCK_SIZE {
+        return matches;
+    }
+
+    let block_stride = (source.len() / BLOCK_SIZE / MAX_INDEXED_BLOCKS + 1) * BLOCK_SIZE;
+
+    let mut index: HashMap<u32, usize> = HashMap::with_capacity(source.len() / block_stride + 1);
+    let mut position = 0;
+    while position + BLOCK_SIZE <= source.len() {
+        index.entry(block_hash(&source[position..position + BLOCK_SIZE])).or_insert(position);
+        position += block_stride;
+    }
+
+    let mut previous_match_end = 0;
+    let mut position = 0;
+    let mut hash = block_hash(&target[0..BLOCK_SIZE]);
+
+    while position + BLOCK_SIZE <= target.len() {
+        let candidate = index
+            .get(&hash)
+            .copied()
+            .filter(|&source_position| source[source_position..source_position + BLOCK_SIZE] == target[position..position + BLOCK_SIZE]);
+
+        if let Some(source_position) = candidate {
+            let mut match_start = position;
+            let mut match_source_start = source_position;
+            while match_start > previous_match_end && match_source_start > 0 && target[match_start - 1] == source[match_source_start - 1] {
+                match_start -= 1;
+                match_source_start -= 1;
+            }
+
+            let mut match_end = position + BLOCK_SIZE;
+            let mut match_source_end = source_position + BLOCK_SIZE;
+            while match_end < target.len() && match_source_end < source.len() && target[match_end] == source[match_source_end] {
+                match_end += 1;
+                match_source_end += 1;
+            }
+
+            matches.push(BlockMatch {
+                target_start: match_start,
+                source_start: match_source_start,
+                length: match_end - match_start,
+            });
+
+            previous_match_end = match_end;
+            position = match_end;
+
+            if position + BLOCK_SIZE <= target.len() {
+                hash = block_hash(&target[position..position + BLOCK_SIZE]);
+            }
+
+            continue;
+        }
+
+        if position + BLOCK_SIZE < target.len() {
+            hash = roll_block_hash(hash, target[position], target[position + BLOCK_SIZE]);
+        }
+        position += 1;
+    }
+
+    matches
+}
+
+fn block_hash(block: &[u8]) -> u32 {
+    block.iter().fold(0u32, |hash, &byte| hash.wrapping_mul(HASH_BASE).wrapping_add(byte as u32))
+}
+
+fn roll_block_hash(hash: u32, outgoing: u8, incoming: u8) -> u32 {
+    let outgoing_weight = HASH_BASE.wrapping_pow(BLOCK_SIZE as u32 - 1);
+    hash.wrapping_sub((outgoing as u32).wrapping_mul(outgoing_weight))
+        .wrapping_mul(HASH_BASE)
+        .wrapping_add(incoming as u32)
+}
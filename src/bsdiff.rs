@@ -0,0 +1,326 @@
+//! bsdiff-style delta encoder and decoder.
+//!
+//! Like bsdiff, the patch is a sequence of control entries `(diff length, extra length, seek)` followed by
+//! a diff stream (bytewise differences against approximately matching source regions) and an extra stream
+//! (literal bytes). The three streams are compressed with zstd separately.
+
+use crate::block_matching;
+use std::io;
+
+const MAGIC: &[u8; 8] = b"BIVERBSD";
+const HEADER_LENGTH: usize = MAGIC.len() + 3 * 8;
+
+const ZSTD_LEVEL: i32 = 9;
+
+struct Control {
+    diff_length: u64,
+    extra_length: u64,
+    seek: i64,
+}
+
+struct Region {
+    target_start: usize,
+    source_start: usize,
+    length: usize,
+}
+
+pub fn encode(source: &[u8], target: &[u8]) -> io::Result<Vec<u8>> {
+    let regions = find_regions(source, target);
+
+    let mut controls = Vec::new();
+    let mut diff = Vec::new();
+    let mut extra = Vec::new();
+
+    let first_target_start = regions.first().map(|r| r.target_start).unwrap_or(target.len());
+    let first_source_start = regions.first().map(|r| r.source_start).unwrap_or(0);
+
+    controls.push(Control {
+        diff_length: 0,
+        extra_length: first_target_start as u64,
+        seek: first_source_start as i64,
+    });
+    extra.extend_from_slice(&target[..first_target_start]);
+
+    for (i, region) in regions.iter().enumerate() {
+        let next_region = regions.get(i + 1);
+
+        let region_target_end = region.target_start + region.length;
+        let next_target_start = next_region.map(|r| r.target_start).unwrap_or(target.len());
+        let next_source_start = next_region.map(|r| r.source_start).unwrap_or(region.source_start + region.length);
+
+        for offset in 0..region.length {
+            diff.push(target[region.target_start + offset].wrapping_sub(source[region.source_start + offset]));
+        }
+
+        extra.extend_from_slice(&target[region_target_end..next_target_start]);
+
+        controls.push(Control {
+            diff_length: region.length as u64,
+            extra_length: (next_target_start - region_target_end) as u64,
+            seek: next_source_start as i64 - (region.source_start + region.length) as i64,
+        });
+    }
+
+    let mut control_bytes = Vec::with_capacity(controls.len() * 24);
+    for control in &controls {
+        control_bytes.extend_from_slice(&control.diff_length.to_le_bytes());
+        control_bytes.extend_from_slice(&control.extra_length.to_le_bytes());
+        control_bytes.extend_from_slice(&control.seek.to_le_bytes());
+    }
+
+    let control_bytes = zstd::encode_all(control_bytes.as_slice(), ZSTD_LEVEL)?;
+    let diff = zstd::encode_all(diff.as_slice(), ZSTD_LEVEL)?;
+    let extra = zstd::encode_all(extra.as_slice(), ZSTD_LEVEL)?;
+
+    let mut patch = Vec::with_capacity(HEADER_LENGTH + control_bytes.len() + diff.len() + extra.len());
+    patch.extend_from_slice(MAGIC);
+    patch.extend_from_slice(&(control_bytes.len() as u64).to_le_bytes());
+    patch.extend_from_slice(&(diff.len() as u64).to_le_bytes());
+    patch.extend_from_slice(&(target.len() as u64).to_le_bytes());
+    patch.extend_from_slice(&control_bytes);
+    patch.extend_from_slice(&diff);
+    patch.extend_from_slice(&extra);
+
+    Ok(patch)
+}
+
+pub fn decode(source: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+    if patch.len() < HEADER_LENGTH || &patch[..MAGIC.len()] != MAGIC {
+        return Err(invalid_data("not a bsdiff patch"));
+    }
+
+    let read_u64 = |offset: usize| u64::from_le_bytes(patch[offset..offset + 8].try_into().unwrap());
+
+    let control_length = usize::try_from(read_u64(MAGIC.len())).map_err(|_| invalid_data("bsdiff header is corrupted"))?;
+    let diff_length = usize::try_from(read_u64(MAGIC.len() + 8)).map_err(|_| invalid_data("bsdiff header is corrupted"))?;
+    let target_length = usize::try_from(read_u64(MAGIC.len() + 16)).map_err(|_| invalid_data("bsdiff header is corrupted"))?;
+
+    let control_end = HEADER_LENGTH.checked_add(control_length).filter(|&end| end <= patch.len());
+    let diff_end = control_end.and_then(|end| end.checked_add(diff_length)).filter(|&end| end <= patch.len());
+    let (Some(control_end), Some(diff_end)) = (control_end, diff_end) else {
+        return Err(invalid_data("bsdiff header is corrupted"));
+    };
+
+    let control_bytes = zstd::decode_all(&patch[HEADER_LENGTH..control_end])?;
+    let diff = zstd::decode_all(&patch[control_end..diff_end])?;
+    let extra = zstd::decode_all(&patch[diff_end..])?;
+
+    if control_bytes.len() % 24 != 0 {
+        return Err(invalid_data("bsdiff control stream is corrupted"));
+    }
+
+    // Every target byte comes from the diff or the extra stream, which bounds the allocation for a corrupted target length.
+    let mut target = Vec::with_capacity(target_length.min(diff.len() + extra.len()));
+    let mut source_position: i64 = 0;
+    let mut diff_position: usize = 0;
+    let mut extra_position: usize = 0;
+
+    for entry in control_bytes.chunks_exact(24) {
+        let diff_length = u64::from_le_bytes(entry[0..8].try_into().unwrap()) as usize;
+        let extra_length = u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize;
+        let seek = i64::from_le_bytes(entry[16..24].try_into().unwrap());
+
+        let source_start = usize::try_from(source_position).map_err(|_| invalid_data("bsdiff seek out of range"))?;
+        let source_region = source_start.checked_add(diff_length).and_then(|end| source.get(source_start..end));
+        let diff_region = diff_position.checked_add(diff_length).and_then(|end| diff.get(diff_position..end));
+        let (Some(source_region), Some(diff_region)) = (source_region, diff_region) else {
+            return Err(invalid_data("bsdiff diff out of range"));
+        };
+
+        target.extend(source_region.iter().zip(diff_region).map(|(s, d)| s.wrapping_add(*d)));
+        diff_position += diff_length;
+
+        let extra_region = extra_position.checked_add(extra_length).and_then(|end| extra.get(extra_position..end));
+        let Some(extra_region) = extra_region else {
+            return Err(invalid_data("bsdiff extra out of range"));
+        };
+
+        target.extend_from_slice(extra_region);
+        extra_position += extra_length;
+
+        source_position = i64::try_from(diff_length)
+            .ok()
+            .and_then(|diff_length| source_position.checked_add(diff_length))
+            .and_then(|position| position.checked_add(seek))
+            .ok_or_else(|| invalid_data("bsdiff seek out of range"))?;
+    }
+
+    if target.len() != target_length {
+        return Err(invalid_data("bsdiff target length mismatch"));
+    }
+
+    Ok(target)
+}
+
+/// Widens exact block matches with bsdiff's approximate extension: a region keeps growing while at least half of its bytes match.
+fn find_regions(source: &[u8], target: &[u8]) -> Vec<Region> {
+    let matches = block_matching::find_matches(source, target);
+
+    let mut regions: Vec<Region> = Vec::with_capacity(matches.len());
+
+    for (i, block_match) in matches.iter().enumerate() {
+        let previous_end = regions.last().map(|r| r.target_start + r.length).unwrap_or(0);
+        let next_start = matches.get(i + 1).map(|m| m.target_start).unwrap_or(target.len());
+
+        let backward_limit = (block_match.target_start - previous_end).min(block_match.source_start);
+        let backward_length = approximate_match_length(backward_limit, |k| target[block_match.target_start - 1 - k] == source[block_match.source_start - 1 - k]);
+
+        let match_target_end = block_match.target_start + block_match.length;
+        let match_source_end = block_match.source_start + block_match.length;
+
+        let forward_limit = (next_start - match_target_end).min(source.len() - match_source_end);
+        let forward_length = approximate_match_length(forward_limit, |k| target[match_target_end + k] == source[match_source_end + k]);
+
+        regions.push(Region {
+            target_start: block_match.target_start - backward_length,
+            source_start: block_match.source_start - backward_length,
+            length: backward_length + block_match.length + forward_length,
+        });
+    }
+
+    regions
+}
+
+fn approximate_match_length(limit: usize, bytes_equal: impl Fn(usize) -> bool) -> usize {
+    let mut equal_count: i64 = 0;
+    let mut best_score: i64 = 0;
+    let mut best_length = 0;
+
+    for k in 0..limit {
+        if bytes_equal(k) {
+            equal_count += 1;
+        }
+
+        let length = k as i64 + 1;
+        let score = equal_count * 2 - length;
+
+        if score > best_score {
+            best_score = score;
+            best_length = k + 1;
+        }
+    }
+
+    best_length
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{edited, pseudo_random_bytes};
+
+    fn assert_round_trip(source: &[u8], target: &[u8]) {
+        let patch = encode(source, target).unwrap();
+        assert_eq!(decode(source, &patch).unwrap(), target);
+    }
+
+    /// A patch with the given control entries and uncompressed diff and extra streams.
+    fn patch_of(controls: &[(u64, u64, i64)], diff: &[u8], extra: &[u8], target_length: u64) -> Vec<u8> {
+        let control_bytes: Vec<u8> = controls
+            .iter()
+            .flat_map(|(diff_length, extra_length, seek)| [diff_length.to_le_bytes(), extra_length.to_le_bytes(), seek.to_le_bytes()].concat())
+            .collect();
+
+        let control_bytes = zstd::encode_all(control_bytes.as_slice(), ZSTD_LEVEL).unwrap();
+        let diff = zstd::encode_all(diff, ZSTD_LEVEL).unwrap();
+        let extra = zstd::encode_all(extra, ZSTD_LEVEL).unwrap();
+
+        let mut patch = MAGIC.to_vec();
+        patch.extend_from_slice(&(control_bytes.len() as u64).to_le_bytes());
+        patch.extend_from_slice(&(diff.len() as u64).to_le_bytes());
+        patch.extend_from_slice(&target_length.to_le_bytes());
+        patch.extend_from_slice(&control_bytes);
+        patch.extend_from_slice(&diff);
+        patch.extend_from_slice(&extra);
+        patch
+    }
+
+    #[test]
+    fn round_trips_edited_content() {
+        let source = pseudo_random_bytes(200_000, 1);
+        assert_round_trip(&source, &edited(&source));
+    }
+
+    #[test]
+    fn round_trips_unrelated_content() {
+        assert_round_trip(&pseudo_random_bytes(10_000, 2), &pseudo_random_bytes(12_000, 3));
+    }
+
+    #[test]
+    fn round_trips_empty_source_and_target() {
+        let content = pseudo_random_bytes(1000, 4);
+        assert_round_trip(&[], &content);
+        assert_round_trip(&content, &[]);
+        assert_round_trip(&[], &[]);
+    }
+
+    #[test]
+    fn rejects_patch_without_magic() {
+        let source = pseudo_random_bytes(1000, 5);
+        let mut patch = encode(&source, &edited(&source)).unwrap();
+        patch[0] ^= 0xFF;
+
+        assert_eq!(decode(&source, &patch).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(&source, &patch[..HEADER_LENGTH - 1]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_patch() {
+        let source = pseudo_random_bytes(10_000, 6);
+        let patch = encode(&source, &edited(&source)).unwrap();
+
+        for length in [HEADER_LENGTH, HEADER_LENGTH + 1, patch.len() / 2, patch.len() - 1] {
+            assert!(decode(&source, &patch[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_stream_lengths_beyond_the_patch() {
+        let source = pseudo_random_bytes(1000, 7);
+        let mut patch = encode(&source, &edited(&source)).unwrap();
+        patch[MAGIC.len()..MAGIC.len() + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+
+        assert_eq!(decode(&source, &patch).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_controls_reading_beyond_the_streams() {
+        let source = [1, 2, 3, 4];
+
+        let diff_beyond_source = patch_of(&[(5, 0, 0)], &[0; 5], &[], 5);
+        let diff_beyond_diff_stream = patch_of(&[(4, 0, 0)], &[0; 2], &[], 4);
+        let extra_beyond_extra_stream = patch_of(&[(0, 3, 0)], &[], &[9], 3);
+        let seek_before_source = patch_of(&[(0, 0, -1), (1, 0, 0)], &[0], &[], 1);
+        let seek_overflowing = patch_of(&[(1, 0, i64::MAX), (1, 0, 0)], &[0, 0], &[], 2);
+        let length_mismatch = patch_of(&[(4, 0, 0)], &[0; 4], &[], 5);
+        let huge_target_length = patch_of(&[(4, 0, 0)], &[0; 4], &[], u64::MAX);
+
+        for patch in [
+            diff_beyond_source,
+            diff_beyond_diff_stream,
+            extra_beyond_extra_stream,
+            seek_before_source,
+            seek_overflowing,
+            length_mismatch,
+            huge_target_length,
+        ] {
+            assert_eq!(decode(&source, &patch).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn rejects_control_stream_of_partial_entries() {
+        let mut patch = patch_of(&[], &[], &[], 0);
+        let control_bytes = zstd::encode_all([0u8; 10].as_slice(), ZSTD_LEVEL).unwrap();
+        patch.truncate(HEADER_LENGTH);
+        patch[MAGIC.len()..MAGIC.len() + 8].copy_from_slice(&(control_bytes.len() as u64).to_le_bytes());
+        patch.extend_from_slice(&control_bytes);
+        patch.extend_from_slice(&zstd::encode_all([].as_slice(), ZSTD_LEVEL).unwrap());
+        patch.extend_from_slice(&zstd::encode_all([].as_slice(), ZSTD_LEVEL).unwrap());
+
+        assert_eq!(decode(&[], &patch).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,186 @@
+//! Single-file bundles of a repository: a manifest listing every file with its length and checksum, followed by the contents of the files.
+
+use crate::hash;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+use xxhash_rust::xxh3::Xxh3;
+
+const MAGIC: &[u8] = b"BIVER-BUNDLE\n";
+
+pub const CURRENT_BUNDLE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleManifest {
+    pub format_version: u32,
+    /// File name of the versioned file the bundle was created from.
+    pub versioned_file_name: String,
+    pub files: Vec<BundleFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct BundleFile {
+    pub name: String,
+    pub length: u64,
+    pub xxh3_128: u128,
+}
+
+pub enum BundleFileSource {
+    Path(PathBuf),
+    Content(Vec<u8>),
+}
+
+pub struct Bundle {
+    path: PathBuf,
+    pub manifest: BundleManifest,
+    contents_offset: u64,
+}
+
+pub enum OpenBundleResult {
+    Ok(Bundle),
+    NotABundle,
+    NewerFormatVersion(u32),
+}
+
+/// Writes through a temporary file, so that an interrupted write never leaves a partial bundle under the final name.
+pub fn write_bundle(path: &Path, versioned_file_name: &str, files: &[(String, BundleFileSource)]) -> io::Result<()> {
+    let mut manifest = BundleManifest {
+        format_version: CURRENT_BUNDLE_FORMAT_VERSION,
+        versioned_file_name: versioned_file_name.to_string(),
+        files: Vec::with_capacity(files.len()),
+    };
+
+    for (name, source) in files {
+        let (length, xxh3_128) = match source {
+            BundleFileSource::Path(path) => (fs::metadata(path)?.len(), hash::xxh3_128(&File::open(path)?)?),
+            BundleFileSource::Content(content) => (content.len() as u64, hash::xxh3_128_of_bytes(content)),
+        };
+
+        manifest.files.push(BundleFile {
+            name: name.clone(),
+            length,
+            xxh3_128,
+        });
+    }
+
+    let mut temp_file_name = path.file_name().expect("Bundle path must have a file name").to_os_string();
+    temp_file_name.push(".tmp");
+    let temp_file_path = path.with_file_name(temp_file_name);
+
+    let mut writer = BufWriter::new(File::create(&temp_file_path)?);
+
+    let manifest = serde_json::to_vec(&manifest)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&(manifest.len() as u64).to_le_bytes())?;
+    writer.write_all(&manifest)?;
+
+    for (_, source) in files {
+        match source {
+            BundleFileSource::Path(path) => {
+                io::copy(&mut File::open(path)?, &mut writer)?;
+            }
+            BundleFileSource::Content(content) => writer.write_all(content)?,
+        }
+    }
+
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+
+    fs::rename(&temp_file_path, path)
+}
+
+impl Bundle {
+    pub fn open(path: &Path) -> io::Result<OpenBundleResult> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = [0; MAGIC.len()];
+        let mut manifest_length = [0; 8];
+        match reader.read_exact(&mut magic).and_then(|_| reader.read_exact(&mut manifest_length)) {
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(OpenBundleResult::NotABundle),
+            result => result?,
+        }
+        if magic != MAGIC {
+            return Ok(OpenBundleResult::NotABundle);
+        }
+
+        let manifest_length = u64::from_le_bytes(manifest_length);
+        let mut manifest = Vec::new();
+        reader.by_ref().take(manifest_length).read_to_end(&mut manifest)?;
+
+        let manifest: BundleManifest = match serde_json::from_slice(&manifest) {
+            Ok(manifest) => manifest,
+            Err(_) => return Ok(OpenBundleResult::NotABundle),
+        };
+
+        if manifest.format_version > CURRENT_BUNDLE_FORMAT_VERSION {
+            return Ok(OpenBundleResult::NewerFormatVersion(manifest.format_version));
+        }
+
+        Ok(OpenBundleResult::Ok(Bundle {
+            path: path.to_path_buf(),
+            manifest,
+            contents_offset: (MAGIC.len() + 8) as u64 + manifest_length,
+        }))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.manifest.files.iter().any(|f| f.name == name)
+    }
+
+    /// Names of the files whose content is truncated or does not match its checksum.
+    pub fn corrupted_files(&self) -> io::Result<Vec<String>> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(self.contents_offset))?;
+
+        let mut corrupted_files = Vec::new();
+        let mut buffer = [0; 8192];
+
+        for file in &self.manifest.files {
+            let mut file_reader = reader.by_ref().take(file.length);
+            let mut hasher = Xxh3::new();
+            let mut length = 0;
+
+            loop {
+                let bytes_read = file_reader.read(&mut buffer)?;
+                if bytes_read == 0 {
+                    break;
+                }
+                hasher.update(&buffer[..bytes_read]);
+                length += bytes_read as u64;
+            }
+
+            if length != file.length || hasher.digest128() != file.xxh3_128 {
+                corrupted_files.push(file.name.clone());
+            }
+        }
+
+        Ok(corrupted_files)
+    }
+
+    pub fn read_file(&self, name: &str) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.file_reader(name)?.read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    pub fn extract_file(&self, name: &str, destination: &Path) -> io::Result<()> {
+        let mut destination_file = File::create(destination)?;
+        io::copy(&mut self.file_reader(name)?, &mut destination_file)?;
+        destination_file.sync_all()
+    }
+
+    fn file_reader(&self, name: &str) -> io::Result<impl Read> {
+        let mut offset = self.contents_offset;
+        for file in &self.manifest.files {
+            if file.name == name {
+                let mut reader = BufReader::new(File::open(&self.path)?);
+                reader.seek(SeekFrom::Start(offset))?;
+                return Ok(reader.take(file.length));
+            }
+            offset += file.length;
+        }
+
+        Err(io::Error::new(ErrorKind::NotFound, format!("{} is not in the bundle", name)))
+    }
+}
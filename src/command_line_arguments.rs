@@ -1,5 +1,5 @@
-use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 pub struct CommandLineArguments {
@@ -11,10 +11,62 @@ pub struct CommandLineArguments {
     #[arg(global(true), long = "image-magick-path", env = "BIVER_IMAGE_MAGICK_PATH")]
     pub image_magick_path: Option<PathBuf>,
 
+    /// Codec used for storing new versions as patches (default: xdelta3)
+    #[arg(global(true), long = "patch-codec", env = "BIVER_PATCH_CODEC")]
+    pub patch_codec: Option<PatchCodec>,
+
+    /// Codec used for storing new versions in full (default: raw)
+    #[arg(global(true), long = "full-codec", env = "BIVER_FULL_CODEC")]
+    pub full_codec: Option<FullCodec>,
+
+    /// Seconds to wait for another biver process to release the repository lock (default: 0)
+    #[arg(global(true), long = "lock-timeout", env = "BIVER_LOCK_TIMEOUT")]
+    pub lock_timeout: Option<u64>,
+
+    /// Author name recorded on new versions (default: the author-name setting or the login name)
+    #[arg(global(true), long = "author-name", env = "BIVER_AUTHOR_NAME")]
+    pub author_name: Option<String>,
+
+    /// Author email recorded on new versions (default: the author-email setting)
+    #[arg(global(true), long = "author-email", env = "BIVER_AUTHOR_EMAIL")]
+    pub author_email: Option<String>,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+#[derive(Copy, Clone, ValueEnum)]
+pub enum PatchCodec {
+    /// VCDIFF deltas compatible with xdelta3
+    Xdelta3,
+    /// bsdiff-style deltas compressed with zstd
+    Bsdiff,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum FullCodec {
+    /// Plain copies of the versioned file
+    Raw,
+    /// zstd-compressed copies of the versioned file
+    Zstd,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum StorageMode {
+    /// Patches are stored against parents, so the root and periodic keyframes are stored in full
+    Forward,
+    /// Branch tips are stored in full and ancestors are stored as patches against their children
+    Reverse,
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum RepositoryLayout {
+    /// In a .biver directory next to the versioned file
+    Sibling,
+    /// In the central store in the user's data directory, with only a small .biver pointer file next to the versioned file
+    Central,
+}
+
 #[derive(Subcommand)]
 pub enum Command {
     /// Show the current status of the repository
@@ -23,9 +75,21 @@ pub enum Command {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
 
-        /// Show all versions (by default, limited to 20 most recent)
+        /// Show all versions (by default, limited to the most recent ones as set by max-versions-to-print)
         #[arg(short = 'a', long = "all")]
         all: bool,
+
+        /// Only show versions whose author name, email or computer name contains this text (case-insensitive)
+        #[arg(long = "author")]
+        author: Option<String>,
+
+        /// Only show versions that have this property set (KEY) or set to this value (KEY=VALUE). May be repeated.
+        #[arg(long = "prop", value_name = "KEY[=VALUE]")]
+        properties: Vec<String>,
+
+        /// Print the versions as JSON, including their authors and properties (always all matching versions)
+        #[arg(long = "json")]
+        json: bool,
     },
 
     /// Preview a version
@@ -34,7 +98,7 @@ pub enum Command {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
 
-        /// Target branch or version to preview. May be one of the following (in order of precedence): branch name, version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        /// Target branch or version to preview. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
         target: String,
     },
 
@@ -44,10 +108,10 @@ pub enum Command {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
 
-        /// Target branch or version to compare. May be one of the following (in order of precedence): branch name, version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        /// Target branch or version to compare. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
         target1: String,
 
-        /// (Default: head) Target branch or version to compare. May be one of the following (in order of precedence): branch name, version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        /// (Default: head) Target branch or version to compare. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
         target2: Option<String>,
     },
 
@@ -56,10 +120,18 @@ pub enum Command {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
 
-        /// The initial branch name (default: main)
+        /// The initial branch name (default: the default-branch setting)
         #[arg(short = 'b', long = "branch")]
         initial_branch_name: Option<String>,
 
+        /// How versions are laid out in storage (default: the storage-mode setting)
+        #[arg(long = "storage-mode")]
+        storage_mode: Option<StorageMode>,
+
+        /// Where the repository is kept (default: the layout setting)
+        #[arg(long = "layout")]
+        layout: Option<RepositoryLayout>,
+
         /// Description of the initial version
         #[arg(value_name = "INITIAL_VERSION_DESCRIPTION")]
         initial_version_description: Option<String>,
@@ -90,6 +162,25 @@ pub enum Command {
         description: Option<String>,
     },
 
+    /// Commit the content of another version as a new version on the current branch
+    #[command(name = "cherry-pick")]
+    CherryPick {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Target branch or version to take the content from. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        target: String,
+    },
+
+    /// Commit the content of an older version as a new version on the current branch
+    Revert {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Version whose content to restore. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        target: String,
+    },
+
     /// Change description of the specified version
     Reword {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
@@ -103,6 +194,67 @@ pub enum Command {
         description: String,
     },
 
+    /// Move the versions that only a branch leads to onto another base version
+    Rebase {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Branch to rebase (default: the current branch)
+        #[arg(short = 'b', long = "branch")]
+        branch: Option<String>,
+
+        /// New base version. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        target: String,
+    },
+
+    /// Remove a version from the middle of history, keeping its descendants
+    Drop {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+
+        /// Version to drop. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        target: String,
+    },
+
+    /// Fold a range of versions into its newest version, merging their descriptions
+    Squash {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+
+        /// Oldest version of the range. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        from: String,
+
+        /// Newest version of the range, which keeps its content. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        to: String,
+    },
+
+    /// Undo the most recent operation that changed the repository
+    Undo {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+
+    /// Redo the most recently undone operation
+    Redo {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+
+    /// List the operations that changed the repository
+    Oplog {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+
     /// Discard uncommitted changes
     Discard {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
@@ -135,7 +287,7 @@ pub enum Command {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
 
-        /// Target branch or version to preview. May be one of the following (in order of precedence): branch name, version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        /// Target branch or version to preview. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
         target: String,
     },
 
@@ -148,10 +300,61 @@ pub enum Command {
         #[arg(short = 'o', long = "output")]
         output: Option<PathBuf>,
 
-        /// Target branch or version to restore. May be one of the following (in order of precedence): branch name, version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        /// Target branch or version to restore. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
         target: String,
     },
 
+    /// Move or rename the versioned file together with its repository
+    Mv {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// The versioned file has already been moved to the destination, only move the repository
+        #[arg(long = "after")]
+        after: bool,
+
+        /// New path of the versioned file, or a directory to move it into
+        destination: PathBuf,
+    },
+
+    /// Delete files in the repository directory that are not referenced by any version
+    Gc {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Only list the files that would be deleted
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+    },
+
+    /// Check that every version's blobs are present and that its content can be restored intact
+    Fsck {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+
+    /// Re-encode the content of all versions, choosing new full versions and patches
+    Repack {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Maximum number of consecutive patches before a version is stored in full (default: unlimited)
+        #[arg(long = "max-chain-depth")]
+        max_chain_depth: Option<usize>,
+
+        /// Maximum total size of a patch chain relative to the size of the version it produces (default: the max-patch-chain-ratio setting)
+        #[arg(long = "max-patch-ratio")]
+        max_patch_ratio: Option<f64>,
+
+        /// Switch the repository to another storage mode (default: keep the current one)
+        #[arg(long = "storage-mode")]
+        storage_mode: Option<StorageMode>,
+    },
+
     /// Create commands
     #[command(subcommand)]
     Create(CreateCommand),
@@ -168,6 +371,67 @@ pub enum Command {
     #[command(subcommand)]
     Delete(DeleteCommand),
 
+    /// Inspect and restore backups of the repository data
+    #[command(subcommand)]
+    Recover(RecoverCommand),
+
+    /// Pack a repository into a single file or create one from such a bundle
+    #[command(subcommand)]
+    Bundle(BundleCommand),
+
+    /// Create a versioned file and its repository from another repository or a bundle file, and add the repository as the origin remote
+    Clone {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Where the repository is kept (default: the layout setting)
+        #[arg(long = "layout")]
+        layout: Option<RepositoryLayout>,
+
+        /// Copy the blobs instead of sharing them with the source repository through hard links
+        #[arg(long = "copy")]
+        copy: bool,
+
+        /// Versioned file or repository directory of the source repository, or a bundle file
+        source: PathBuf,
+    },
+
+    /// Manage the remote repositories that versions are pushed to and pulled from
+    #[command(subcommand)]
+    Remote(RemoteCommand),
+
+    /// Manage key/value properties attached to versions
+    #[command(subcommand)]
+    Prop(PropCommand),
+
+    /// Send the versions of local branches to a remote repository and fast-forward its branches
+    Push {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Only push this branch. May be given multiple times (default: all branches)
+        #[arg(short = 'b', long = "branch")]
+        branches: Vec<String>,
+
+        /// Name of the remote
+        #[arg(default_value = "origin")]
+        remote: String,
+    },
+
+    /// Fetch the versions of all branches of a remote repository and fast-forward the local branches
+    Pull {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Name of the remote
+        #[arg(default_value = "origin")]
+        remote: String,
+    },
+
+    /// Read and change settings of the repository or, with --global, of all repositories
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
     /// List dependencies and check their statuses
     Dependencies,
 }
@@ -185,6 +449,19 @@ pub enum CreateCommand {
         /// The name of the new branch
         name: String,
     },
+
+    /// Create a tag, a name for a version that never moves
+    Tag {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// The name of the new tag
+        name: String,
+
+        /// Branch or version to tag. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        target: String,
+    },
 }
 
 #[derive(Subcommand)]
@@ -194,6 +471,248 @@ pub enum ListCommand {
         #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
         versioned_file_path: PathBuf,
     },
+
+    /// List tags and the versions they point to
+    Tags {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RecoverCommand {
+    /// List backups of the repository data
+    List {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+
+    /// Show how a backup differs from the current repository data
+    Diff {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Backup number as shown by the list command
+        backup: usize,
+    },
+
+    /// Replace the current repository data with a backup
+    Restore {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+
+        /// Backup number as shown by the list command
+        backup: usize,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum BundleCommand {
+    /// Pack the repository data and blobs into a bundle file
+    Create {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Only bundle this branch. May be given multiple times (default: all branches)
+        #[arg(short = 'b', long = "branch")]
+        branches: Vec<String>,
+
+        /// Only bundle versions from this version on, making it the root of the bundled history. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(long = "since")]
+        since: Option<String>,
+
+        /// Path of the bundle file to create
+        output: PathBuf,
+    },
+
+    /// Create a repository from a bundle file and restore the versioned file at head
+    Import {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Where the repository is kept (default: the layout setting)
+        #[arg(long = "layout")]
+        layout: Option<RepositoryLayout>,
+
+        /// Path of the bundle file
+        bundle: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum RemoteCommand {
+    /// Add a remote repository
+    Add {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Name of the remote
+        name: String,
+
+        /// Versioned file of the remote repository, or its repository directory
+        path: PathBuf,
+    },
+
+    /// Remove a remote, together with the versions that only its remote-tracking branches lead to
+    Remove {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+
+        /// Name of the remote
+        name: String,
+    },
+
+    /// List remotes and their remote-tracking branches
+    List {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PropCommand {
+    /// Set a property of a version
+    Set {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Name of the property
+        key: String,
+
+        /// Value of the property
+        value: String,
+
+        /// Target branch or version. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        target: String,
+    },
+
+    /// Print the value of a property of a version
+    Get {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Name of the property
+        key: String,
+
+        /// Target branch or version. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        target: String,
+    },
+
+    /// Remove a property from a version
+    Unset {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Name of the property
+        key: String,
+
+        /// Target branch or version. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        target: String,
+    },
+
+    /// List the properties of a version
+    List {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Target branch or version. May be one of the following (in order of precedence): branch name, tag name, remote-tracking branch name (origin/main), version ID, head offset (~, ~1, ~2), version nickname (adjective-noun, adjectivenoun, an).
+        #[arg(default_value = "~")]
+        target: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommand {
+    /// Print the effective value of a setting
+    Get {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE", required_unless_present = "global")]
+        versioned_file_path: Option<PathBuf>,
+
+        /// Use the global configuration instead of the repository configuration
+        #[arg(long = "global")]
+        global: bool,
+
+        key: ConfigKey,
+    },
+
+    /// Change a setting
+    Set {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE", required_unless_present = "global")]
+        versioned_file_path: Option<PathBuf>,
+
+        /// Use the global configuration instead of the repository configuration
+        #[arg(long = "global")]
+        global: bool,
+
+        key: ConfigKey,
+
+        value: String,
+    },
+
+    /// Remove a setting, so that it falls back to the global configuration or its default value
+    Unset {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE", required_unless_present = "global")]
+        versioned_file_path: Option<PathBuf>,
+
+        /// Use the global configuration instead of the repository configuration
+        #[arg(long = "global")]
+        global: bool,
+
+        key: ConfigKey,
+    },
+
+    /// List the effective value of every setting and where it comes from
+    List {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE", required_unless_present = "global")]
+        versioned_file_path: Option<PathBuf>,
+
+        /// Use the global configuration instead of the repository configuration
+        #[arg(long = "global")]
+        global: bool,
+    },
+}
+
+#[derive(Copy, Clone, ValueEnum)]
+pub enum ConfigKey {
+    /// Name of the initial branch created by init (default: main)
+    DefaultBranch,
+    /// Storage mode of repositories created by init (default: forward)
+    StorageMode,
+    /// Where init keeps new repositories, sibling or central (default: sibling)
+    Layout,
+    /// Maximum total size of a patch chain relative to the size of the version it produces (default: 0.65)
+    MaxPatchChainRatio,
+    /// Number of versions shown by status without --all (default: 20)
+    MaxVersionsToPrint,
+    /// Maximum width and height of image previews in pixels (default: 1024)
+    PreviewSize,
+    /// Path to xdelta3 executable (default: searched in PATH)
+    Xdelta3Path,
+    /// Path to ImageMagick executable (default: searched in PATH)
+    ImageMagickPath,
+    /// Codec used for storing new versions as patches, xdelta3 or bsdiff (default: xdelta3)
+    PatchCodec,
+    /// Codec used for storing new versions in full, raw or zstd (default: raw)
+    FullCodec,
+    /// Seconds to wait for another biver process to release the repository lock (default: 0)
+    LockTimeout,
+    /// Author name recorded on new versions (default: the login name)
+    AuthorName,
+    /// Author email recorded on new versions (default: none)
+    AuthorEmail,
+    /// Whether the computer name is recorded together with the author, true or false (default: false)
+    RecordHostname,
 }
 
 #[derive(Subcommand)]
@@ -225,4 +744,77 @@ pub enum DeleteCommand {
         #[arg(value_name = "NAME")]
         name: String,
     },
+
+    /// Delete a tag, together with the versions that only it leads to
+    Tag {
+        #[arg(short = 'f', long = "file", env = "BIVER_VERSIONED_FILE")]
+        versioned_file_path: PathBuf,
+
+        /// Do not ask for confirmation
+        #[arg(short = 'y', long = "yes")]
+        confirmed: bool,
+
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+}
+
+impl Command {
+    /// The versioned file the command operates on, used for finding the repository configuration.
+    pub fn versioned_file_path(&self) -> Option<&Path> {
+        match self {
+            Command::Status { versioned_file_path, .. }
+            | Command::Preview { versioned_file_path, .. }
+            | Command::Compare { versioned_file_path, .. }
+            | Command::Init { versioned_file_path, .. }
+            | Command::Commit { versioned_file_path, .. }
+            | Command::Amend { versioned_file_path, .. }
+            | Command::CherryPick { versioned_file_path, .. }
+            | Command::Revert { versioned_file_path, .. }
+            | Command::Reword { versioned_file_path, .. }
+            | Command::Rebase { versioned_file_path, .. }
+            | Command::Drop { versioned_file_path, .. }
+            | Command::Undo { versioned_file_path }
+            | Command::Redo { versioned_file_path }
+            | Command::Oplog { versioned_file_path }
+            | Command::Squash { versioned_file_path, .. }
+            | Command::Discard { versioned_file_path, .. }
+            | Command::Reset { versioned_file_path, .. }
+            | Command::Checkout { versioned_file_path, .. }
+            | Command::Restore { versioned_file_path, .. }
+            | Command::Mv { versioned_file_path, .. }
+            | Command::Gc { versioned_file_path, .. }
+            | Command::Fsck { versioned_file_path, .. }
+            | Command::Repack { versioned_file_path, .. }
+            | Command::Create(CreateCommand::Branch { versioned_file_path, .. })
+            | Command::Create(CreateCommand::Tag { versioned_file_path, .. })
+            | Command::List(ListCommand::Branches { versioned_file_path })
+            | Command::List(ListCommand::Tags { versioned_file_path })
+            | Command::Rename(RenameCommand::Branch { versioned_file_path, .. })
+            | Command::Delete(DeleteCommand::Branch { versioned_file_path, .. })
+            | Command::Delete(DeleteCommand::Tag { versioned_file_path, .. })
+            | Command::Recover(RecoverCommand::List { versioned_file_path })
+            | Command::Recover(RecoverCommand::Diff { versioned_file_path, .. })
+            | Command::Recover(RecoverCommand::Restore { versioned_file_path, .. })
+            | Command::Bundle(BundleCommand::Create { versioned_file_path, .. })
+            | Command::Bundle(BundleCommand::Import { versioned_file_path, .. })
+            | Command::Clone { versioned_file_path, .. }
+            | Command::Remote(RemoteCommand::Add { versioned_file_path, .. })
+            | Command::Remote(RemoteCommand::Remove { versioned_file_path, .. })
+            | Command::Remote(RemoteCommand::List { versioned_file_path })
+            | Command::Push { versioned_file_path, .. }
+            | Command::Pull { versioned_file_path, .. }
+            | Command::Prop(PropCommand::Set { versioned_file_path, .. })
+            | Command::Prop(PropCommand::Get { versioned_file_path, .. })
+            | Command::Prop(PropCommand::Unset { versioned_file_path, .. })
+            | Command::Prop(PropCommand::List { versioned_file_path, .. }) => Some(versioned_file_path),
+            Command::Config(
+                ConfigCommand::Get { versioned_file_path, .. }
+                | ConfigCommand::Set { versioned_file_path, .. }
+                | ConfigCommand::Unset { versioned_file_path, .. }
+                | ConfigCommand::List { versioned_file_path, .. },
+            ) => versioned_file_path.as_deref(),
+            Command::Dependencies => None,
+        }
+    }
 }
@@ -0,0 +1,346 @@
+//! Settings layered from the command line, the repository configuration file and the global configuration file, in order of precedence.
+
+use crate::repository_data::{ContentBlobCodec, ContentBlobKind, StorageMode};
+use crate::repository_io;
+use crate::repository_paths::{RepositoryLayout, RepositoryPaths};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+pub const CONFIG_FILE_NAME: &str = "config.json";
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub storage_mode: Option<StorageMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layout: Option<RepositoryLayout>,
+    /// Patch chains longer than this fraction of the versioned file length end with a version stored in full.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_patch_chain_ratio: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_versions_to_print: Option<usize>,
+    /// Maximum width and height of image previews in pixels.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preview_size: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub xdelta3_path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_magick_path: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub patch_codec: Option<ContentBlobCodec>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_codec: Option<ContentBlobCodec>,
+    /// Seconds to wait for another biver process to release the repository lock.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lock_timeout: Option<u64>,
+    /// Recorded as the author of new versions. Defaults to the login name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author_email: Option<String>,
+    /// Whether the name of the computer is recorded together with the author of new versions.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub record_hostname: Option<bool>,
+}
+
+impl Config {
+    /// Tool paths have no default because the tools are searched in PATH. The author has no default because it is looked up from the system.
+    pub fn defaults() -> Config {
+        Config {
+            default_branch: Some("main".to_string()),
+            storage_mode: Some(StorageMode::Forward),
+            layout: Some(RepositoryLayout::Sibling),
+            max_patch_chain_ratio: Some(0.65),
+            max_versions_to_print: Some(20),
+            preview_size: Some(1024),
+            xdelta3_path: None,
+            image_magick_path: None,
+            patch_codec: Some(ContentBlobCodec::Xdelta3),
+            full_codec: Some(ContentBlobCodec::Raw),
+            lock_timeout: Some(0),
+            author_name: None,
+            author_email: None,
+            record_hostname: Some(false),
+        }
+    }
+
+    /// Settings missing from this configuration are taken from the lower one.
+    pub fn or(self, lower: Config) -> Config {
+        Config {
+            default_branch: self.default_branch.or(lower.default_branch),
+            storage_mode: self.storage_mode.or(lower.storage_mode),
+            layout: self.layout.or(lower.layout),
+            max_patch_chain_ratio: self.max_patch_chain_ratio.or(lower.max_patch_chain_ratio),
+            max_versions_to_print: self.max_versions_to_print.or(lower.max_versions_to_print),
+            preview_size: self.preview_size.or(lower.preview_size),
+            xdelta3_path: self.xdelta3_path.or(lower.xdelta3_path),
+            image_magick_path: self.image_magick_path.or(lower.image_magick_path),
+            patch_codec: self.patch_codec.or(lower.patch_codec),
+            full_codec: self.full_codec.or(lower.full_codec),
+            lock_timeout: self.lock_timeout.or(lower.lock_timeout),
+            author_name: self.author_name.or(lower.author_name),
+            author_email: self.author_email.or(lower.author_email),
+            record_hostname: self.record_hostname.or(lower.record_hostname),
+        }
+    }
+
+    pub fn get(&self, key: ConfigKey) -> Option<String> {
+        match key {
+            ConfigKey::DefaultBranch => self.default_branch.clone(),
+            ConfigKey::StorageMode => self.storage_mode.map(|m| storage_mode_name(m).to_string()),
+            ConfigKey::Layout => self.layout.map(|l| layout_name(l).to_string()),
+            ConfigKey::MaxPatchChainRatio => self.max_patch_chain_ratio.map(|r| r.to_string()),
+            ConfigKey::MaxVersionsToPrint => self.max_versions_to_print.map(|n| n.to_string()),
+            ConfigKey::PreviewSize => self.preview_size.map(|s| s.to_string()),
+            ConfigKey::Xdelta3Path => self.xdelta3_path.as_ref().map(|p| p.display().to_string()),
+            ConfigKey::ImageMagickPath => self.image_magick_path.as_ref().map(|p| p.display().to_string()),
+            ConfigKey::PatchCodec => self.patch_codec.map(|c| codec_name(c).to_string()),
+            ConfigKey::FullCodec => self.full_codec.map(|c| codec_name(c).to_string()),
+            ConfigKey::LockTimeout => self.lock_timeout.map(|t| t.to_string()),
+            ConfigKey::AuthorName => self.author_name.clone(),
+            ConfigKey::AuthorEmail => self.author_email.clone(),
+            ConfigKey::RecordHostname => self.record_hostname.map(|r| r.to_string()),
+        }
+    }
+
+    pub fn set(&mut self, key: ConfigKey, value: &str) -> SetConfigResult {
+        let valid = match key {
+            ConfigKey::DefaultBranch => set_parsed(&mut self.default_branch, Some(value.to_string()).filter(|b| !b.is_empty())),
+            ConfigKey::StorageMode => set_parsed(&mut self.storage_mode, parse_storage_mode(value)),
+            ConfigKey::Layout => set_parsed(&mut self.layout, parse_layout(value)),
+            ConfigKey::MaxPatchChainRatio => set_parsed(&mut self.max_patch_chain_ratio, value.parse().ok().filter(|r: &f64| r.is_finite() && *r > 0.0)),
+            ConfigKey::MaxVersionsToPrint => set_parsed(&mut self.max_versions_to_print, value.parse().ok().filter(|n| *n > 0)),
+            ConfigKey::PreviewSize => set_parsed(&mut self.preview_size, value.parse().ok().filter(|s| *s > 0)),
+            ConfigKey::Xdelta3Path => set_parsed(&mut self.xdelta3_path, Some(PathBuf::from(value)).filter(|_| !value.is_empty())),
+            ConfigKey::ImageMagickPath => set_parsed(&mut self.image_magick_path, Some(PathBuf::from(value)).filter(|_| !value.is_empty())),
+            ConfigKey::PatchCodec => set_parsed(&mut self.patch_codec, parse_codec(value, ContentBlobKind::Patch)),
+            ConfigKey::FullCodec => set_parsed(&mut self.full_codec, parse_codec(value, ContentBlobKind::Full)),
+            ConfigKey::LockTimeout => set_parsed(&mut self.lock_timeout, value.parse().ok()),
+            ConfigKey::AuthorName => set_parsed(&mut self.author_name, Some(value.to_string()).filter(|n| !n.is_empty())),
+            ConfigKey::AuthorEmail => set_parsed(&mut self.author_email, Some(value.to_string()).filter(|e| !e.is_empty())),
+            ConfigKey::RecordHostname => set_parsed(&mut self.record_hostname, value.parse().ok()),
+        };
+
+        if valid { SetConfigResult::Ok } else { SetConfigResult::InvalidValue }
+    }
+
+    pub fn unset(&mut self, key: ConfigKey) {
+        match key {
+            ConfigKey::DefaultBranch => self.default_branch = None,
+            ConfigKey::StorageMode => self.storage_mode = None,
+            ConfigKey::Layout => self.layout = None,
+            ConfigKey::MaxPatchChainRatio => self.max_patch_chain_ratio = None,
+            ConfigKey::MaxVersionsToPrint => self.max_versions_to_print = None,
+            ConfigKey::PreviewSize => self.preview_size = None,
+            ConfigKey::Xdelta3Path => self.xdelta3_path = None,
+            ConfigKey::ImageMagickPath => self.image_magick_path = None,
+            ConfigKey::PatchCodec => self.patch_codec = None,
+            ConfigKey::FullCodec => self.full_codec = None,
+            ConfigKey::LockTimeout => self.lock_timeout = None,
+            ConfigKey::AuthorName => self.author_name = None,
+            ConfigKey::AuthorEmail => self.author_email = None,
+            ConfigKey::RecordHostname => self.record_hostname = None,
+        }
+    }
+}
+
+pub enum SetConfigResult {
+    Ok,
+    InvalidValue,
+}
+
+#[derive(Copy, Clone)]
+pub enum ConfigKey {
+    DefaultBranch,
+    StorageMode,
+    Layout,
+    MaxPatchChainRatio,
+    MaxVersionsToPrint,
+    PreviewSize,
+    Xdelta3Path,
+    ImageMagickPath,
+    PatchCodec,
+    FullCodec,
+    LockTimeout,
+    AuthorName,
+    AuthorEmail,
+    RecordHostname,
+}
+
+impl ConfigKey {
+    pub const ALL: [ConfigKey; 14] = [
+        ConfigKey::DefaultBranch,
+        ConfigKey::StorageMode,
+        ConfigKey::Layout,
+        ConfigKey::MaxPatchChainRatio,
+        ConfigKey::MaxVersionsToPrint,
+        ConfigKey::PreviewSize,
+        ConfigKey::Xdelta3Path,
+        ConfigKey::ImageMagickPath,
+        ConfigKey::PatchCodec,
+        ConfigKey::FullCodec,
+        ConfigKey::LockTimeout,
+        ConfigKey::AuthorName,
+        ConfigKey::AuthorEmail,
+        ConfigKey::RecordHostname,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ConfigKey::DefaultBranch => "default-branch",
+            ConfigKey::StorageMode => "storage-mode",
+            ConfigKey::Layout => "layout",
+            ConfigKey::MaxPatchChainRatio => "max-patch-chain-ratio",
+            ConfigKey::MaxVersionsToPrint => "max-versions-to-print",
+            ConfigKey::PreviewSize => "preview-size",
+            ConfigKey::Xdelta3Path => "xdelta3-path",
+            ConfigKey::ImageMagickPath => "image-magick-path",
+            ConfigKey::PatchCodec => "patch-codec",
+            ConfigKey::FullCodec => "full-codec",
+            ConfigKey::LockTimeout => "lock-timeout",
+            ConfigKey::AuthorName => "author-name",
+            ConfigKey::AuthorEmail => "author-email",
+            ConfigKey::RecordHostname => "record-hostname",
+        }
+    }
+
+    pub fn expected_value(&self) -> &'static str {
+        match self {
+            ConfigKey::DefaultBranch => "a branch name",
+            ConfigKey::StorageMode => "forward or reverse",
+            ConfigKey::Layout => "sibling or central",
+            ConfigKey::MaxPatchChainRatio => "a positive number",
+            ConfigKey::MaxVersionsToPrint | ConfigKey::PreviewSize => "a positive integer",
+            ConfigKey::Xdelta3Path | ConfigKey::ImageMagickPath => "a path",
+            ConfigKey::PatchCodec => "xdelta3 or bsdiff",
+            ConfigKey::FullCodec => "raw or zstd",
+            ConfigKey::LockTimeout => "a number of seconds",
+            ConfigKey::AuthorName => "a name",
+            ConfigKey::AuthorEmail => "an email address",
+            ConfigKey::RecordHostname => "true or false",
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub enum ConfigLayer {
+    Repository,
+    Global,
+    Default,
+}
+
+pub struct ConfigEntry {
+    pub key: ConfigKey,
+    pub value: Option<String>,
+    /// The layer the value comes from, None if the setting is not set in any layer.
+    pub layer: Option<ConfigLayer>,
+}
+
+/// Resolves every setting from the highest layer that sets it.
+pub fn effective_entries(layers: &[(ConfigLayer, &Config)]) -> Vec<ConfigEntry> {
+    ConfigKey::ALL
+        .iter()
+        .map(|key| {
+            let found = layers.iter().find_map(|(layer, config)| config.get(*key).map(|value| (*layer, value)));
+
+            ConfigEntry {
+                key: *key,
+                value: found.as_ref().map(|(_, value)| value.clone()),
+                layer: found.map(|(layer, _)| layer),
+            }
+        })
+        .collect()
+}
+
+/// The biver directory in the XDG config directory (or the roaming application data directory on Windows).
+pub fn global_config_path() -> Option<PathBuf> {
+    let config_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    };
+
+    config_dir.map(|dir| dir.join("biver").join(CONFIG_FILE_NAME))
+}
+
+pub fn repository_config_path(repo_paths: &RepositoryPaths) -> PathBuf {
+    repo_paths.file_path(CONFIG_FILE_NAME)
+}
+
+/// A missing configuration file is an empty configuration.
+pub fn read_config(path: &Path) -> io::Result<Config> {
+    if !path.exists() {
+        return Ok(Config::default());
+    }
+
+    let content = fs::read(path)?;
+
+    serde_json::from_slice(&content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("invalid configuration file {}: {}", path.display(), e)))
+}
+
+pub fn write_config(path: &Path, config: &Config) -> io::Result<()> {
+    if let Some(config_dir) = path.parent() {
+        fs::create_dir_all(config_dir)?;
+    }
+
+    repository_io::write_atomically(path, serde_json::to_string_pretty(config)?.as_bytes())
+}
+
+fn set_parsed<T>(setting: &mut Option<T>, parsed: Option<T>) -> bool {
+    match parsed {
+        Some(value) => {
+            *setting = Some(value);
+            true
+        }
+        None => false,
+    }
+}
+
+fn storage_mode_name(storage_mode: StorageMode) -> &'static str {
+    match storage_mode {
+        StorageMode::Forward => "forward",
+        StorageMode::Reverse => "reverse",
+    }
+}
+
+fn parse_storage_mode(value: &str) -> Option<StorageMode> {
+    [StorageMode::Forward, StorageMode::Reverse]
+        .into_iter()
+        .find(|m| storage_mode_name(*m).eq_ignore_ascii_case(value))
+}
+
+fn layout_name(layout: RepositoryLayout) -> &'static str {
+    match layout {
+        RepositoryLayout::Sibling => "sibling",
+        RepositoryLayout::Central => "central",
+    }
+}
+
+fn parse_layout(value: &str) -> Option<RepositoryLayout> {
+    [RepositoryLayout::Sibling, RepositoryLayout::Central]
+        .into_iter()
+        .find(|l| layout_name(*l).eq_ignore_ascii_case(value))
+}
+
+fn codec_name(codec: ContentBlobCodec) -> &'static str {
+    match codec {
+        ContentBlobCodec::Raw => "raw",
+        ContentBlobCodec::Zstd => "zstd",
+        ContentBlobCodec::Xdelta3 => "xdelta3",
+        ContentBlobCodec::Bsdiff => "bsdiff",
+    }
+}
+
+fn parse_codec(value: &str, kind: ContentBlobKind) -> Option<ContentBlobCodec> {
+    [ContentBlobCodec::Raw, ContentBlobCodec::Zstd, ContentBlobCodec::Xdelta3, ContentBlobCodec::Bsdiff]
+        .into_iter()
+        .filter(|c| c.kind().is_patch() == kind.is_patch())
+        .find(|c| codec_name(*c).eq_ignore_ascii_case(value))
+}
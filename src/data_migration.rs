@@ -0,0 +1,105 @@
+//! Upgrades repository data written by older biver versions to the current format.
+//!
+//! Every change to the serialized repository data increments `CURRENT_FORMAT_VERSION` and appends a migration from the previous format version.
+//! Migrations operate on raw JSON, so they keep working however the data structures change later.
+
+use serde_json::{Map, Value, json};
+use std::io;
+
+/// Data files written before format versions were introduced have no format_version field.
+const UNVERSIONED_FORMAT_VERSION: u32 = 1;
+
+pub const CURRENT_FORMAT_VERSION: u32 = 7;
+
+/// The migration at index i upgrades format version i + 1 to i + 2.
+const MIGRATIONS: [fn(&mut Map<String, Value>); (CURRENT_FORMAT_VERSION - UNVERSIONED_FORMAT_VERSION) as usize] = [
+    record_codecs_and_storage_mode,
+    add_remotes,
+    add_tags,
+    record_authors,
+    add_properties,
+    derive_content_blob_kinds,
+];
+
+pub enum MigrationResult {
+    Ok,
+    NewerFormatVersion(u32),
+}
+
+pub fn migrate(data: &mut Value) -> io::Result<MigrationResult> {
+    let Some(data) = data.as_object_mut() else {
+        return Err(invalid_data("repository data is not a JSON object"));
+    };
+
+    let format_version = match data.get("format_version") {
+        None => UNVERSIONED_FORMAT_VERSION,
+        Some(format_version) => format_version
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .filter(|v| *v >= UNVERSIONED_FORMAT_VERSION)
+            .ok_or_else(|| invalid_data("format_version is not a valid version number"))?,
+    };
+
+    if format_version > CURRENT_FORMAT_VERSION {
+        return Ok(MigrationResult::NewerFormatVersion(format_version));
+    }
+
+    for migration in &MIGRATIONS[(format_version - UNVERSIONED_FORMAT_VERSION) as usize..] {
+        migration(data);
+    }
+
+    data.insert("format_version".to_string(), json!(CURRENT_FORMAT_VERSION));
+
+    Ok(MigrationResult::Ok)
+}
+
+/// Format version 2 records the storage mode and the codec of every content blob explicitly.
+/// Before that, a missing codec meant a plain copy for full blobs and an xdelta3 (VCDIFF) delta for patches.
+fn record_codecs_and_storage_mode(data: &mut Map<String, Value>) {
+    data.entry("storage_mode").or_insert_with(|| json!("Forward"));
+
+    let Some(versions) = data.get_mut("versions").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for version in versions.iter_mut().filter_map(Value::as_object_mut) {
+        let codec = match version.get("content_blob_kind").and_then(Value::as_str) {
+            Some("Patch") => "Xdelta3",
+            _ => "Raw",
+        };
+
+        version.entry("content_blob_codec").or_insert_with(|| json!(codec));
+    }
+}
+
+/// Format version 3 adds remotes and their remote-tracking branches.
+fn add_remotes(data: &mut Map<String, Value>) {
+    data.entry("remotes").or_insert_with(|| json!({}));
+}
+
+/// Format version 4 adds tags.
+fn add_tags(data: &mut Map<String, Value>) {
+    data.entry("tags").or_insert_with(|| json!({}));
+}
+
+/// Format version 5 records the author of new versions. Versions created before have none, so there is nothing to migrate.
+fn record_authors(_data: &mut Map<String, Value>) {}
+
+/// Format version 6 adds properties to versions. Versions without any omit the field, so there is nothing to migrate.
+fn add_properties(_data: &mut Map<String, Value>) {}
+
+/// Format version 7 derives whether a content blob is full or a patch from its codec, so the kind is no longer stored.
+/// The codec is what decoding relies on, so it wins where the two disagreed.
+fn derive_content_blob_kinds(data: &mut Map<String, Value>) {
+    let Some(versions) = data.get_mut("versions").and_then(Value::as_array_mut) else {
+        return;
+    };
+
+    for version in versions.iter_mut().filter_map(Value::as_object_mut) {
+        version.remove("content_blob_kind");
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
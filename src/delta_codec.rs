@@ -0,0 +1,175 @@
+use crate::env::Env;
+use crate::repository_data::ContentBlobCodec;
+use crate::{bsdiff, temp_file, vcdiff, xdelta3};
+use std::{fs, io};
+
+pub trait DeltaCodec {
+    /// Encodes `content`. Full codecs ignore `base`.
+    fn encode(&self, base: &[u8], content: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decodes a blob produced by `encode` with the same `base`.
+    fn decode(&self, base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+pub fn codec(env: &Env, codec: ContentBlobCodec) -> Box<dyn DeltaCodec + '_> {
+    match codec {
+        ContentBlobCodec::Raw => Box::new(RawCodec),
+        ContentBlobCodec::Zstd => Box::new(ZstdCodec),
+        ContentBlobCodec::Xdelta3 => Box::new(Xdelta3Codec { env }),
+        ContentBlobCodec::Bsdiff => Box::new(BsdiffCodec),
+    }
+}
+
+struct RawCodec;
+
+impl DeltaCodec for RawCodec {
+    fn encode(&self, _base: &[u8], content: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(content.to_vec())
+    }
+
+    fn decode(&self, _base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(blob.to_vec())
+    }
+}
+
+struct ZstdCodec;
+
+const ZSTD_LEVEL: i32 = 9;
+
+impl DeltaCodec for ZstdCodec {
+    fn encode(&self, _base: &[u8], content: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::encode_all(content, ZSTD_LEVEL)
+    }
+
+    fn decode(&self, _base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>> {
+        zstd::decode_all(blob)
+    }
+}
+
+/// VCDIFF deltas, encoded and decoded in-process. Deltas written by the xdelta3 executable with features the in-process
+/// decoder lacks, DJW or FGK secondary compression or a custom code table, are decoded by the executable.
+struct Xdelta3Codec<'e> {
+    env: &'e Env,
+}
+
+impl DeltaCodec for Xdelta3Codec<'_> {
+    fn encode(&self, base: &[u8], content: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(vcdiff::encode(base, content))
+    }
+
+    fn decode(&self, base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>> {
+        match vcdiff::decode(base, blob) {
+            Err(error) if error.kind() == io::ErrorKind::Unsupported && !xdelta3::ready(self.env) => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}, decoding it needs the xdelta3 executable, which was not found", error),
+            )),
+            Err(error) if error.kind() == io::ErrorKind::Unsupported => decode_with_xdelta3(self.env, base, blob),
+            result => result,
+        }
+    }
+}
+
+fn decode_with_xdelta3(env: &Env, base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>> {
+    let base_file_path = temp_file::path();
+    let patch_file_path = temp_file::path();
+    let output_file_path = temp_file::path();
+
+    fs::write(&base_file_path, base)?;
+    fs::write(&patch_file_path, blob)?;
+
+    let result = xdelta3::apply_patch(env, &base_file_path, &patch_file_path, &output_file_path).and_then(|_| fs::read(&output_file_path));
+
+    for path in [&base_file_path, &patch_file_path, &output_file_path] {
+        if fs::exists(path)? {
+            fs::remove_file(path)?;
+        }
+    }
+
+    result
+}
+
+struct BsdiffCodec;
+
+impl DeltaCodec for BsdiffCodec {
+    fn encode(&self, base: &[u8], content: &[u8]) -> io::Result<Vec<u8>> {
+        bsdiff::encode(base, content)
+    }
+
+    fn decode(&self, base: &[u8], blob: &[u8]) -> io::Result<Vec<u8>> {
+        bsdiff::decode(base, blob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::test_data::{edited, pseudo_random_bytes};
+
+    const ALL_CODECS: [ContentBlobCodec; 4] = [ContentBlobCodec::Raw, ContentBlobCodec::Zstd, ContentBlobCodec::Xdelta3, ContentBlobCodec::Bsdiff];
+
+    #[test]
+    fn every_codec_round_trips() {
+        let env = Env::new(Config::default());
+        let base = pseudo_random_bytes(100_000, 1);
+        let content = edited(&base);
+
+        for codec_kind in ALL_CODECS {
+            let codec = codec(&env, codec_kind);
+            let blob = codec.encode(&base, &content).unwrap();
+            assert_eq!(codec.decode(&base, &blob).unwrap(), content, "{:?}", codec_kind);
+        }
+    }
+
+    #[test]
+    fn every_codec_round_trips_empty_content() {
+        let env = Env::new(Config::default());
+        let base = pseudo_random_bytes(1000, 2);
+
+        for codec_kind in ALL_CODECS {
+            let codec = codec(&env, codec_kind);
+            let blob = codec.encode(&base, &[]).unwrap();
+            assert_eq!(codec.decode(&base, &blob).unwrap(), Vec::<u8>::new(), "{:?}", codec_kind);
+        }
+    }
+
+    #[test]
+    fn full_codecs_ignore_the_base() {
+        let env = Env::new(Config::default());
+        let content = pseudo_random_bytes(1000, 3);
+
+        for codec_kind in ALL_CODECS.into_iter().filter(|c| c.kind().is_full()) {
+            let codec = codec(&env, codec_kind);
+            let blob = codec.encode(&pseudo_random_bytes(100, 4), &content).unwrap();
+            assert_eq!(codec.decode(&[], &blob).unwrap(), content, "{:?}", codec_kind);
+        }
+    }
+
+    #[test]
+    fn compressing_codecs_reject_corrupted_blobs() {
+        let env = Env::new(Config::default());
+        let base = pseudo_random_bytes(10_000, 5);
+        let content = edited(&base);
+
+        for codec_kind in [ContentBlobCodec::Zstd, ContentBlobCodec::Xdelta3, ContentBlobCodec::Bsdiff] {
+            let codec = codec(&env, codec_kind);
+            let blob = codec.encode(&base, &content).unwrap();
+
+            assert!(codec.decode(&base, &blob[..blob.len() / 2]).is_err(), "{:?}", codec_kind);
+            assert!(codec.decode(&base, &[]).is_err(), "{:?}", codec_kind);
+        }
+    }
+
+    #[test]
+    fn xdelta3_codec_reports_deltas_that_need_a_missing_xdelta3_executable() {
+        let mut env = Env::new(Config::default());
+        env.xdelta3_path = Some("/nonexistent/xdelta3".into());
+        // A VCDIFF header announcing DJW secondary compression.
+        let blob = [0xD6, 0xC3, 0xC4, 0x00, 0x01, 0x01];
+
+        let error = codec(&env, ContentBlobCodec::Xdelta3).decode(&[], &blob).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert!(error.to_string().contains("xdelta3 executable"));
+    }
+}
@@ -1,16 +1,62 @@
+use crate::config::Config;
 use crate::image_magick::ImageMagickEnv;
+use crate::repository_data::{ContentBlobCodec, StorageMode};
+use crate::repository_paths::RepositoryLayout;
 use crate::xdelta3::XDelta3Env;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 pub struct Env {
     pub xdelta3_path: Option<PathBuf>,
     pub image_magick_path: Option<PathBuf>,
+    pub patch_codec: ContentBlobCodec,
+    pub full_codec: ContentBlobCodec,
+    pub lock_timeout: Duration,
+    pub default_branch: String,
+    pub storage_mode: StorageMode,
+    pub layout: RepositoryLayout,
+    pub max_patch_chain_ratio: f64,
+    pub max_versions_to_print: usize,
+    pub preview_size: u32,
+    pub author_name: Option<String>,
+    pub author_email: Option<String>,
+    pub record_hostname: bool,
+}
+
+impl Env {
+    /// Settings missing from the configuration take their default values.
+    pub fn new(config: Config) -> Self {
+        let config = config.or(Config::defaults());
+        // Config::defaults sets every setting that is not optional, so after merging them in these are all Some.
+        let default = "Defaults were merged in, and Config::defaults sets every setting that is not optional";
+
+        Env {
+            xdelta3_path: config.xdelta3_path,
+            image_magick_path: config.image_magick_path,
+            patch_codec: config.patch_codec.expect(default),
+            full_codec: config.full_codec.expect(default),
+            lock_timeout: Duration::from_secs(config.lock_timeout.expect(default)),
+            default_branch: config.default_branch.expect(default),
+            storage_mode: config.storage_mode.expect(default),
+            layout: config.layout.expect(default),
+            max_patch_chain_ratio: config.max_patch_chain_ratio.expect(default),
+            max_versions_to_print: config.max_versions_to_print.expect(default),
+            preview_size: config.preview_size.expect(default),
+            author_name: config.author_name,
+            author_email: config.author_email,
+            record_hostname: config.record_hostname.expect(default),
+        }
+    }
 }
 
 impl ImageMagickEnv for Env {
     fn image_magick_path(&self) -> Option<&Path> {
         self.image_magick_path.as_deref()
     }
+
+    fn preview_size(&self) -> u32 {
+        self.preview_size
+    }
 }
 
 impl XDelta3Env for Env {
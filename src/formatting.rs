@@ -1,12 +1,16 @@
-use crate::repository_data::{RepositoryData, Version};
+use crate::config::{ConfigEntry, ConfigLayer};
+use crate::operation_log;
+use crate::operation_log::OperationLogEntry;
+use crate::repository_data::{Head, RepositoryData, Version, VersionFilter};
+use crate::repository_io::DataBackup;
+use crate::repository_operations::{BranchSync, BranchSyncStatus, DataDiff, FsckReport, OrphanedFile, StorageStats, VersionProblem};
+use crate::version_id::VersionId;
+use chrono_humanize::HumanTime;
 use colored::{ColoredString, Colorize};
+use serde_json::json;
 
-const MAX_VERSIONS_TO_PRINT: usize = 20;
-
-pub fn print_repository_data(repo_data: &RepositoryData, has_uncommitted_changes: bool, all: bool) {
-    let limit = if all { None } else { Some(MAX_VERSIONS_TO_PRINT) };
-
-    let versions_to_print: Vec<_> = repo_data.iter_head_and_ancestors().collect();
+pub fn print_repository_data(repo_data: &RepositoryData, has_uncommitted_changes: bool, limit: Option<usize>, filter: &VersionFilter) {
+    let versions_to_print: Vec<_> = repo_data.iter_head_and_ancestors().filter(|v| filter.matches(v)).collect();
 
     let prepared = prepared::prepare(repo_data, &versions_to_print, has_uncommitted_changes, limit);
     let prepared = colorization::colorize_prepared(&prepared);
@@ -24,6 +28,44 @@ pub fn print_repository_data(repo_data: &RepositoryData, has_uncommitted_changes
     }
 }
 
+/// The same versions as the status, oldest first, as a single JSON document for scripts.
+pub fn print_repository_data_json(repo_data: &RepositoryData, has_uncommitted_changes: bool, filter: &VersionFilter) {
+    let mut versions: Vec<_> = repo_data.iter_head_and_ancestors().filter(|v| filter.matches(v)).collect();
+    versions.reverse();
+
+    let versions: Vec<_> = versions
+        .into_iter()
+        .map(|version| {
+            let mut branches: Vec<_> = repo_data.branches.iter().filter(|(_, id)| **id == version.id).map(|(branch, _)| branch).collect();
+            branches.sort();
+            let mut tags: Vec<_> = repo_data.tags.iter().filter(|(_, id)| **id == version.id).map(|(tag, _)| tag).collect();
+            tags.sort();
+
+            json!({
+                "id": version.id.bs58(),
+                "nickname": version.nickname,
+                "creation_time": version.creation_time,
+                "description": version.description,
+                "author": version.author,
+                "properties": version.properties,
+                "parent": version.parent.map(|p| p.bs58()),
+                "branches": branches,
+                "tags": tags,
+                "length": version.versioned_file_length,
+            })
+        })
+        .collect();
+
+    let status = json!({
+        "head": repo_data.head_version().id.bs58(),
+        "branch": repo_data.head.branch(),
+        "uncommitted_changes": has_uncommitted_changes,
+        "versions": versions,
+    });
+
+    println!("{}", serde_json::to_string_pretty(&status).expect("Status must serialize"));
+}
+
 pub fn format_versions(repo_data: &RepositoryData, versions: &[&Version]) -> Vec<String> {
     let prepared = prepared::prepare(repo_data, versions, false, None);
     prepared.versions.iter().map(|v| v.to_string()).collect()
@@ -35,25 +77,330 @@ pub fn print_dependencies(xdelta3_ready: bool, image_magick_ready: bool) {
     }
 
     println!(
-        "{:<14}{:<10}{}",
+        "{:<14}{:<10}(Optional) Used for reading patches created by older biver versions with xdelta3 secondary compression",
         "xdelta3",
-        optional_dep_status(xdelta3_ready),
-        "(Optional) Used for storing version file content as patches, which reduces repository size on disk"
+        optional_dep_status(xdelta3_ready)
     );
     println!(
-        "{:<14}{:<10}{}",
+        "{:<14}{:<10}(Optional) Used for creating version previews for image files",
         "ImageMagick",
-        optional_dep_status(image_magick_ready),
-        "(Optional) Used for creating version previews for image files"
+        optional_dep_status(image_magick_ready)
     );
 }
 
+pub fn print_config_entries(entries: &[ConfigEntry]) {
+    let max_key_length = entries.iter().map(|e| e.key.name().len()).max().unwrap_or(0);
+
+    for entry in entries {
+        let layer = match entry.layer {
+            Some(ConfigLayer::Repository) => "(repository)".bright_white(),
+            Some(ConfigLayer::Global) => "(global)".bright_white(),
+            Some(ConfigLayer::Default) => "(default)".dimmed(),
+            None => "(not set)".dimmed(),
+        };
+
+        println!("{:<width$} {} {}", entry.key.name(), entry.value.as_deref().unwrap_or("-"), layer, width = max_key_length);
+    }
+}
+
+pub fn print_orphaned_files(orphaned_files: &[OrphanedFile]) {
+    for orphaned_file in orphaned_files {
+        let kept_for_undo = if orphaned_file.kept_for_undo { " (kept for undo)".bright_black() } else { "".normal() };
+        println!("{:>10} {}{}", format_byte_count(orphaned_file.length), orphaned_file.file_name, kept_for_undo);
+    }
+
+    let total_length = orphaned_files.iter().map(|f| f.length).sum();
+
+    println!("{} orphaned files, {} reclaimable", orphaned_files.len(), format_byte_count(total_length).bright_white());
+
+    let kept_for_undo_count = orphaned_files.iter().filter(|f| f.kept_for_undo).count();
+    if kept_for_undo_count > 0 {
+        println!(
+            "{} of them are kept for undo, deleting them prevents undoing or redoing the operations that need them",
+            kept_for_undo_count
+        );
+    }
+}
+
+pub fn print_repack_summary(before: &StorageStats, after: &StorageStats, kept_for_undo_length: u64) {
+    fn print_stats(label: &str, stats: &StorageStats) {
+        println!(
+            "{:<8}{:>10} ({} full, {} patches, max chain depth {})",
+            label,
+            format_byte_count(stats.total_length),
+            stats.full_blob_count,
+            stats.patch_blob_count,
+            stats.max_chain_depth
+        );
+    }
+
+    print_stats("Before", before);
+    print_stats("After", after);
+
+    if kept_for_undo_length > 0 {
+        println!("{:<8}{:>10} (replaced blobs, kept for undo until gc)", "Kept", format_byte_count(kept_for_undo_length));
+    }
+}
+
+pub fn print_fsck_report(repo_data: &RepositoryData, report: &FsckReport) {
+    let max_nickname_length = repo_data.versions().iter().map(|v| v.nickname.len()).max().unwrap_or(0);
+
+    for version_check in report.version_checks.iter() {
+        let version = repo_data.version(version_check.version_id).expect("Checked version must exist");
+
+        let status = if version_check.problems.is_empty() { "OK".green() } else { "CORRUPTED".red() };
+
+        println!("{} {:<width$} {}", version.id.bs58().bright_black(), version.nickname, status, width = max_nickname_length);
+
+        for problem in version_check.problems.iter() {
+            println!("    {}", format_version_problem(problem).red());
+        }
+    }
+
+    if !report.graph_valid {
+        println!("{}", "Version graph is invalid".red());
+    }
+}
+
+fn format_version_problem(problem: &VersionProblem) -> String {
+    match problem {
+        VersionProblem::MissingContentBlob(file_name) => format!("Content blob {} is missing", file_name),
+        VersionProblem::MissingPreviewBlob(file_name) => format!("Preview blob {} is missing", file_name),
+        VersionProblem::BrokenPatchChain(version_id) => format!("Patch chain is broken at version {}", version_id.bs58()),
+        VersionProblem::UnreadableContent(message) => format!("Content cannot be restored: {}", message),
+        VersionProblem::NeedsXdelta3(message) => format!("Content cannot be restored without xdelta3: {}", message),
+        VersionProblem::LengthMismatch { expected, actual } => format!("Restored content is {} bytes long, expected {}", actual, expected),
+        VersionProblem::HashMismatch => "Restored content does not match its hash".to_string(),
+    }
+}
+
+pub fn print_data_backups(backups: &[DataBackup]) {
+    for backup in backups {
+        let modified_time_local = backup.modified_time.with_timezone(&chrono::Local);
+
+        print!(
+            "{} {} {} ",
+            backup.number.to_string().bright_white(),
+            modified_time_local.format("%Y-%m-%d %H:%M:%S").to_string().blue(),
+            format!("({})", HumanTime::from(modified_time_local)).bright_blue()
+        );
+
+        let Some(data) = &backup.data else {
+            println!("{}", "unreadable".red());
+            continue;
+        };
+
+        let mut branches: Vec<_> = data.branches.keys().map(String::as_str).collect();
+        branches.sort();
+
+        println!(
+            "{} versions, branches: {}, head: {}",
+            data.versions().len(),
+            branches.join(", ").bright_cyan(),
+            format_head(data).magenta()
+        );
+    }
+}
+
+pub fn print_data_diff(current: &RepositoryData, backup: &RepositoryData, diff: &DataDiff) {
+    if diff.is_empty() {
+        println!("No differences");
+        return;
+    }
+
+    if !diff.versions_only_in_current.is_empty() {
+        println!("Versions that would be lost:");
+        for line in format_versions(current, &diff.versions_only_in_current) {
+            println!("{} {}", "-".red(), line);
+        }
+    }
+
+    if !diff.versions_only_in_backup.is_empty() {
+        println!("Versions that would be restored:");
+        for line in format_versions(backup, &diff.versions_only_in_backup) {
+            println!("{} {}", "+".green(), line);
+        }
+    }
+
+    if !diff.changed_versions.is_empty() {
+        println!("Versions that would change:");
+        for change in diff.changed_versions.iter() {
+            let mut changes = Vec::new();
+            if change.current.description != change.backup.description {
+                changes.push(format!("description \"{}\" -> \"{}\"", change.current.description, change.backup.description));
+            }
+            if change.current.content_blob_file_name != change.backup.content_blob_file_name {
+                changes.push(format!(
+                    "content blob {} -> {}",
+                    change.current.content_blob_file_name, change.backup.content_blob_file_name
+                ));
+            }
+            println!(
+                "{} {} {} {}",
+                "~".yellow(),
+                change.current.id.bs58().bright_black(),
+                change.current.nickname,
+                changes.join(", ")
+            );
+        }
+    }
+
+    if !diff.changed_branches.is_empty() {
+        println!("Branches that would change:");
+        for change in diff.changed_branches.iter() {
+            println!(
+                "{} {} {} -> {}",
+                "~".yellow(),
+                change.name.bright_cyan(),
+                format_optional_version(current, change.current),
+                format_optional_version(backup, change.backup)
+            );
+        }
+    }
+
+    if diff.head_changed {
+        println!("Head would change: {} -> {}", format_head(current).magenta(), format_head(backup).magenta());
+    }
+}
+
+fn format_head(repo_data: &RepositoryData) -> String {
+    match &repo_data.head {
+        Head::Branch(branch) => branch.clone(),
+        Head::Version(version_id) => format_optional_version(repo_data, Some(*version_id)),
+    }
+}
+
+fn format_optional_version(repo_data: &RepositoryData, version_id: Option<VersionId>) -> String {
+    match version_id {
+        None => "(none)".to_string(),
+        Some(version_id) => match repo_data.version(version_id) {
+            Some(version) => format!("{} {}", version.id.bs58(), version.nickname),
+            None => version_id.bs58(),
+        },
+    }
+}
+
+pub fn format_byte_count(byte_count: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+    let mut value = byte_count as f64;
+    let mut unit = 0;
+
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", byte_count, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
 pub fn print_branch_list(repo_data: &RepositoryData) {
     for branch in repo_data.branches.keys() {
         println!("{}", branch)
     }
 }
 
+pub fn print_remotes(repo_data: &RepositoryData) {
+    let mut remotes: Vec<_> = repo_data.remotes.iter().collect();
+    remotes.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (name, remote) in remotes {
+        println!("{} {}", name.bright_white(), remote.path.display());
+
+        let mut branches: Vec<_> = remote.branches.iter().collect();
+        branches.sort_by(|a, b| a.0.cmp(b.0));
+
+        for (branch, version_id) in branches {
+            println!("    {} {}", format!("{}/{}", name, branch).red(), format_optional_version(repo_data, Some(*version_id)));
+        }
+    }
+}
+
+/// Oldest first. Operations that were undone and can be redone are marked.
+pub fn print_operation_log(repo_data: &RepositoryData, entries: &[OperationLogEntry]) {
+    let (_, redoable) = operation_log::undo_redo_stacks(entries);
+    let max_operation_length = entries.iter().map(|e| format_operation(e).len()).max().unwrap_or(0);
+    let max_number_length = entries.iter().map(|e| e.number.to_string().len() + 1).max().unwrap_or(0);
+
+    let format_head = |head: &Head| match head {
+        Head::Branch(branch) => branch.clone(),
+        Head::Version(version_id) => format_optional_version(repo_data, Some(*version_id)),
+    };
+
+    for entry in entries {
+        let head_change = if entry.before.head == entry.after.head {
+            String::new()
+        } else {
+            format!(", head {} -> {}", format_head(&entry.before.head), format_head(&entry.after.head))
+        };
+        let undone = if redoable.iter().any(|e| e.number == entry.number) {
+            " (undone)".yellow()
+        } else {
+            "".normal()
+        };
+
+        println!(
+            "{:<number_width$} {} {:<width$} {}{}",
+            format!("#{}", entry.number).bright_black(),
+            entry.time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string().blue(),
+            format_operation(entry),
+            format!("{} versions affected{}", entry.affected_version_ids().count(), head_change).dimmed(),
+            undone,
+            number_width = max_number_length,
+            width = max_operation_length
+        );
+    }
+}
+
+fn format_operation(entry: &OperationLogEntry) -> String {
+    match entry.applied_entry {
+        Some(applied_entry) => format!("{} #{}", entry.operation, applied_entry),
+        None => entry.operation.clone(),
+    }
+}
+
+/// The destination is the side whose branches were updated, remote for push and local for pull.
+pub fn print_branch_syncs(branch_syncs: &[BranchSync], destination: &str) {
+    let max_branch_length = branch_syncs.iter().map(|b| b.branch.len()).max().unwrap_or(0);
+
+    for branch_sync in branch_syncs {
+        let status = match branch_sync.status {
+            BranchSyncStatus::Created => "created".green(),
+            BranchSyncStatus::FastForwarded => "fast-forwarded".green(),
+            BranchSyncStatus::UpToDate => "up to date".dimmed(),
+            BranchSyncStatus::DestinationAhead => format!("{} branch is ahead", destination).dimmed(),
+            BranchSyncStatus::Diverged => "diverged, left unchanged".yellow(),
+            BranchSyncStatus::Unrelated => "no common history, left unchanged".yellow(),
+            BranchSyncStatus::BlockedByUncommittedChanges => format!("checked out with uncommitted changes in {} versioned file, left unchanged", destination).yellow(),
+        };
+
+        println!("{:<width$} {}", branch_sync.branch.bright_cyan(), status, width = max_branch_length);
+    }
+}
+
+pub fn print_tag_list(repo_data: &RepositoryData) {
+    let mut tags: Vec<_> = repo_data.tags.iter().collect();
+    tags.sort_by(|a, b| a.0.cmp(b.0));
+
+    let max_tag_length = tags.iter().map(|(tag, _)| tag.len()).max().unwrap_or(0);
+
+    for (tag, version_id) in tags {
+        println!("{:<width$} {}", tag, format_optional_version(repo_data, Some(*version_id)), width = max_tag_length);
+    }
+}
+
+pub fn print_properties(version: &Version) {
+    let max_key_length = version.properties.keys().map(|key| key.len()).max().unwrap_or(0);
+
+    for (key, value) in &version.properties {
+        println!("{:<width$} {}", key.cyan(), value, width = max_key_length);
+    }
+}
+
 mod colorization {
     use crate::formatting::prepared::{Prepared, PreparedOffScreen, PreparedUncommitedChanges, PreparedVersion};
     use colored::{ColoredString, Colorize};
@@ -81,8 +428,11 @@ mod colorization {
             creation_time_humanized: prepared_version.creation_time_humanized.bright_blue(),
             id: prepared_version.id.bright_black(),
             nickname: prepared_version.nickname.white(),
+            author: prepared_version.author.clone().map(|a| a.cyan()),
             head_badge: prepared_version.head_badge.clone().map(|h| h.magenta()),
             other_branches_here: prepared_version.other_branches_here.clone().map(|b| b.bright_cyan()),
+            remote_branches_here: prepared_version.remote_branches_here.clone().map(|b| b.red()),
+            tags_here: prepared_version.tags_here.clone().map(|t| t.yellow()),
             forking_branches: prepared_version.forking_branches.clone().map(|f| f.bright_cyan()),
             description: prepared_version.description.clone().map(|d| d.green()),
         }
@@ -135,8 +485,11 @@ mod prepared {
         pub creation_time_humanized: T,
         pub id: T,
         pub nickname: T,
+        pub author: Option<T>,
         pub head_badge: Option<T>,
         pub other_branches_here: Option<T>,
+        pub remote_branches_here: Option<T>,
+        pub tags_here: Option<T>,
         pub forking_branches: Option<T>,
         pub description: Option<T>,
     }
@@ -158,6 +511,11 @@ mod prepared {
             fmt_clearance(f)?;
             self.nickname.fmt(f)?;
 
+            if let Some(author) = &self.author {
+                fmt_clearance(f)?;
+                author.fmt(f)?;
+            }
+
             if let Some(head_badge) = &self.head_badge {
                 fmt_clearance(f)?;
                 head_badge.fmt(f)?;
@@ -168,6 +526,16 @@ mod prepared {
                 other_branches_here.fmt(f)?;
             }
 
+            if let Some(remote_branches_here) = &self.remote_branches_here {
+                fmt_clearance(f)?;
+                remote_branches_here.fmt(f)?;
+            }
+
+            if let Some(tags_here) = &self.tags_here {
+                fmt_clearance(f)?;
+                tags_here.fmt(f)?;
+            }
+
             if let Some(forking_branches) = &self.forking_branches {
                 fmt_clearance(f)?;
                 forking_branches.fmt(f)?;
@@ -195,19 +563,23 @@ mod prepared {
     pub fn prepare(repo_data: &RepositoryData, versions_to_prepare: &[&Version], has_uncommitted_changes: bool, limit_from_end: Option<usize>) -> Prepared<String> {
         let mut prepared_versions = Vec::new();
 
-        let head_version_ids: Vec<VersionId> = repo_data.iter_head_and_ancestors().map(|v| v.id).collect();
+        let head_version_ids: HashSet<VersionId> = repo_data.iter_head_and_ancestors().map(|v| v.id).collect();
+
+        let remote_branches: Vec<(String, VersionId)> = repo_data.iter_remote_branches().collect();
 
         let branches_forking_at_version_id: HashMap<VersionId, Vec<String>> = repo_data
             .branches
             .iter()
+            .map(|(branch, branch_leaf_id)| (branch.clone(), *branch_leaf_id))
+            .chain(remote_branches.iter().cloned())
             .map(|(branch, branch_leaf_id)| {
                 let join_version_id = repo_data
-                    .iter_version_and_ancestors(*branch_leaf_id)
+                    .iter_version_and_ancestors(branch_leaf_id)
                     .map(|v| v.id)
                     .find(|id| head_version_ids.contains(id))
                     .unwrap();
 
-                (join_version_id, branch.clone())
+                (join_version_id, branch)
             })
             .fold(HashMap::new(), |mut acc, (version_id, branch)| {
                 acc.entry(version_id).or_insert_with(Vec::new).push(branch);
@@ -219,11 +591,22 @@ mod prepared {
             acc
         });
 
+        let tags_by_version: HashMap<VersionId, Vec<&str>> = repo_data.tags.iter().fold(HashMap::new(), |mut acc, (tag, version_id)| {
+            acc.entry(*version_id).or_insert_with(Vec::new).push(tag);
+            acc
+        });
+
+        let remote_branches_by_version: HashMap<VersionId, Vec<&str>> = remote_branches.iter().fold(HashMap::new(), |mut acc, (branch, version_id)| {
+            acc.entry(*version_id).or_insert_with(Vec::new).push(branch);
+            acc
+        });
+
         let limit_from_end = limit_from_end.unwrap_or(versions_to_prepare.len());
         let mut version_count = 0;
         let mut off_screen_branches = HashSet::new();
 
         let mut max_nickname_length = 0;
+        let mut max_author_length = 0;
         let mut max_creation_time_humanized_length = 0;
 
         let head_branch = repo_data.head.branch();
@@ -251,7 +634,13 @@ mod prepared {
                 forking_branches.retain(|b| !branches_on_version.contains(&b.as_str()));
             }
 
-            let forking_branches = if forking_branches.len() > 0 {
+            let remote_branches_on_version = remote_branches_by_version.get(&version.id);
+
+            if let Some(remote_branches_on_version) = remote_branches_on_version {
+                forking_branches.retain(|b| !remote_branches_on_version.contains(&b.as_str()));
+            }
+
+            let forking_branches = if !forking_branches.is_empty() {
                 Some(format!("->[{}]", forking_branches.join(", ")))
             } else {
                 None
@@ -283,7 +672,7 @@ mod prepared {
                         }
                     };
 
-                    if branches_on_version.len() == 0 {
+                    if branches_on_version.is_empty() {
                         None
                     } else {
                         Some(format!("[{}]", branches_on_version.join(", ")))
@@ -291,7 +680,25 @@ mod prepared {
                 }
             };
 
+            let remote_branches_here = remote_branches_on_version.map(|remote_branches_on_version| {
+                let mut remote_branches_on_version = remote_branches_on_version.clone();
+                remote_branches_on_version.sort();
+                format!("[{}]", remote_branches_on_version.join(", "))
+            });
+
+            let tags_here = tags_by_version.get(&version.id).map(|tags_on_version| {
+                let mut tags_on_version = tags_on_version.clone();
+                tags_on_version.sort();
+                format!("[tag: {}]", tags_on_version.join(", "))
+            });
+
+            let author = version.author.as_ref().map(|a| match &a.hostname {
+                Some(hostname) => format!("{} ({})", a.name, hostname),
+                None => a.name.clone(),
+            });
+
             max_nickname_length = max_nickname_length.max(version.nickname.len());
+            max_author_length = max_author_length.max(author.as_ref().map_or(0, |a| a.chars().count()));
             max_creation_time_humanized_length = max_creation_time_humanized_length.max(creation_time_humanized.len());
 
             prepared_versions.push(PreparedVersion {
@@ -299,25 +706,27 @@ mod prepared {
                 creation_time_humanized: creation_time_humanized.to_string(),
                 id: version.id.bs58(),
                 nickname: version.nickname.clone(),
+                author,
                 head_badge,
                 other_branches_here,
+                remote_branches_here,
+                tags_here,
                 forking_branches,
-                description: if version.description.len() > 0 { Some(version.description.to_string()) } else { None },
+                description: if !version.description.is_empty() { Some(version.description.to_string()) } else { None },
             });
         }
 
         for version in &mut prepared_versions {
             version.nickname = format!("{:>max_nickname_length$}", version.nickname);
             version.creation_time_humanized = format!("{:<max_creation_time_humanized_length$}", version.creation_time_humanized);
+            if max_author_length > 0 {
+                version.author = Some(format!("{:<max_author_length$}", version.author.as_deref().unwrap_or_default()));
+            }
         }
 
         let total_version_count = versions_to_prepare.len();
 
-        let off_screen_version_count = if total_version_count > limit_from_end {
-            total_version_count - limit_from_end
-        } else {
-            0
-        };
+        let off_screen_version_count = total_version_count.saturating_sub(limit_from_end);
 
         let version_id_position = max_creation_time_humanized_length + 21;
 
@@ -326,10 +735,11 @@ mod prepared {
         } else {
             let more_versions_text = format!("...{} more versions", off_screen_version_count);
 
-            let more_versions_slot_length = 23 + max_nickname_length;
+            let author_slot_length = if max_author_length > 0 { max_author_length + 1 } else { 0 };
+            let more_versions_slot_length = 23 + max_nickname_length + author_slot_length;
             let forking_branches_offset = more_versions_slot_length - more_versions_text.len().min(more_versions_slot_length) + 1;
 
-            let forking_branches = if off_screen_branches.len() == 0 {
+            let forking_branches = if off_screen_branches.is_empty() {
                 None
             } else {
                 Some(format!("->[{}]", off_screen_branches.into_iter().collect::<Vec<_>>().join(", ")))
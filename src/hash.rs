@@ -18,3 +18,7 @@ pub fn xxh3_128(file: &File) -> io::Result<u128> {
 
     Ok(hasher.digest128())
 }
+
+pub fn xxh3_128_of_bytes(bytes: &[u8]) -> u128 {
+    xxhash_rust::xxh3::xxh3_128(bytes)
+}
@@ -0,0 +1,18 @@
+use std::process::{Command, Stdio};
+use std::{env, fs};
+
+/// Name of this computer, used to attribute versions and to tell whether a lock owner runs on this host. None if it cannot be determined.
+pub fn hostname() -> Option<String> {
+    if cfg!(windows) {
+        return env::var("COMPUTERNAME").ok().filter(|h| !h.is_empty());
+    }
+
+    let from_file = fs::read_to_string("/etc/hostname").ok().map(|h| h.trim().to_string()).filter(|h| !h.is_empty());
+
+    from_file.or_else(|| {
+        let output = Command::new("hostname").stderr(Stdio::null()).output().ok()?;
+        let hostname = String::from_utf8_lossy(&output.stdout).trim().to_string();
+
+        if output.status.success() && !hostname.is_empty() { Some(hostname) } else { None }
+    })
+}
@@ -5,6 +5,7 @@ use std::process::{Command, ExitStatus, Stdio};
 
 pub trait ImageMagickEnv {
     fn image_magick_path(&self) -> Option<&Path>;
+    fn preview_size(&self) -> u32;
 }
 
 pub fn ready(env: &impl ImageMagickEnv) -> bool {
@@ -23,7 +24,7 @@ pub fn create_preview(env: &impl ImageMagickEnv, input: &Path, preview: &Path) -
         .arg(input)
         .arg("-flatten")
         .arg("-thumbnail")
-        .arg("1024x1024>")
+        .arg(format!("{0}x{0}>", env.preview_size()))
         .arg(preview_with_prefix)
         .status();
 
@@ -31,13 +32,7 @@ pub fn create_preview(env: &impl ImageMagickEnv, input: &Path, preview: &Path) -
 }
 
 fn map_image_magick_status(status_result: io::Result<ExitStatus>) -> io::Result<()> {
-    status_result.and_then(|status| {
-        if status.success() {
-            Ok(())
-        } else {
-            Err(io::Error::new(io::ErrorKind::Other, "ImageMagick failed."))
-        }
-    })
+    status_result.and_then(|status| if status.success() { Ok(()) } else { Err(io::Error::other("ImageMagick failed.")) })
 }
 
 fn image_magick_command(env: &impl ImageMagickEnv) -> Command {
@@ -1,32 +1,53 @@
 use crate::biver_result::{BiverError, BiverErrorSeverity, BiverResult, error, warning};
-use crate::command_line_arguments::{Command, CommandLineArguments, CreateCommand, DeleteCommand, ListCommand, RenameCommand};
+use crate::command_line_arguments::{
+    BundleCommand, Command, CommandLineArguments, ConfigCommand, ConfigKey, CreateCommand, DeleteCommand, FullCodec, ListCommand, PatchCodec, PropCommand, RecoverCommand,
+    RemoteCommand, RenameCommand, RepositoryLayout, StorageMode,
+};
+use crate::config::{Config, ConfigLayer, SetConfigResult};
 use crate::env::Env;
-use crate::repository_data::RepositoryData;
+use crate::repository_data::{ContentBlobCodec, PropertyFilter, RepositoryData, VersionFilter};
 use crate::repository_io::RepositoryDataResult;
+use crate::repository_lock::{LockResult, RepositoryLock};
 use crate::repository_operations::{
-    AmendResult, CheckOutResult, CommitResult, CreateBranchResult, DeleteBranchResult, InitResult, PreviewResult, RenameBranchResult, ResetResult, RestoreResult, RewordResult,
-    VersionResult,
+    AddRemoteResult, AmendResult, BranchSync, BranchSyncStatus, BundleSelection, CheckOutResult, CherryPickResult, CommitResult, CreateBranchResult, CreateBundleResult,
+    CreateTagResult, DeleteBranchResult, DeleteTagResult, DropResult, InitResult, MoveResult, PreviewResult, PullResult, PushResult, RebaseResult, RedoResult, RemoveRemoteResult,
+    RenameBranchResult, RepackPolicy, RepackResult, ResetResult, RestoreBackupResult, RestoreResult, RevertResult, RewordResult, SetPropertyResult, SquashResult, UndoResult,
+    UnsetPropertyResult, VerifiedBundle, VerifyBundleResult, VersionResult,
 };
 use crate::repository_paths::RepositoryPaths;
 use clap::Parser;
 use colored::Colorize;
-use std::io;
+use std::path::PathBuf;
 use std::process::ExitCode;
+use std::{fs, io};
 
+mod author;
 mod biver_result;
+mod block_matching;
+mod bsdiff;
+mod bundle;
 mod command_line_arguments;
+mod config;
+mod data_migration;
+mod delta_codec;
 mod env;
 mod extensions;
 mod formatting;
 mod hash;
+mod hostname;
 mod image_magick;
 mod known_file_types;
 mod nickname;
+mod operation_log;
 mod repository_data;
 mod repository_io;
+mod repository_lock;
 mod repository_operations;
 mod repository_paths;
 mod temp_file;
+#[cfg(test)]
+mod test_data;
+mod vcdiff;
 mod version_id;
 mod viewer;
 mod xdelta3;
@@ -34,12 +55,7 @@ mod xdelta3;
 fn main() -> ExitCode {
     let arguments = CommandLineArguments::parse();
 
-    let env = Env {
-        xdelta3_path: arguments.xdelta3_path,
-        image_magick_path: arguments.image_magick_path,
-    };
-
-    match run_command(&env, arguments.command) {
+    match load_env(&arguments).and_then(|env| run_command(&env, arguments.command)) {
         Ok(()) => ExitCode::SUCCESS,
 
         Err(BiverError {
@@ -60,17 +76,73 @@ fn main() -> ExitCode {
     }
 }
 
+/// Command line options and environment variables take precedence over the repository configuration, which takes precedence over the global configuration.
+fn load_env(arguments: &CommandLineArguments) -> BiverResult<Env> {
+    let command_line_config = Config {
+        xdelta3_path: arguments.xdelta3_path.clone(),
+        image_magick_path: arguments.image_magick_path.clone(),
+        patch_codec: arguments.patch_codec.map(|codec| match codec {
+            PatchCodec::Xdelta3 => ContentBlobCodec::Xdelta3,
+            PatchCodec::Bsdiff => ContentBlobCodec::Bsdiff,
+        }),
+        full_codec: arguments.full_codec.map(|codec| match codec {
+            FullCodec::Raw => ContentBlobCodec::Raw,
+            FullCodec::Zstd => ContentBlobCodec::Zstd,
+        }),
+        lock_timeout: arguments.lock_timeout,
+        author_name: arguments.author_name.clone(),
+        author_email: arguments.author_email.clone(),
+        ..Config::default()
+    };
+
+    let repository_config = match arguments.command.versioned_file_path() {
+        Some(versioned_file_path) => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path.to_path_buf())?;
+            config::read_config(&config::repository_config_path(&repo_paths))?
+        }
+        None => Config::default(),
+    };
+
+    let global_config = match config::global_config_path() {
+        Some(global_config_path) => config::read_config(&global_config_path)?,
+        None => Config::default(),
+    };
+
+    Ok(Env::new(command_line_config.or(repository_config).or(global_config)))
+}
+
 fn run_command(env: &Env, command: Command) -> BiverResult<()> {
     match command {
-        Command::Status { versioned_file_path, all } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+        Command::Status {
+            versioned_file_path,
+            all,
+            author,
+            properties,
+            json,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?;
 
             match repo_data {
                 RepositoryDataResult::NotInitialized => println!("Not initialized"),
+                RepositoryDataResult::NewerFormatVersion(format_version) => return newer_format_version_error(format_version),
                 RepositoryDataResult::Initialized(repository_data) => {
+                    if !fs::exists(&repo_paths.versioned_file)? {
+                        return offer_reattach(env, &repo_paths, &repository_data);
+                    }
+
                     let has_uncommitted_changes = repository_operations::has_uncommitted_changes(&repo_paths, &repository_data)?;
-                    formatting::print_repository_data(&repository_data, has_uncommitted_changes, all);
+                    let filter = VersionFilter {
+                        author,
+                        properties: properties.iter().map(|p| PropertyFilter::parse(p)).collect(),
+                    };
+
+                    if json {
+                        formatting::print_repository_data_json(&repository_data, has_uncommitted_changes, &filter);
+                    } else {
+                        let limit = if all { None } else { Some(env.max_versions_to_print) };
+                        formatting::print_repository_data(&repository_data, has_uncommitted_changes, limit, &filter);
+                    }
                 }
             }
 
@@ -78,7 +150,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
         }
 
         Command::Preview { versioned_file_path, target } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let version = match repository_operations::version(&repo_data, &target) {
@@ -101,7 +173,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             target1,
             target2,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let version_and_preview = |target: Option<&str>| {
@@ -113,7 +185,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                     },
                 };
 
-                match repository_operations::preview(&repo_paths, &version) {
+                match repository_operations::preview(&repo_paths, version) {
                     PreviewResult::NoPreviewAvailable => error(format!("No preview available for {}", version.id.bs58())),
                     PreviewResult::Ok(preview) => Ok((version, preview)),
                 }
@@ -122,7 +194,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             let (version1, preview_file_path1) = version_and_preview(Some(&target1))?;
             let (version2, preview_file_path2) = version_and_preview(target2.as_deref())?;
 
-            let formatted_versions = formatting::format_versions(&repo_data, &vec![version1, version2]);
+            let formatted_versions = formatting::format_versions(&repo_data, &[version1, version2]);
             let description1 = &formatted_versions[0];
             let description2 = &formatted_versions[1];
 
@@ -134,11 +206,16 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
         Command::Init {
             versioned_file_path,
             initial_branch_name: branch_name,
+            storage_mode,
+            layout,
             initial_version_description: description,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = create_repository_dir(env, versioned_file_path, layout)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+
+            let storage_mode = storage_mode.map(repository_storage_mode).unwrap_or(env.storage_mode);
 
-            let result = repository_operations::init(env, &repo_paths, branch_name.as_deref(), description.as_deref())?;
+            let result = repository_operations::init(env, &repo_paths, branch_name.as_deref(), storage_mode, description.as_deref())?;
 
             match result {
                 InitResult::Ok => success_ok(),
@@ -148,7 +225,8 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
         }
 
         Command::Commit { versioned_file_path, description } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let result = repository_operations::commit_version(env, &repo_paths, &mut repo_data, description.as_deref())?;
@@ -160,12 +238,45 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             }
         }
 
+        Command::CherryPick { versioned_file_path, target } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::cherry_pick(env, &repo_paths, &mut repo_data, &target)?;
+
+            match result {
+                CherryPickResult::Ok => success_ok(),
+                CherryPickResult::UncommittedChanges => error("There are uncommitted changes. Commit or discard them first."),
+                CherryPickResult::HeadMustBeOnBranch => error("Head must be on a branch"),
+                CherryPickResult::InvalidTarget => error("Invalid target"),
+                CherryPickResult::SameContentAsHead => warning("Target has the same content as head, nothing to cherry-pick"),
+            }
+        }
+
+        Command::Revert { versioned_file_path, target } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::revert(env, &repo_paths, &mut repo_data, &target)?;
+
+            match result {
+                RevertResult::Ok { versioned_file_updated: true } => success_ok(),
+                RevertResult::Ok { versioned_file_updated: false } => warning("Reverted, but the versioned file was left unchanged because it has uncommitted changes"),
+                RevertResult::HeadMustBeOnBranch => error("Head must be on a branch"),
+                RevertResult::InvalidTarget => error("Invalid target"),
+                RevertResult::SameContentAsHead => warning("Target has the same content as head, nothing to revert"),
+            }
+        }
+
         Command::Amend {
             versioned_file_path,
             confirmed,
             description,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             if !confirmed {
@@ -184,6 +295,8 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                 AmendResult::HeadMustBeBranch => error("Head must be on a branch"),
                 AmendResult::CannotAmendParent => error("Cannot amend head version because it has children"),
                 AmendResult::HeadEqualsParent => error("Amend would result in head version file content being identical to its parent's file content. Use hard reset instead."),
+                AmendResult::HeadIsOnOtherBranches => error("Cannot amend head version because other branches or remote-tracking branches point to it"),
+                AmendResult::HeadIsTagged => error("Cannot amend head version because it is tagged"),
             }
         }
 
@@ -192,7 +305,8 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             target,
             description,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let result = repository_operations::reword(&repo_paths, &mut repo_data, &target, &description)?;
@@ -203,8 +317,154 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             }
         }
 
+        Command::Rebase {
+            versioned_file_path,
+            branch,
+            target,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::rebase(env, &repo_paths, &mut repo_data, branch.as_deref(), &target)?;
+
+            match result {
+                RebaseResult::Ok { moved_version_count } => {
+                    println!("Moved {} versions", moved_version_count);
+                    success_ok()
+                }
+                RebaseResult::HeadMustBeOnBranch => error("Head must be on a branch, or a branch must be specified"),
+                RebaseResult::InvalidBranch => error("Branch does not exist"),
+                RebaseResult::InvalidTarget => error("Invalid target"),
+                RebaseResult::NoVersionsUniqueToBranch => error("All versions of the branch are shared with other branches, remote-tracking branches or tags"),
+                RebaseResult::TargetIsOnBranch => error("Cannot rebase a branch onto one of its own versions"),
+                RebaseResult::AlreadyOnTarget => warning("Branch is already based on the target"),
+            }
+        }
+
+        Command::Drop {
+            versioned_file_path,
+            confirmed,
+            target,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            if !confirmed {
+                println!("Are you sure you want to drop the version? (y/N)");
+                let confirmed = read_yes_no_input()?.unwrap_or(false);
+                if !confirmed {
+                    return success();
+                }
+            }
+
+            let result = repository_operations::drop_version(env, &repo_paths, &mut repo_data, &target)?;
+
+            match result {
+                DropResult::Ok => success_ok(),
+                DropResult::InvalidTarget => error("Invalid target"),
+                DropResult::CannotDropRoot => error("Cannot drop the root version"),
+                DropResult::CannotDropHead => error("Cannot drop the head version. Use reset instead."),
+                DropResult::VersionIsTagged => error("Cannot drop a tagged version"),
+                DropResult::VersionIsPushed => error("Cannot drop a version that a remote-tracking branch leads to"),
+            }
+        }
+
+        Command::Squash {
+            versioned_file_path,
+            confirmed,
+            from,
+            to,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            if !confirmed {
+                println!("Are you sure you want to squash the versions? (y/N)");
+                let confirmed = read_yes_no_input()?.unwrap_or(false);
+                if !confirmed {
+                    return success();
+                }
+            }
+
+            let result = repository_operations::squash(env, &repo_paths, &mut repo_data, &from, &to)?;
+
+            match result {
+                SquashResult::Ok { squashed_version_count } => {
+                    println!("Squashed {} versions", squashed_version_count);
+                    success_ok()
+                }
+                SquashResult::InvalidTarget => error("Invalid target"),
+                SquashResult::NothingToSquash => warning("The oldest and newest versions are the same, nothing to squash"),
+                SquashResult::NotAnAncestor => error("The oldest version must be an ancestor of the newest version"),
+                SquashResult::CannotRemoveHead => error("Head is in the squashed range. Check out the newest version of the range or one of its descendants first."),
+                SquashResult::VersionHasOtherChildren => error("Versions in the squashed range have children outside of it"),
+                SquashResult::VersionIsTagged => error("Cannot squash tagged versions"),
+                SquashResult::VersionIsPushed => error("Cannot squash versions that a remote-tracking branch leads to"),
+            }
+        }
+
+        Command::Undo { versioned_file_path } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::undo(env, &repo_paths, &mut repo_data)?;
+
+            match result {
+                UndoResult::Ok {
+                    operation,
+                    versioned_file_left_unchanged,
+                } => {
+                    println!("Undid {}", operation);
+                    applied_log_entry_outcome(versioned_file_left_unchanged)
+                }
+                UndoResult::NothingToUndo => warning("Nothing to undo"),
+                UndoResult::RepositoryChanged => error("The repository was changed outside of the operation log since the operation"),
+                UndoResult::BlobsDeleted => error("Blobs needed to undo the operation were deleted by gc"),
+            }
+        }
+
+        Command::Redo { versioned_file_path } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::redo(env, &repo_paths, &mut repo_data)?;
+
+            match result {
+                RedoResult::Ok {
+                    operation,
+                    versioned_file_left_unchanged,
+                } => {
+                    println!("Redid {}", operation);
+                    applied_log_entry_outcome(versioned_file_left_unchanged)
+                }
+                RedoResult::NothingToRedo => warning("Nothing to redo"),
+                RedoResult::RepositoryChanged => error("The repository was changed outside of the operation log since the operation"),
+                RedoResult::BlobsDeleted => error("Blobs needed to redo the operation were deleted by gc"),
+            }
+        }
+
+        Command::Oplog { versioned_file_path } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let entries = operation_log::read_entries(&repo_paths)?;
+
+            if entries.is_empty() {
+                return warning("No operations");
+            }
+
+            formatting::print_operation_log(&repo_data, &entries);
+
+            success()
+        }
+
         Command::Discard { versioned_file_path, confirmed } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             if !repository_operations::has_uncommitted_changes(&repo_paths, &repo_data)? {
@@ -230,7 +490,8 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             confirmed,
             target,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             if !confirmed {
@@ -241,7 +502,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                 }
             }
 
-            let result = repository_operations::reset(&repo_paths, &mut repo_data, target.as_str())?;
+            let result = repository_operations::reset(env, &repo_paths, &mut repo_data, target.as_str())?;
 
             match result {
                 ResetResult::Ok => {
@@ -254,11 +515,14 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                 ResetResult::HeadMustBeBranch => error("Head must be on a branch"),
                 ResetResult::InvalidTarget => error("Invalid target"),
                 ResetResult::CannotLeaveOrphans => error("Reset would leave orphaned versions. Make sure none of the erased versions have children outside of the reset range."),
+                ResetResult::CannotEraseOtherBranches => error("Reset would erase versions that other branches or remote-tracking branches point to"),
+                ResetResult::CannotEraseTaggedVersions => error("Reset would erase tagged versions"),
             }
         }
 
         Command::Checkout { versioned_file_path, target } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let result = repository_operations::check_out(env, &repo_paths, &mut repo_data, &target)?;
@@ -274,7 +538,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             output,
             target,
         } => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let result = repository_operations::restore(env, &repo_paths, &repo_data, &target, output.as_deref())?;
@@ -286,12 +550,121 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             }
         }
 
+        Command::Mv {
+            versioned_file_path,
+            after,
+            destination,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let mut lock = lock_repository(env, &repo_paths)?;
+
+            let result = repository_operations::move_versioned_file(&repo_paths, &destination, after)?;
+
+            match result {
+                MoveResult::Ok(new_repo_paths) => {
+                    lock.relocate(&new_repo_paths);
+                    success_ok()
+                }
+                MoveResult::VersionedFileMissing => error("Versioned file is missing"),
+                MoveResult::DestinationExists => error("Destination already exists"),
+                MoveResult::DestinationMissing => error("Destination does not exist"),
+                MoveResult::DestinationHasRepository => error("Destination already has a repository"),
+            }
+        }
+
+        Command::Gc {
+            versioned_file_path,
+            dry_run,
+            confirmed,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let orphaned_files = repository_operations::orphaned_files(&repo_paths, &repo_data)?;
+
+            if orphaned_files.is_empty() {
+                return warning("No orphaned files");
+            }
+
+            formatting::print_orphaned_files(&orphaned_files);
+
+            if dry_run {
+                return success();
+            }
+
+            if !confirmed {
+                println!("Are you sure you want to delete these files? (y/N)");
+                let confirmed = read_yes_no_input()?.unwrap_or(false);
+                if !confirmed {
+                    return success();
+                }
+            }
+
+            repository_operations::delete_orphaned_files(&repo_paths, &orphaned_files)?;
+
+            success_ok()
+        }
+
+        Command::Fsck { versioned_file_path } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let report = repository_operations::fsck(env, &repo_paths, &repo_data)?;
+
+            formatting::print_fsck_report(&repo_data, &report);
+
+            let corrupted_version_count = report.corrupted_version_count();
+
+            if corrupted_version_count > 0 {
+                return error(format!("{} of {} versions are corrupted", corrupted_version_count, report.version_checks.len()));
+            }
+
+            if !report.graph_valid {
+                return error("Repository data is invalid");
+            }
+
+            success_ok()
+        }
+
+        Command::Repack {
+            versioned_file_path,
+            max_chain_depth,
+            max_patch_ratio,
+            storage_mode,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let policy = RepackPolicy {
+                max_chain_depth,
+                max_patch_chain_ratio: max_patch_ratio.unwrap_or(env.max_patch_chain_ratio),
+                storage_mode: storage_mode.map(repository_storage_mode).unwrap_or(repo_data.storage_mode),
+            };
+
+            let result = repository_operations::repack(env, &repo_paths, &mut repo_data, &policy)?;
+
+            match result {
+                RepackResult::Ok {
+                    before,
+                    after,
+                    kept_for_undo_length,
+                } => {
+                    formatting::print_repack_summary(&before, &after, kept_for_undo_length);
+                    success_ok()
+                }
+                RepackResult::ContentMismatch(version_id) => error(format!("Content of version {} does not match its hash", version_id.bs58())),
+            }
+        }
+
         Command::Create(CreateCommand::Branch {
             versioned_file_path,
             checkout,
             name,
         }) => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
             let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             let result = repository_operations::create_branch(&repo_paths, &mut repo_data, &name, checkout)?;
@@ -303,8 +676,27 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             }
         }
 
+        Command::Create(CreateCommand::Tag {
+            versioned_file_path,
+            name,
+            target,
+        }) => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let result = repository_operations::create_tag(&repo_paths, &mut repo_data, &name, &target)?;
+
+            match result {
+                CreateTagResult::Ok => success_ok(),
+                CreateTagResult::InvalidTagName => error("Invalid tag name"),
+                CreateTagResult::TagAlreadyExists => error("Tag already exists"),
+                CreateTagResult::InvalidTarget => error("Invalid target"),
+            }
+        }
+
         Command::List(ListCommand::Branches { versioned_file_path }) => {
-            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
             let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
             formatting::print_branch_list(&repo_data);
@@ -312,13 +704,27 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
             success()
         }
 
+        Command::List(ListCommand::Tags { versioned_file_path }) => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            if repo_data.tags.is_empty() {
+                return warning("No tags");
+            }
+
+            formatting::print_tag_list(&repo_data);
+
+            success()
+        }
+
         Command::Rename(rename_command) => match rename_command {
             RenameCommand::Branch {
                 versioned_file_path,
                 old_name,
                 new_name,
             } => {
-                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
                 let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
                 let result = repository_operations::rename_branch(&repo_paths, &mut repo_data, &old_name, &new_name)?;
@@ -337,7 +743,8 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                 confirmed,
                 name,
             } => {
-                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path);
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
                 let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
 
                 if !confirmed {
@@ -348,7 +755,7 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                     }
                 }
 
-                let result = repository_operations::delete_branch(&repo_paths, &mut repo_data, &name)?;
+                let result = repository_operations::delete_branch(env, &repo_paths, &mut repo_data, &name)?;
 
                 match result {
                     DeleteBranchResult::Ok => success_ok(),
@@ -356,6 +763,420 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
                     DeleteBranchResult::CannotDeleteHead => error("Cannot delete the version currently pointed at by HEAD"),
                 }
             }
+
+            DeleteCommand::Tag {
+                versioned_file_path,
+                confirmed,
+                name,
+            } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                if !confirmed {
+                    println!("Are you sure you want to delete this tag? (y/N)");
+                    let confirmed = read_yes_no_input()?.unwrap_or(false);
+                    if !confirmed {
+                        return success();
+                    }
+                }
+
+                let result = repository_operations::delete_tag(env, &repo_paths, &mut repo_data, &name)?;
+
+                match result {
+                    DeleteTagResult::Ok => success_ok(),
+                    DeleteTagResult::TagDoesNotExist => error("Tag does not exist"),
+                    DeleteTagResult::HeadWouldBeErased => error("Cannot delete the tag because head is only reachable through it. Check out a branch first."),
+                }
+            }
+        },
+
+        Command::Recover(recover_command) => match recover_command {
+            RecoverCommand::List { versioned_file_path } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+
+                let backups = repository_io::read_data_backups(&repo_paths)?;
+
+                if backups.is_empty() {
+                    return warning("No backups");
+                }
+
+                formatting::print_data_backups(&backups);
+
+                success()
+            }
+
+            RecoverCommand::Diff { versioned_file_path, backup } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let Some(backup) = repository_io::read_data_backup(&repo_paths, backup)? else {
+                    return error("Backup does not exist");
+                };
+
+                let Some(backup_data) = &backup.data else {
+                    return error("Backup cannot be read");
+                };
+
+                let diff = repository_operations::diff_data(&repo_data, backup_data);
+
+                formatting::print_data_diff(&repo_data, backup_data, &diff);
+
+                success()
+            }
+
+            RecoverCommand::Restore {
+                versioned_file_path,
+                confirmed,
+                backup,
+            } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+
+                if !confirmed {
+                    println!("Are you sure you want to replace the repository data with backup {}? (y/N)", backup);
+                    let confirmed = read_yes_no_input()?.unwrap_or(false);
+                    if !confirmed {
+                        return success();
+                    }
+                }
+
+                let result = repository_operations::restore_backup(&repo_paths, backup)?;
+
+                match result {
+                    RestoreBackupResult::Ok => success_ok(),
+                    RestoreBackupResult::BackupDoesNotExist => error("Backup does not exist"),
+                    RestoreBackupResult::BackupUnreadable => error("Backup cannot be read"),
+                    RestoreBackupResult::BackupInvalid => error("Backup contains invalid repository data"),
+                    RestoreBackupResult::MissingBlobs(file_names) => error(format!("Backup references missing files: {}", file_names.join(", "))),
+                }
+            }
+        },
+
+        Command::Bundle(bundle_command) => match bundle_command {
+            BundleCommand::Create {
+                versioned_file_path,
+                branches,
+                since,
+                output,
+            } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let selection = BundleSelection { branches, since };
+
+                match repository_operations::create_bundle(env, &repo_paths, &repo_data, &selection, &output)? {
+                    CreateBundleResult::Ok { version_count, branch_count } => {
+                        println!("Bundled {} versions on {} branches", version_count, branch_count);
+                        success_ok()
+                    }
+                    CreateBundleResult::OutputExists => error("Output file already exists"),
+                    CreateBundleResult::InvalidBranch(branch) => error(format!("Branch {} does not exist", branch)),
+                    CreateBundleResult::InvalidSince => error("Invalid target"),
+                    CreateBundleResult::SinceNotOnBranch(branch) => error(format!("Branch {} does not contain the since version", branch)),
+                    CreateBundleResult::NothingSelected => error("No branch contains the since version"),
+                }
+            }
+
+            BundleCommand::Import {
+                versioned_file_path,
+                layout,
+                bundle,
+            } => {
+                let verified_bundle = verified_bundle(repository_operations::verify_bundle(&bundle)?)?;
+
+                if fs::exists(repository_paths::sibling_path(&versioned_file_path))? {
+                    return error("Already initialized");
+                }
+                if fs::exists(&versioned_file_path)? {
+                    return error("Versioned file already exists");
+                }
+
+                let repo_paths = create_repository_dir(env, versioned_file_path, layout)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+
+                repository_operations::import_bundle(env, &repo_paths, verified_bundle)?;
+
+                success_ok()
+            }
+        },
+
+        Command::Clone {
+            versioned_file_path,
+            layout,
+            copy,
+            source,
+        } => {
+            // Versioned files are files too, so anything that does not start like a bundle is taken for a repository.
+            let verified_bundle = if source.is_file() {
+                match repository_operations::verify_bundle(&source)? {
+                    VerifyBundleResult::NotABundle => None,
+                    result => Some(verified_bundle(result)?),
+                }
+            } else {
+                None
+            };
+
+            if fs::exists(repository_paths::sibling_path(&versioned_file_path))? {
+                return error("Already initialized");
+            }
+            if fs::exists(&versioned_file_path)? {
+                return error("Versioned file already exists");
+            }
+
+            if let Some(verified_bundle) = verified_bundle {
+                let repo_paths = create_repository_dir(env, versioned_file_path, layout)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+
+                repository_operations::import_bundle(env, &repo_paths, verified_bundle)?;
+
+                return success_ok();
+            }
+
+            let source_paths = RepositoryPaths::from_versioned_file_path(repository_paths::versioned_file_path_of_repository(&source))?;
+            if !fs::exists(&source_paths.data_file)? {
+                return error("Source is neither an initialized repository nor a bundle");
+            }
+
+            let _source_lock = lock_repository(env, &source_paths)?;
+            let source_data = repository_io::read_data(&source_paths)?.initialized()?;
+
+            let repo_paths = create_repository_dir(env, versioned_file_path, layout)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+
+            let version_count = repository_operations::clone_repository(env, &source_paths, &source_data, &repo_paths, copy)?;
+
+            println!("Cloned {} versions on {} branches", version_count, source_data.branches.len());
+
+            success_ok()
+        }
+
+        Command::Remote(remote_command) => match remote_command {
+            RemoteCommand::Add { versioned_file_path, name, path } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let result = repository_operations::add_remote(&repo_paths, &mut repo_data, &name, &path)?;
+
+                match result {
+                    AddRemoteResult::Ok => success_ok(),
+                    AddRemoteResult::InvalidName => error("Invalid remote name"),
+                    AddRemoteResult::RemoteExists => error("Remote already exists"),
+                    AddRemoteResult::NotInitialized => error("Remote repository is not initialized"),
+                    AddRemoteResult::SameRepository => error("Cannot add the repository as its own remote"),
+                }
+            }
+
+            RemoteCommand::Remove {
+                versioned_file_path,
+                confirmed,
+                name,
+            } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                if !confirmed {
+                    println!("Are you sure you want to remove this remote and the versions only on its remote-tracking branches? (y/N)");
+                    let confirmed = read_yes_no_input()?.unwrap_or(false);
+                    if !confirmed {
+                        return success();
+                    }
+                }
+
+                let result = repository_operations::remove_remote(env, &repo_paths, &mut repo_data, &name)?;
+
+                match result {
+                    RemoveRemoteResult::Ok => success_ok(),
+                    RemoveRemoteResult::RemoteDoesNotExist => error("Remote does not exist"),
+                    RemoveRemoteResult::HeadWouldBeErased => error("Cannot remove the remote because head is only on its remote-tracking branches. Check out a branch first."),
+                }
+            }
+
+            RemoteCommand::List { versioned_file_path } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                if repo_data.remotes.is_empty() {
+                    return warning("No remotes");
+                }
+
+                formatting::print_remotes(&repo_data);
+
+                success()
+            }
+        },
+
+        Command::Push {
+            versioned_file_path,
+            branches,
+            remote,
+        } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let (remote_paths, _remote_lock, mut remote_data) = open_remote(env, &repo_data, &remote)?;
+
+            let result = repository_operations::push(env, &repo_paths, &mut repo_data, &remote, &remote_paths, &mut remote_data, &branches)?;
+
+            match result {
+                PushResult::Ok { version_count, branches } => {
+                    formatting::print_branch_syncs(&branches, "remote");
+                    println!("Pushed {} versions", version_count);
+                    branch_syncs_outcome(&branches)
+                }
+                PushResult::InvalidBranch(branch) => error(format!("Branch {} does not exist", branch)),
+                PushResult::HeadWouldBeErased => error("Head is only on remote-tracking branches that the push would move. Check out a branch first."),
+            }
+        }
+
+        Command::Pull { versioned_file_path, remote } => {
+            let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+            let _lock = lock_repository(env, &repo_paths)?;
+            let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+            let (remote_paths, _remote_lock, remote_data) = open_remote(env, &repo_data, &remote)?;
+
+            let result = repository_operations::pull(env, &repo_paths, &mut repo_data, &remote, &remote_paths, &remote_data)?;
+
+            match result {
+                PullResult::Ok { version_count, branches } => {
+                    formatting::print_branch_syncs(&branches, "local");
+                    println!("Pulled {} versions", version_count);
+                    branch_syncs_outcome(&branches)
+                }
+                PullResult::HeadWouldBeErased => error("Head is only on remote-tracking branches that the pull would move. Check out a branch first."),
+            }
+        }
+
+        Command::Prop(prop_command) => match prop_command {
+            PropCommand::Set {
+                versioned_file_path,
+                key,
+                value,
+                target,
+            } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let result = repository_operations::set_property(&repo_paths, &mut repo_data, &key, &value, &target)?;
+
+                match result {
+                    SetPropertyResult::Ok => success_ok(),
+                    SetPropertyResult::InvalidKey => error("Invalid property name, it must not be empty or contain spaces or ="),
+                    SetPropertyResult::InvalidTarget => error("Invalid target"),
+                }
+            }
+
+            PropCommand::Get { versioned_file_path, key, target } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let version = match repository_operations::version(&repo_data, &target) {
+                    VersionResult::InvalidTarget => return error("Invalid target"),
+                    VersionResult::Ok(version) => version,
+                };
+
+                match version.properties.get(&key) {
+                    Some(value) => println!("{}", value),
+                    None => return warning("Not set"),
+                }
+
+                success()
+            }
+
+            PropCommand::Unset { versioned_file_path, key, target } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let _lock = lock_repository(env, &repo_paths)?;
+                let mut repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let result = repository_operations::unset_property(&repo_paths, &mut repo_data, &key, &target)?;
+
+                match result {
+                    UnsetPropertyResult::Ok => success_ok(),
+                    UnsetPropertyResult::PropertyNotSet => warning("Not set"),
+                    UnsetPropertyResult::InvalidTarget => error("Invalid target"),
+                }
+            }
+
+            PropCommand::List { versioned_file_path, target } => {
+                let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+                let repo_data = repository_io::read_data(&repo_paths)?.initialized()?;
+
+                let version = match repository_operations::version(&repo_data, &target) {
+                    VersionResult::InvalidTarget => return error("Invalid target"),
+                    VersionResult::Ok(version) => version,
+                };
+
+                if version.properties.is_empty() {
+                    return warning("No properties");
+                }
+
+                formatting::print_properties(version);
+
+                success()
+            }
+        },
+
+        Command::Config(config_command) => match config_command {
+            ConfigCommand::Get { versioned_file_path, global, key } => {
+                let key = config_key(key);
+                let layers = read_config_layers(versioned_file_path, global)?;
+
+                match layers.iter().find_map(|(_, config)| config.get(key)) {
+                    Some(value) => println!("{}", value),
+                    None => return warning("Not set"),
+                }
+
+                success()
+            }
+
+            ConfigCommand::Set {
+                versioned_file_path,
+                global,
+                key,
+                value,
+            } => {
+                let key = config_key(key);
+                let (config_path, _lock) = writable_config_path(env, versioned_file_path, global)?;
+                let mut config = config::read_config(&config_path)?;
+
+                if let SetConfigResult::InvalidValue = config.set(key, &value) {
+                    return error(format!("Invalid value for {}, expected {}", key.name(), key.expected_value()));
+                }
+
+                config::write_config(&config_path, &config)?;
+
+                success_ok()
+            }
+
+            ConfigCommand::Unset { versioned_file_path, global, key } => {
+                let key = config_key(key);
+                let (config_path, _lock) = writable_config_path(env, versioned_file_path, global)?;
+                let mut config = config::read_config(&config_path)?;
+
+                if config.get(key).is_none() {
+                    return warning("Not set");
+                }
+
+                config.unset(key);
+                config::write_config(&config_path, &config)?;
+
+                success_ok()
+            }
+
+            ConfigCommand::List { versioned_file_path, global } => {
+                let layers = read_config_layers(versioned_file_path, global)?;
+                let entries = config::effective_entries(&layers.iter().map(|(layer, config)| (*layer, config)).collect::<Vec<_>>());
+
+                formatting::print_config_entries(&entries);
+
+                success()
+            }
         },
 
         Command::Dependencies => {
@@ -365,6 +1186,14 @@ fn run_command(env: &Env, command: Command) -> BiverResult<()> {
     }
 }
 
+fn applied_log_entry_outcome(versioned_file_left_unchanged: bool) -> BiverResult<()> {
+    if versioned_file_left_unchanged {
+        return warning("The versioned file was left unchanged because it has uncommitted changes");
+    }
+
+    success_ok()
+}
+
 fn success_ok() -> BiverResult<()> {
     println!("{}", "OK".green());
     Ok(())
@@ -374,6 +1203,167 @@ fn success() -> BiverResult<()> {
     Ok(())
 }
 
+fn repository_storage_mode(storage_mode: StorageMode) -> repository_data::StorageMode {
+    match storage_mode {
+        StorageMode::Forward => repository_data::StorageMode::Forward,
+        StorageMode::Reverse => repository_data::StorageMode::Reverse,
+    }
+}
+
+/// Offers to move the repository to a file that matches its head version, for when the versioned file has been renamed without it.
+fn offer_reattach(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<()> {
+    let Some(renamed_versioned_file) = repository_operations::find_renamed_versioned_file(repo_paths, repo_data)? else {
+        return error("Versioned file is missing");
+    };
+
+    println!(
+        "Versioned file {} is missing, but {} matches the head version. Reattach the repository to it? (y/N)",
+        repo_paths.versioned_file.display(),
+        renamed_versioned_file.display()
+    );
+    let confirmed = read_yes_no_input()?.unwrap_or(false);
+    if !confirmed {
+        return success();
+    }
+
+    let mut lock = lock_repository(env, repo_paths)?;
+
+    match repository_operations::move_versioned_file(repo_paths, &renamed_versioned_file, true)? {
+        MoveResult::Ok(new_repo_paths) => {
+            lock.relocate(&new_repo_paths);
+            success_ok()
+        }
+        MoveResult::DestinationHasRepository => error("The matching file already has a repository"),
+        MoveResult::VersionedFileMissing | MoveResult::DestinationExists | MoveResult::DestinationMissing => error("The matching file has been moved"),
+    }
+}
+
+/// Creates the repository directory in the requested layout, unless the versioned file already has a repository.
+fn create_repository_dir(env: &Env, versioned_file_path: PathBuf, layout: Option<RepositoryLayout>) -> BiverResult<RepositoryPaths> {
+    let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path.clone())?;
+
+    if fs::exists(&repo_paths.repository_dir)? {
+        return Ok(repo_paths);
+    }
+
+    match layout.map(repository_layout).unwrap_or(env.layout) {
+        repository_paths::RepositoryLayout::Sibling => {
+            fs::create_dir(&repo_paths.repository_dir)?;
+            Ok(repo_paths)
+        }
+        repository_paths::RepositoryLayout::Central => Ok(RepositoryPaths::create_central(versioned_file_path)?),
+    }
+}
+
+fn repository_layout(layout: RepositoryLayout) -> repository_paths::RepositoryLayout {
+    match layout {
+        RepositoryLayout::Sibling => repository_paths::RepositoryLayout::Sibling,
+        RepositoryLayout::Central => repository_paths::RepositoryLayout::Central,
+    }
+}
+
+fn config_key(key: ConfigKey) -> config::ConfigKey {
+    match key {
+        ConfigKey::DefaultBranch => config::ConfigKey::DefaultBranch,
+        ConfigKey::StorageMode => config::ConfigKey::StorageMode,
+        ConfigKey::Layout => config::ConfigKey::Layout,
+        ConfigKey::MaxPatchChainRatio => config::ConfigKey::MaxPatchChainRatio,
+        ConfigKey::MaxVersionsToPrint => config::ConfigKey::MaxVersionsToPrint,
+        ConfigKey::PreviewSize => config::ConfigKey::PreviewSize,
+        ConfigKey::Xdelta3Path => config::ConfigKey::Xdelta3Path,
+        ConfigKey::ImageMagickPath => config::ConfigKey::ImageMagickPath,
+        ConfigKey::PatchCodec => config::ConfigKey::PatchCodec,
+        ConfigKey::FullCodec => config::ConfigKey::FullCodec,
+        ConfigKey::LockTimeout => config::ConfigKey::LockTimeout,
+        ConfigKey::AuthorName => config::ConfigKey::AuthorName,
+        ConfigKey::AuthorEmail => config::ConfigKey::AuthorEmail,
+        ConfigKey::RecordHostname => config::ConfigKey::RecordHostname,
+    }
+}
+
+/// The configuration layers from highest to lowest precedence. With --global, the repository configuration is left out.
+fn read_config_layers(versioned_file_path: Option<PathBuf>, global: bool) -> BiverResult<Vec<(ConfigLayer, Config)>> {
+    let mut layers = Vec::new();
+
+    if !global {
+        let versioned_file_path = versioned_file_path.expect("The versioned file is required without --global");
+        let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+        layers.push((ConfigLayer::Repository, config::read_config(&config::repository_config_path(&repo_paths))?));
+    }
+
+    if let Some(global_config_path) = config::global_config_path() {
+        layers.push((ConfigLayer::Global, config::read_config(&global_config_path)?));
+    }
+
+    layers.push((ConfigLayer::Default, Config::defaults()));
+
+    Ok(layers)
+}
+
+/// The repository configuration is changed under the repository lock.
+fn writable_config_path(env: &Env, versioned_file_path: Option<PathBuf>, global: bool) -> BiverResult<(PathBuf, Option<RepositoryLock>)> {
+    if global {
+        return match config::global_config_path() {
+            Some(global_config_path) => Ok((global_config_path, None)),
+            None => error("Cannot determine the global configuration directory"),
+        };
+    }
+
+    let versioned_file_path = versioned_file_path.expect("The versioned file is required without --global");
+    let repo_paths = RepositoryPaths::from_versioned_file_path(versioned_file_path)?;
+    let lock = lock_repository(env, &repo_paths)?;
+
+    Ok((config::repository_config_path(&repo_paths), Some(lock)))
+}
+
+fn verified_bundle(result: VerifyBundleResult) -> BiverResult<VerifiedBundle> {
+    match result {
+        VerifyBundleResult::Ok(verified_bundle) => Ok(*verified_bundle),
+        VerifyBundleResult::NotABundle => error("Not a biver bundle"),
+        VerifyBundleResult::NewerBundleFormatVersion(format_version) => error(format!(
+            "Bundle format version {} is newer than the latest version {} supported by this biver. Upgrade biver to import this bundle.",
+            format_version,
+            bundle::CURRENT_BUNDLE_FORMAT_VERSION
+        )),
+        VerifyBundleResult::CorruptedFiles(file_names) => error(format!("Bundle is corrupted: {}", file_names.join(", "))),
+        VerifyBundleResult::NewerFormatVersion(format_version) => newer_format_version_error(format_version),
+        VerifyBundleResult::InvalidData => error("Bundle contains invalid repository data"),
+        VerifyBundleResult::MissingFiles(file_names) => error(format!("Bundle is missing files: {}", file_names.join(", "))),
+    }
+}
+
+/// Locks the repository of the remote and reads its data. The lock is held until the push or pull is done.
+fn open_remote(env: &Env, repo_data: &RepositoryData, remote_name: &str) -> BiverResult<(RepositoryPaths, RepositoryLock, RepositoryData)> {
+    let Some(remote) = repo_data.remotes.get(remote_name) else {
+        return error(format!("Remote {} does not exist", remote_name));
+    };
+
+    let remote_paths = RepositoryPaths::from_versioned_file_path(remote.path.clone())?;
+    if !fs::exists(&remote_paths.data_file)? {
+        return error(format!("Remote repository {} is not initialized", remote.path.display()));
+    }
+
+    let remote_lock = lock_repository(env, &remote_paths)?;
+    let remote_data = repository_io::read_data(&remote_paths)?.initialized()?;
+
+    Ok((remote_paths, remote_lock, remote_data))
+}
+
+fn branch_syncs_outcome(branch_syncs: &[BranchSync]) -> BiverResult<()> {
+    let some_branches_left_unchanged = branch_syncs.iter().any(|b| {
+        matches!(
+            b.status,
+            BranchSyncStatus::Diverged | BranchSyncStatus::Unrelated | BranchSyncStatus::BlockedByUncommittedChanges
+        )
+    });
+
+    if some_branches_left_unchanged {
+        warning("Some branches were left unchanged")
+    } else {
+        success_ok()
+    }
+}
+
 fn read_yes_no_input() -> BiverResult<Option<bool>> {
     let mut input = String::new();
     io::stdin().read_line(&mut input)?;
@@ -389,6 +1379,27 @@ fn read_yes_no_input() -> BiverResult<Option<bool>> {
     Ok(None)
 }
 
+/// Mutating commands hold the lock from reading the repository data until they are done writing it.
+/// An operation interrupted while holding the lock is recovered as soon as the lock is taken again.
+fn lock_repository(env: &Env, repo_paths: &RepositoryPaths) -> BiverResult<RepositoryLock> {
+    if !fs::exists(&repo_paths.repository_dir)? {
+        return error("Not initialized");
+    }
+
+    match repository_lock::acquire(repo_paths, env.lock_timeout)? {
+        LockResult::Acquired(lock) => {
+            repository_io::recover_interrupted_operation(repo_paths)?;
+            Ok(lock)
+        }
+        LockResult::Locked(Some(owner)) => error(format!(
+            "Repository is locked by PID {} since {}",
+            owner.pid,
+            owner.since.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S")
+        )),
+        LockResult::Locked(None) => error("Repository is locked"),
+    }
+}
+
 trait RepositoryDataResultExtensions {
     fn initialized(self) -> BiverResult<RepositoryData>;
 }
@@ -400,7 +1411,16 @@ impl RepositoryDataResultExtensions for RepositoryDataResult {
                 error_message: "Not initialized".to_string(),
                 severity: BiverErrorSeverity::Error,
             }),
-            RepositoryDataResult::Initialized(repository_data) => Ok(repository_data),
+            RepositoryDataResult::NewerFormatVersion(format_version) => newer_format_version_error(format_version),
+            RepositoryDataResult::Initialized(repository_data) => Ok(*repository_data),
         }
     }
 }
+
+fn newer_format_version_error<T>(format_version: u32) -> BiverResult<T> {
+    error(format!(
+        "Repository data format version {} is newer than the latest version {} supported by this biver. Upgrade biver to use this repository.",
+        format_version,
+        data_migration::CURRENT_FORMAT_VERSION
+    ))
+}
@@ -0,0 +1,321 @@
+//! The operation log records every change of the repository data, so that operations can be undone and redone.
+//! Entries are appended as JSON lines and never rewritten.
+
+use crate::repository_data::{DataChanges, RepositoryData, RepositoryState, Version};
+use crate::repository_paths::RepositoryPaths;
+use crate::version_id::VersionId;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::{fs, io};
+
+pub const OPERATION_LOG_FILE_NAME: &str = "operation_log.jsonl";
+
+pub const UNDO_OPERATION: &str = "undo";
+pub const REDO_OPERATION: &str = "redo";
+
+/// A version whose blob, description, properties or parent changed.
+#[derive(Serialize, Deserialize)]
+pub struct ChangedVersion {
+    pub before: Version,
+    pub after: Version,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OperationLogEntry {
+    pub number: u64,
+    pub operation: String,
+    /// The entry that an undo or redo entry undoes or redoes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub applied_entry: Option<u64>,
+    pub time: DateTime<Utc>,
+    pub before: RepositoryState,
+    pub after: RepositoryState,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub added_versions: Vec<Version>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed_versions: Vec<Version>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub changed_versions: Vec<ChangedVersion>,
+}
+
+impl OperationLogEntry {
+    pub fn affected_version_ids(&self) -> impl Iterator<Item = VersionId> {
+        self.added_versions
+            .iter()
+            .chain(self.removed_versions.iter())
+            .chain(self.changed_versions.iter().map(|c| &c.after))
+            .map(|v| v.id)
+    }
+
+    /// The versions as they were before and after the operation.
+    pub fn versions(&self) -> impl Iterator<Item = &Version> {
+        self.added_versions
+            .iter()
+            .chain(self.removed_versions.iter())
+            .chain(self.changed_versions.iter().flat_map(|c| [&c.before, &c.after]))
+    }
+
+    /// Blobs of the versions as they were before and after the operation, which undoing or redoing it needs.
+    pub fn referenced_file_names(&self) -> impl Iterator<Item = &str> {
+        self.versions()
+            .flat_map(|v| [Some(v.content_blob_file_name.as_str()), v.preview_blob_file_name.as_deref()])
+            .flatten()
+    }
+}
+
+/// The entry for the changes recorded in the data, numbered after the last entry. None if the data was not read from the data file
+/// or nothing changed.
+pub fn new_entry(last_entry: Option<&OperationLogEntry>, operation: &str, applied_entry: Option<u64>, changes: DataChanges, data: &RepositoryData) -> Option<OperationLogEntry> {
+    let before_state = changes.state_before?;
+    let after_state = RepositoryState::of(data);
+
+    let added_versions: Vec<_> = changes.added_version_ids.iter().filter_map(|id| data.version(*id)).cloned().collect();
+    let mut removed_versions = Vec::new();
+    let mut changed_versions = Vec::new();
+    for (id, before_version) in changes.versions_before {
+        match data.version(id) {
+            Some(after_version) if *after_version != before_version => changed_versions.push(ChangedVersion {
+                before: before_version,
+                after: after_version.clone(),
+            }),
+            Some(_) => {}
+            None => removed_versions.push(before_version),
+        }
+    }
+    removed_versions.sort_by_key(|v| v.creation_time);
+    changed_versions.sort_by_key(|c| c.after.creation_time);
+
+    if before_state == after_state && added_versions.is_empty() && removed_versions.is_empty() && changed_versions.is_empty() {
+        return None;
+    }
+
+    Some(OperationLogEntry {
+        number: last_entry.map_or(1, |e| e.number + 1),
+        operation: operation.to_string(),
+        applied_entry,
+        time: Utc::now(),
+        before: before_state,
+        after: after_state,
+        added_versions,
+        removed_versions,
+        changed_versions,
+    })
+}
+
+pub fn append_entry(paths: &RepositoryPaths, entry: &OperationLogEntry) -> io::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    let mut file = OpenOptions::new().create(true).append(true).open(paths.file_path(OPERATION_LOG_FILE_NAME))?;
+    file.write_all(&line)?;
+    file.sync_all()
+}
+
+pub fn read_entries(paths: &RepositoryPaths) -> io::Result<Vec<OperationLogEntry>> {
+    let log_file_path = paths.file_path(OPERATION_LOG_FILE_NAME);
+
+    if !log_file_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(log_file_path)?;
+
+    content.lines().filter(|line| !line.is_empty()).map(|line| Ok(serde_json::from_str(line)?)).collect()
+}
+
+/// Reads the log backwards from its end, so that the cost does not grow with the length of the history.
+pub fn read_last_entry(paths: &RepositoryPaths) -> io::Result<Option<OperationLogEntry>> {
+    const CHUNK_LENGTH: u64 = 64 * 1024;
+
+    let log_file_path = paths.file_path(OPERATION_LOG_FILE_NAME);
+
+    if !log_file_path.exists() {
+        return Ok(None);
+    }
+
+    let mut file = File::open(log_file_path)?;
+    let mut position = file.metadata()?.len();
+    let mut tail = Vec::new();
+
+    loop {
+        // The last line ends with the trailing newline, so only an earlier newline marks its start.
+        let trimmed_length = tail.iter().rposition(|b: &u8| !b.is_ascii_whitespace()).map_or(0, |i| i + 1);
+        if let Some(line_start) = tail[..trimmed_length].iter().rposition(|b| *b == b'\n') {
+            return Ok(Some(serde_json::from_slice(&tail[line_start + 1..trimmed_length])?));
+        }
+
+        if position == 0 {
+            return match trimmed_length {
+                0 => Ok(None),
+                _ => Ok(Some(serde_json::from_slice(&tail[..trimmed_length])?)),
+            };
+        }
+
+        let chunk_length = position.min(CHUNK_LENGTH);
+        position -= chunk_length;
+
+        let mut chunk = vec![0; chunk_length as usize];
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut chunk)?;
+        chunk.append(&mut tail);
+        tail = chunk;
+    }
+}
+
+/// Entries that can be undone, most recent last, and entries that can be redone, most recently undone last.
+/// Undoing an entry makes it redoable until another operation changes the repository.
+pub fn undo_redo_stacks(entries: &[OperationLogEntry]) -> (Vec<&OperationLogEntry>, Vec<&OperationLogEntry>) {
+    let entries_by_number: HashMap<u64, &OperationLogEntry> = entries.iter().map(|e| (e.number, e)).collect();
+
+    let mut undoable = Vec::new();
+    let mut redoable = Vec::new();
+
+    for entry in entries {
+        let applied_entry = entry.applied_entry.and_then(|number| entries_by_number.get(&number).copied());
+
+        match (entry.operation.as_str(), applied_entry) {
+            (UNDO_OPERATION, Some(applied_entry)) => {
+                undoable.retain(|e: &&OperationLogEntry| e.number != applied_entry.number);
+                redoable.push(applied_entry);
+            }
+            (REDO_OPERATION, Some(applied_entry)) => {
+                redoable.retain(|e: &&OperationLogEntry| e.number != applied_entry.number);
+                undoable.push(applied_entry);
+            }
+            _ => {
+                undoable.push(entry);
+                redoable.clear();
+            }
+        }
+    }
+
+    (undoable, redoable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository_data::{ContentBlobCodec, Head, StorageMode};
+    use crate::temp_file;
+    use std::collections::BTreeMap;
+
+    fn version(parent: Option<VersionId>, description: &str) -> Version {
+        let id = VersionId::new();
+        Version {
+            id,
+            creation_time: Utc::now(),
+            nickname: String::new(),
+            versioned_file_length: 0,
+            versioned_file_xxh3_128: 0,
+            description: description.to_string(),
+            author: None,
+            properties: BTreeMap::new(),
+            parent,
+            content_blob_file_name: id.to_file_name(),
+            content_blob_codec: ContentBlobCodec::Raw,
+            content_blob_base: None,
+            preview_blob_file_name: None,
+        }
+    }
+
+    /// Data as read from the data file, with a single root version on the main branch.
+    fn read_data(root: Version) -> RepositoryData {
+        let data = RepositoryData::new(
+            1,
+            StorageMode::Forward,
+            Head::Branch("main".to_string()),
+            HashMap::from([("main".to_string(), root.id)]),
+            vec![root],
+        );
+        serde_json::from_slice(&serde_json::to_vec(&data).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn entry_holds_the_changes_made_since_the_data_was_read() {
+        let root = version(None, "root");
+        let root_id = root.id;
+        let mut data = read_data(root);
+
+        let added = version(Some(root_id), "added");
+        let added_id = added.id;
+        data.add_version(added);
+        data.set_description(root_id, "reworded".to_string());
+        data.branches.insert("main".to_string(), added_id);
+
+        let entry = new_entry(None, "commit", None, data.take_changes(), &data).unwrap();
+
+        assert_eq!(entry.number, 1);
+        assert_eq!(entry.added_versions.iter().map(|v| v.id).collect::<Vec<_>>(), vec![added_id]);
+        assert!(entry.removed_versions.is_empty());
+        assert_eq!(entry.changed_versions.len(), 1);
+        assert_eq!(entry.changed_versions[0].before.description, "root");
+        assert_eq!(entry.changed_versions[0].after.description, "reworded");
+        assert_eq!(entry.before.branches["main"], root_id);
+        assert_eq!(entry.after.branches["main"], added_id);
+
+        data.remove_versions(&[added_id]);
+        data.branches.insert("main".to_string(), root_id);
+
+        let entry = new_entry(Some(&entry), "undo", Some(1), data.take_changes(), &data).unwrap();
+
+        assert_eq!(entry.number, 2);
+        assert_eq!(entry.removed_versions.iter().map(|v| v.id).collect::<Vec<_>>(), vec![added_id]);
+        assert!(entry.added_versions.is_empty() && entry.changed_versions.is_empty());
+    }
+
+    #[test]
+    fn no_entry_without_changes_or_for_data_that_was_not_read() {
+        let root = version(None, "root");
+        let root_id = root.id;
+        let mut data = read_data(root);
+
+        let added = version(Some(root_id), "added");
+        let added_id = added.id;
+        data.add_version(added);
+        data.remove_versions(&[added_id]);
+        data.set_description(root_id, "reworded".to_string());
+        data.set_description(root_id, "root".to_string());
+
+        assert!(new_entry(None, "commit", None, data.take_changes(), &data).is_none());
+
+        let root = version(None, "root");
+        let mut data = RepositoryData::new(
+            1,
+            StorageMode::Forward,
+            Head::Branch("main".to_string()),
+            HashMap::from([("main".to_string(), root.id)]),
+            vec![root],
+        );
+
+        assert!(new_entry(None, "init", None, data.take_changes(), &data).is_none());
+    }
+
+    #[test]
+    fn last_entry_is_read_from_the_end_of_a_log_longer_than_a_chunk() {
+        let paths = RepositoryPaths::from_versioned_file_path(temp_file::path()).unwrap();
+        fs::create_dir_all(&paths.repository_dir).unwrap();
+
+        assert!(read_last_entry(&paths).unwrap().is_none());
+
+        let root = version(None, "root");
+        let root_id = root.id;
+        let mut data = read_data(root);
+        let mut last_entry = None;
+
+        for number in 1..=3 {
+            data.set_description(root_id, number.to_string().repeat(100 * 1024));
+            let entry = new_entry(last_entry.as_ref(), "reword", None, data.take_changes(), &data).unwrap();
+            append_entry(&paths, &entry).unwrap();
+            last_entry = read_last_entry(&paths).unwrap();
+
+            assert_eq!(last_entry.as_ref().map(|e| e.number), Some(number));
+        }
+
+        assert_eq!(read_entries(&paths).unwrap().len(), 3);
+
+        fs::remove_dir_all(&paths.repository_dir).unwrap();
+    }
+}
@@ -1,18 +1,267 @@
 use crate::version_id::VersionId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
 
 #[derive(Debug, Serialize, Deserialize)]
+#[serde(from = "SerializedRepositoryData")]
 pub struct RepositoryData {
+    pub format_version: u32,
+    pub storage_mode: StorageMode,
     pub head: Head,
     pub branches: HashMap<String, VersionId>,
-    pub versions: Vec<Version>,
+    pub remotes: HashMap<String, Remote>,
+    pub tags: HashMap<String, VersionId>,
+    versions: Vec<Version>,
+    /// Position of every version in `versions`.
+    #[serde(skip)]
+    version_indices: HashMap<VersionId, usize>,
+    /// Ids of the children of every version that has any.
+    #[serde(skip)]
+    children: HashMap<VersionId, Vec<VersionId>>,
+    /// Ids of the versions whose blob is a patch against it, for every version that is a patch base.
+    #[serde(skip)]
+    dependents: HashMap<VersionId, Vec<VersionId>>,
+    #[serde(skip)]
+    changes: DataChanges,
+}
+
+/// Everything in the repository data apart from the versions.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RepositoryState {
+    pub storage_mode: StorageMode,
+    pub head: Head,
+    pub branches: HashMap<String, VersionId>,
+    pub tags: HashMap<String, VersionId>,
+    pub remotes: HashMap<String, Remote>,
+}
+
+impl RepositoryState {
+    pub fn of(data: &RepositoryData) -> Self {
+        RepositoryState {
+            storage_mode: data.storage_mode,
+            head: data.head.clone(),
+            branches: data.branches.clone(),
+            tags: data.tags.clone(),
+            remotes: data.remotes.clone(),
+        }
+    }
+}
+
+/// Changes made to the data since it was read or last written. They are recorded as they are made,
+/// so that the operation log does not need to compare the whole data with the data file.
+#[derive(Debug, Default)]
+pub struct DataChanges {
+    /// None for data that was created rather than read, which has no previous state.
+    pub state_before: Option<RepositoryState>,
+    /// Versions as they were before they were first changed or removed.
+    pub versions_before: HashMap<VersionId, Version>,
+    pub added_version_ids: HashSet<VersionId>,
+}
+
+impl DataChanges {
+    fn record_before(&mut self, version: &Version) {
+        if !self.added_version_ids.contains(&version.id) && !self.versions_before.contains_key(&version.id) {
+            self.versions_before.insert(version.id, version.clone());
+        }
+    }
+
+    fn record_removed(&mut self, version: Version) {
+        if !self.added_version_ids.remove(&version.id) {
+            self.versions_before.entry(version.id).or_insert(version);
+        }
+    }
+
+    fn record_added(&mut self, id: VersionId) {
+        if !self.versions_before.contains_key(&id) {
+            self.added_version_ids.insert(id);
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SerializedRepositoryData {
+    format_version: u32,
+    storage_mode: StorageMode,
+    head: Head,
+    branches: HashMap<String, VersionId>,
+    remotes: HashMap<String, Remote>,
+    tags: HashMap<String, VersionId>,
+    versions: Vec<Version>,
+}
+
+impl From<SerializedRepositoryData> for RepositoryData {
+    fn from(data: SerializedRepositoryData) -> Self {
+        let mut repository_data = RepositoryData::new(data.format_version, data.storage_mode, data.head, data.branches, data.versions);
+        repository_data.remotes = data.remotes;
+        repository_data.tags = data.tags;
+        repository_data.changes.state_before = Some(RepositoryState::of(&repository_data));
+        repository_data
+    }
 }
 
 impl RepositoryData {
+    pub fn new(format_version: u32, storage_mode: StorageMode, head: Head, branches: HashMap<String, VersionId>, versions: Vec<Version>) -> Self {
+        let mut data = RepositoryData {
+            format_version,
+            storage_mode,
+            head,
+            branches,
+            remotes: HashMap::new(),
+            tags: HashMap::new(),
+            versions,
+            version_indices: HashMap::new(),
+            children: HashMap::new(),
+            dependents: HashMap::new(),
+            changes: DataChanges::default(),
+        };
+        data.rebuild_indices();
+        data
+    }
+
+    fn rebuild_indices(&mut self) {
+        self.version_indices = self.versions.iter().enumerate().map(|(index, v)| (v.id, index)).collect();
+        self.children.clear();
+        self.dependents.clear();
+        for version in &self.versions {
+            if let Some(parent) = version.parent {
+                self.children.entry(parent).or_default().push(version.id);
+            }
+            if let Some(base) = version.patch_base() {
+                self.dependents.entry(base).or_default().push(version.id);
+            }
+        }
+    }
+
+    fn move_dependent(&mut self, id: VersionId, previous_base: Option<VersionId>, base: Option<VersionId>) {
+        if previous_base == base {
+            return;
+        }
+        if let Some(previous_base) = previous_base {
+            remove_from_index(&mut self.dependents, previous_base, id);
+        }
+        if let Some(base) = base {
+            self.dependents.entry(base).or_default().push(id);
+        }
+    }
+
+    pub fn versions(&self) -> &[Version] {
+        &self.versions
+    }
+
+    pub fn add_version(&mut self, version: Version) {
+        self.changes.record_added(version.id);
+        self.version_indices.insert(version.id, self.versions.len());
+        if let Some(parent) = version.parent {
+            self.children.entry(parent).or_default().push(version.id);
+        }
+        if let Some(base) = version.patch_base() {
+            self.dependents.entry(base).or_default().push(version.id);
+        }
+        self.versions.push(version);
+    }
+
+    pub fn remove_versions(&mut self, version_ids: &[VersionId]) {
+        if version_ids.is_empty() {
+            return;
+        }
+        let version_ids: HashSet<_> = version_ids.iter().collect();
+        let (removed_versions, versions) = std::mem::take(&mut self.versions).into_iter().partition(|v| version_ids.contains(&v.id));
+        self.versions = versions;
+        for version in removed_versions {
+            self.changes.record_removed(version);
+        }
+        self.rebuild_indices();
+    }
+
+    /// Moves the version under another parent, or makes it the root. Its blob must not be a patch against the previous parent.
+    /// A patch base that becomes the parent is left implicit.
+    pub fn set_parent(&mut self, id: VersionId, parent: Option<VersionId>) {
+        let index = self.version_indices[&id];
+        self.changes.record_before(&self.versions[index]);
+        let version = &mut self.versions[index];
+        let previous_base = version.patch_base();
+        let previous_parent = std::mem::replace(&mut version.parent, parent);
+        if version.content_blob_base.is_some() && version.content_blob_base == parent {
+            version.content_blob_base = None;
+        }
+        let base = version.patch_base();
+
+        if let Some(previous_parent) = previous_parent {
+            remove_from_index(&mut self.children, previous_parent, id);
+        }
+        if let Some(parent) = parent {
+            self.children.entry(parent).or_default().push(id);
+        }
+        self.move_dependent(id, previous_base, base);
+    }
+
+    /// Replaces the version that has the same id, which may have another parent.
+    pub fn replace_version(&mut self, version: Version) {
+        self.set_parent(version.id, version.parent);
+        let index = self.version_indices[&version.id];
+        let previous_base = self.versions[index].patch_base();
+        let (id, base) = (version.id, version.patch_base());
+        self.changes.record_before(&self.versions[index]);
+        self.versions[index] = version;
+        self.move_dependent(id, previous_base, base);
+    }
+
+    /// Replaces all of the data, as when restoring a backup, recording the differences as changes.
+    pub fn replace_with(&mut self, data: RepositoryData) {
+        let mut changes = std::mem::take(&mut self.changes);
+        for version in std::mem::take(&mut self.versions) {
+            changes.record_removed(version);
+        }
+
+        *self = data;
+        for version in &self.versions {
+            changes.record_added(version.id);
+        }
+        self.changes = changes;
+    }
+
+    /// Returns the changes made since the data was read or last taken, and starts recording anew from the current state.
+    pub fn take_changes(&mut self) -> DataChanges {
+        let state = RepositoryState::of(self);
+        std::mem::replace(
+            &mut self.changes,
+            DataChanges {
+                state_before: Some(state),
+                ..DataChanges::default()
+            },
+        )
+    }
+
     pub fn version(&self, id: VersionId) -> Option<&Version> {
-        self.versions.iter().find(|v| v.id == id)
+        self.version_indices.get(&id).map(|index| &self.versions[*index])
+    }
+
+    pub fn set_description(&mut self, id: VersionId, description: String) {
+        let index = self.version_indices[&id];
+        self.changes.record_before(&self.versions[index]);
+        self.versions[index].description = description;
+    }
+
+    pub fn properties_mut(&mut self, id: VersionId) -> Option<&mut BTreeMap<String, String>> {
+        let index = *self.version_indices.get(&id)?;
+        self.changes.record_before(&self.versions[index]);
+        Some(&mut self.versions[index].properties)
+    }
+
+    /// Replaces the content blob of the version and returns the file name of the previous one. A base equal to the parent is left implicit.
+    pub fn set_content_blob(&mut self, id: VersionId, file_name: String, codec: ContentBlobCodec, base: Option<VersionId>) -> String {
+        let index = self.version_indices[&id];
+        self.changes.record_before(&self.versions[index]);
+        let version = &mut self.versions[index];
+        let previous_base = version.patch_base();
+        version.content_blob_codec = codec;
+        version.content_blob_base = base.filter(|base_id| version.parent != Some(*base_id));
+        let base = version.patch_base();
+        let previous_file_name = std::mem::replace(&mut version.content_blob_file_name, file_name);
+        self.move_dependent(id, previous_base, base);
+        previous_file_name
     }
 
     pub fn head_version(&self) -> &Version {
@@ -28,38 +277,76 @@ impl RepositoryData {
     }
 
     pub fn valid(&self) -> bool {
+        let all_ids_are_unique = self.version_indices.len() == self.versions.len();
+
         let there_is_exactly_one_root = self.versions.iter().filter(|v| v.parent.is_none()).count() == 1;
 
-        let all_parent_references_are_valid = self.versions.iter().all(|v| {
-            if let Some(parent) = &v.parent {
-                self.versions.iter().any(|v2| v2.id == *parent)
-            } else {
-                true
-            }
+        let all_parent_references_are_valid = self.versions.iter().all(|v| match &v.parent {
+            Some(parent) => self.version_indices.contains_key(parent),
+            None => true,
         });
 
         let head_reference_is_valid = match &self.head {
             Head::Branch(branch) => self.branches.contains_key(branch),
-            Head::Version(version_id) => self.versions.iter().any(|v| v.id == *version_id),
+            Head::Version(version_id) => self.version_indices.contains_key(version_id),
         };
 
-        let all_branches_reference_valid_versions = self.branches.values().all(|branch_version_id| self.versions.iter().any(|v| v.id == *branch_version_id));
+        let all_patch_bases_are_valid = self.versions.iter().all(|v| match v.patch_base() {
+            Some(base) => self.version_indices.contains_key(&base),
+            None => v.content_blob_kind().is_full(),
+        }) && self.blob_chains_are_acyclic();
+
+        let all_branches_reference_valid_versions = self.branches.values().all(|branch_version_id| self.version_indices.contains_key(branch_version_id));
+
+        let all_remote_branches_reference_valid_versions = self.iter_remote_branch_leaves().all(|version_id| self.version_indices.contains_key(&version_id));
+
+        let all_tags_reference_valid_versions = self.tags.values().all(|version_id| self.version_indices.contains_key(version_id));
 
-        let all_versions_belong_to_branches = {
-            let mut versions_belonging_to_branches = HashSet::new();
+        let all_versions_are_reachable = {
+            let mut reachable_versions = HashSet::new();
 
-            for branch_leaf_id in self.branches.values() {
-                for v in self.iter_version_and_ancestors(*branch_leaf_id) {
-                    if !versions_belonging_to_branches.insert(v.id) {
+            for referenced_version_id in self.iter_referenced_version_ids() {
+                for v in self.iter_version_and_ancestors(referenced_version_id) {
+                    if !reachable_versions.insert(v.id) {
                         break;
                     }
                 }
             }
 
-            versions_belonging_to_branches.len() == self.versions.len()
+            reachable_versions.len() == self.versions.len()
         };
 
-        there_is_exactly_one_root && all_parent_references_are_valid && head_reference_is_valid && all_branches_reference_valid_versions && all_versions_belong_to_branches
+        all_ids_are_unique
+            && there_is_exactly_one_root
+            && all_parent_references_are_valid
+            && all_patch_bases_are_valid
+            && head_reference_is_valid
+            && all_branches_reference_valid_versions
+            && all_remote_branches_reference_valid_versions
+            && all_tags_reference_valid_versions
+            && all_versions_are_reachable
+    }
+
+    /// Walks every blob chain once, remembering the versions already known to end in a full blob.
+    fn blob_chains_are_acyclic(&self) -> bool {
+        let mut ends_in_full_blob = HashSet::new();
+
+        for version in &self.versions {
+            let mut visited_in_this_chain = HashSet::new();
+
+            for v in self.iter_blob_chain(version.id) {
+                if ends_in_full_blob.contains(&v.id) {
+                    break;
+                }
+                if !visited_in_this_chain.insert(v.id) {
+                    return false;
+                }
+            }
+
+            ends_in_full_blob.extend(visited_in_this_chain);
+        }
+
+        true
     }
 
     pub fn iter_version_and_ancestors(&'_ self, version_id: VersionId) -> impl Iterator<Item = &'_ Version> {
@@ -74,16 +361,69 @@ impl RepositoryData {
         self.iter_version_and_ancestors(self.head_version().id)
     }
 
+    /// Iterates over the versions whose blobs are needed to reconstruct the content of the specified version,
+    /// starting with the version itself and ending with a version stored in full.
+    pub fn iter_blob_chain(&'_ self, version_id: VersionId) -> impl Iterator<Item = &'_ Version> {
+        let version = self.version(version_id);
+        BlobChain {
+            repository_data: self,
+            current_version: version,
+        }
+    }
+
     pub fn iter_children(&self, version_id: VersionId) -> impl Iterator<Item = &'_ Version> {
-        self.versions.iter().filter(move |v| v.parent == Some(version_id))
+        self.children.get(&version_id).into_iter().flatten().filter_map(|child_id| self.version(*child_id))
+    }
+
+    /// Versions whose blob is a patch against the specified version.
+    pub fn iter_dependents(&self, version_id: VersionId) -> impl Iterator<Item = &'_ Version> {
+        self.dependents
+            .get(&version_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|dependent_id| self.version(*dependent_id))
     }
 
     pub fn branch_leaf(&self, branch: &str) -> Option<&Version> {
         self.branches.get(branch).and_then(|version_id| self.version(*version_id))
     }
+
+    /// Remote-tracking branches are named after their remote, as in origin/main.
+    pub fn iter_remote_branches(&self) -> impl Iterator<Item = (String, VersionId)> {
+        self.remotes
+            .iter()
+            .flat_map(|(remote_name, remote)| remote.branches.iter().map(move |(branch, version_id)| (format!("{}/{}", remote_name, branch), *version_id)))
+    }
+
+    pub fn iter_remote_branch_leaves(&self) -> impl Iterator<Item = VersionId> {
+        self.remotes.values().flat_map(|remote| remote.branches.values()).copied()
+    }
+
+    /// Leaves of the local and remote-tracking branches and tagged versions. Every version is an ancestor of at least one of them.
+    pub fn iter_referenced_version_ids(&self) -> impl Iterator<Item = VersionId> {
+        self.branches.values().copied().chain(self.iter_remote_branch_leaves()).chain(self.tags.values().copied())
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn remove_from_index(index: &mut HashMap<VersionId, Vec<VersionId>>, key: VersionId, id: VersionId) {
+    if let Some(ids) = index.get_mut(&key) {
+        ids.retain(|other_id| *other_id != id);
+        if ids.is_empty() {
+            index.remove(&key);
+        }
+    }
+}
+
+/// Another repository that versions are pushed to and pulled from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Remote {
+    /// Absolute path of the versioned file of the remote repository.
+    pub path: PathBuf,
+    /// Where the branches of the remote were the last time they were pushed to or pulled from.
+    pub branches: HashMap<String, VersionId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Version {
     pub id: VersionId,
     pub creation_time: DateTime<Utc>,
@@ -91,9 +431,18 @@ pub struct Version {
     pub versioned_file_length: u64,
     pub versioned_file_xxh3_128: u128,
     pub description: String,
+    /// Versions created before authors were recorded have none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub author: Option<Author>,
+    /// Free-form key/value pairs attached to the version, such as approvals or ticket numbers.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub properties: BTreeMap<String, String>,
     pub parent: Option<VersionId>,
     pub content_blob_file_name: String,
-    pub content_blob_kind: ContentBlobKind,
+    pub content_blob_codec: ContentBlobCodec,
+    /// The version whose content a patch blob applies to. Defaults to the parent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_blob_base: Option<VersionId>,
     pub preview_blob_file_name: Option<String>,
 }
 
@@ -101,9 +450,89 @@ impl Version {
     pub fn is_root(&self) -> bool {
         self.parent.is_none()
     }
+
+    /// Derived from the codec, so that the two cannot disagree.
+    pub fn content_blob_kind(&self) -> ContentBlobKind {
+        self.content_blob_codec.kind()
+    }
+
+    pub fn patch_base(&self) -> Option<VersionId> {
+        match self.content_blob_kind() {
+            ContentBlobKind::Full => None,
+            ContentBlobKind::Patch => self.content_blob_base.or(self.parent),
+        }
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Author {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// Name of the computer the version was created on, if recording it was enabled.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+}
+
+impl Author {
+    /// Whether the name, email or hostname contains the text, ignoring case.
+    pub fn matches(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+        [Some(&self.name), self.email.as_ref(), self.hostname.as_ref()]
+            .into_iter()
+            .flatten()
+            .any(|field| field.to_lowercase().contains(&text))
+    }
+}
+
+/// Selects versions by author and properties. The default filter selects every version.
+#[derive(Default)]
+pub struct VersionFilter {
+    pub author: Option<String>,
+    pub properties: Vec<PropertyFilter>,
+}
+
+impl VersionFilter {
+    pub fn matches(&self, version: &Version) -> bool {
+        let author_matches = self.author.as_ref().is_none_or(|text| version.author.as_ref().is_some_and(|a| a.matches(text)));
+        author_matches && self.properties.iter().all(|p| p.matches(version))
+    }
+}
+
+/// Either `key`, matching versions where the property is set, or `key=value`, matching versions where it has exactly that value.
+pub struct PropertyFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl PropertyFilter {
+    pub fn parse(text: &str) -> Self {
+        match text.split_once('=') {
+            Some((key, value)) => PropertyFilter {
+                key: key.to_string(),
+                value: Some(value.to_string()),
+            },
+            None => PropertyFilter {
+                key: text.to_string(),
+                value: None,
+            },
+        }
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        version.properties.get(&self.key).is_some_and(|v| self.value.as_ref().is_none_or(|value| v == value))
+    }
+}
+
+/// Forward storage keeps patches against parents. Reverse storage keeps branch leaves in full and patches ancestors against their children.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StorageMode {
+    #[default]
+    Forward,
+    Reverse,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Head {
     Branch(String),
     Version(VersionId),
@@ -118,7 +547,7 @@ impl Head {
     }
 }
 
-#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum ContentBlobKind {
     Full,
     Patch,
@@ -133,6 +562,23 @@ impl ContentBlobKind {
     }
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentBlobCodec {
+    Raw,
+    Zstd,
+    Xdelta3,
+    Bsdiff,
+}
+
+impl ContentBlobCodec {
+    pub fn kind(&self) -> ContentBlobKind {
+        match self {
+            ContentBlobCodec::Raw | ContentBlobCodec::Zstd => ContentBlobKind::Full,
+            ContentBlobCodec::Xdelta3 | ContentBlobCodec::Bsdiff => ContentBlobKind::Patch,
+        }
+    }
+}
+
 pub struct VersionAndAncestors<'a> {
     repository_data: &'a RepositoryData,
     current_version: Option<&'a Version>,
@@ -151,3 +597,22 @@ impl<'a> Iterator for VersionAndAncestors<'a> {
         }
     }
 }
+
+pub struct BlobChain<'a> {
+    repository_data: &'a RepositoryData,
+    current_version: Option<&'a Version>,
+}
+
+impl<'a> Iterator for BlobChain<'a> {
+    type Item = &'a Version;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.current_version {
+            None => None,
+            Some(version) => {
+                self.current_version = version.patch_base().and_then(|base_id| self.repository_data.version(base_id));
+                Some(version)
+            }
+        }
+    }
+}
@@ -1,15 +1,26 @@
+use crate::data_migration::MigrationResult;
 use crate::env::Env;
-use crate::repository_data::{ContentBlobKind, RepositoryData};
+use crate::repository_data::{ContentBlobCodec, RepositoryData};
 use crate::repository_paths::RepositoryPaths;
 use crate::version_id::VersionId;
-use crate::{image_magick, temp_file, xdelta3};
+use crate::{config, data_migration, delta_codec, image_magick, operation_log, repository_lock};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::time::{Duration, SystemTime};
 use std::{fs, io};
 
+const DATA_BACKUP_FILE_NAMES: [&str; 5] = ["data_backup1.json", "data_backup2.json", "data_backup3.json", "data_backup4.json", "data_backup5.json"];
+const JOURNAL_FILE_NAME: &str = "journal.json";
+
 pub enum RepositoryDataResult {
-    Initialized(RepositoryData),
+    Initialized(Box<RepositoryData>),
     NotInitialized,
+    NewerFormatVersion(u32),
 }
 
 pub fn read_data(repository_paths: &RepositoryPaths) -> io::Result<RepositoryDataResult> {
@@ -18,21 +29,44 @@ pub fn read_data(repository_paths: &RepositoryPaths) -> io::Result<RepositoryDat
     }
 
     let data_file_contents = fs::read(&repository_paths.data_file)?;
-    let repository_data = serde_json::from_slice(&data_file_contents)?;
 
-    Ok(RepositoryDataResult::Initialized(repository_data))
+    parse_data(&data_file_contents)
+}
+
+#[derive(Deserialize)]
+struct DataFormatVersion {
+    format_version: Option<u32>,
+}
+
+/// Repository data in an older format is migrated to the current one. It is written in the current format the next time it is written.
+pub fn parse_data(data_file_contents: &[u8]) -> io::Result<RepositoryDataResult> {
+    // Going through a JSON value for the migrations is slow for large repositories, so data already in the current format skips it.
+    let data_format_version: DataFormatVersion = serde_json::from_slice(data_file_contents)?;
+    if data_format_version.format_version == Some(data_migration::CURRENT_FORMAT_VERSION) {
+        return Ok(RepositoryDataResult::Initialized(Box::new(serde_json::from_slice(data_file_contents)?)));
+    }
+
+    let mut data: Value = serde_json::from_slice(data_file_contents)?;
+
+    if let MigrationResult::NewerFormatVersion(format_version) = data_migration::migrate(&mut data)? {
+        return Ok(RepositoryDataResult::NewerFormatVersion(format_version));
+    }
+
+    Ok(RepositoryDataResult::Initialized(Box::new(serde_json::from_value(data)?)))
+}
+
+/// The changes are appended to the operation log under the name of the operation.
+pub fn write_data(paths: &RepositoryPaths, data: &mut RepositoryData, operation: &str) -> io::Result<()> {
+    write_logged_data(paths, data, operation, None)
 }
 
-pub fn write_data(paths: &RepositoryPaths, data: &RepositoryData) -> io::Result<()> {
+/// Like write_data, for undo and redo, whose log entries refer to the entry they undo or redo.
+pub fn write_logged_data(paths: &RepositoryPaths, data: &mut RepositoryData, operation: &str, applied_entry: Option<u64>) -> io::Result<()> {
     if !data.valid() {
         panic!("Repository data is not valid: {:#?}", data);
     }
 
-    let backup1 = paths.file_path("data_backup1.json");
-    let backup2 = paths.file_path("data_backup2.json");
-    let backup3 = paths.file_path("data_backup3.json");
-    let backup4 = paths.file_path("data_backup4.json");
-    let backup5 = paths.file_path("data_backup5.json");
+    let [backup1, backup2, backup3, backup4, backup5] = DATA_BACKUP_FILE_NAMES.map(|n| paths.file_path(n));
 
     rotate_backup(&backup4, &backup5, Duration::from_hours(24))?;
     rotate_backup(&backup3, &backup4, Duration::from_hours(5))?;
@@ -40,53 +74,201 @@ pub fn write_data(paths: &RepositoryPaths, data: &RepositoryData) -> io::Result<
     rotate_backup(&backup1, &backup2, Duration::from_mins(5))?;
     rotate_backup(&paths.data_file, &backup1, Duration::from_secs(10))?;
 
-    let data_file_content = serde_json::to_string_pretty(data)?;
-    fs::write(&paths.data_file, data_file_content)?;
+    let data_file_content = serde_json::to_vec(data)?;
+    write_atomically(&paths.data_file, &data_file_content)?;
+
+    let changes = data.take_changes();
+    let last_log_entry = operation_log::read_last_entry(paths)?;
+
+    if let Some(log_entry) = operation_log::new_entry(last_log_entry.as_ref(), operation, applied_entry, changes, data) {
+        operation_log::append_entry(paths, &log_entry)?;
+    }
 
     Ok(())
 }
 
-pub fn store_version_content_patch(env: &Env, base_blob_file_path: &Path, content_to_store_path: &Path, patch_blob_file_path: &Path) -> io::Result<()> {
-    xdelta3::create_patch(env, &base_blob_file_path, content_to_store_path, &patch_blob_file_path)?;
+pub struct DataBackup {
+    pub number: usize,
+    pub modified_time: DateTime<Utc>,
+    /// None if the backup cannot be parsed.
+    pub data: Option<RepositoryData>,
+}
 
-    Ok(())
+/// Backups are numbered from 1 (the most recent) to 5 (the oldest). Backups that do not exist are skipped.
+pub fn read_data_backups(paths: &RepositoryPaths) -> io::Result<Vec<DataBackup>> {
+    let mut backups = Vec::new();
+
+    for number in 1..=DATA_BACKUP_FILE_NAMES.len() {
+        if let Some(backup) = read_data_backup(paths, number)? {
+            backups.push(backup);
+        }
+    }
+
+    Ok(backups)
 }
 
-pub fn store_version_content_full(content_to_store_path: &Path, full_blob_file_path: &Path) -> io::Result<()> {
-    fs::copy(content_to_store_path, full_blob_file_path)?;
+pub fn read_data_backup(paths: &RepositoryPaths, number: usize) -> io::Result<Option<DataBackup>> {
+    let Some(file_name) = number.checked_sub(1).and_then(|index| DATA_BACKUP_FILE_NAMES.get(index)) else {
+        return Ok(None);
+    };
 
-    Ok(())
+    let backup_file_path = paths.file_path(file_name);
+
+    if !backup_file_path.exists() {
+        return Ok(None);
+    }
+
+    let modified_time = fs::metadata(&backup_file_path)?.modified()?.into();
+    let data = match parse_data(&fs::read(&backup_file_path)?) {
+        Ok(RepositoryDataResult::Initialized(data)) => Some(*data),
+        _ => None,
+    };
+
+    Ok(Some(DataBackup { number, modified_time, data }))
 }
 
-pub fn extract_version_content(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, version_id: VersionId, destination_path: &Path) -> io::Result<()> {
-    let mut chain = vec![];
+/// Files in the repository directory that hold repository metadata rather than version blobs.
+pub fn is_metadata_file(paths: &RepositoryPaths, file_name: &str) -> bool {
+    paths.data_file.file_name().is_some_and(|n| n == file_name)
+        || DATA_BACKUP_FILE_NAMES.contains(&file_name)
+        || file_name == JOURNAL_FILE_NAME
+        || file_name == operation_log::OPERATION_LOG_FILE_NAME
+        || file_name == repository_lock::LOCK_FILE_NAME
+        || file_name == repository_lock::TAKEOVER_FILE_NAME
+        || file_name == config::CONFIG_FILE_NAME
+}
+
+/// Records the files an operation creates and makes obsolete, so that an operation interrupted before or after writing the data file
+/// can be rolled back or completed by the next biver invocation.
+pub struct Journal<'a> {
+    paths: &'a RepositoryPaths,
+    entry: JournalEntry,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JournalEntry {
+    operation: String,
+    created_files: Vec<String>,
+    obsolete_files: Vec<String>,
+}
+
+pub fn begin_operation<'a>(paths: &'a RepositoryPaths, operation: &str) -> io::Result<Journal<'a>> {
+    let journal = Journal {
+        paths,
+        entry: JournalEntry {
+            operation: operation.to_string(),
+            created_files: Vec::new(),
+            obsolete_files: Vec::new(),
+        },
+    };
+
+    journal.write()?;
+
+    Ok(journal)
+}
+
+impl Journal<'_> {
+    /// Must be called before the file is written.
+    pub fn record_created_file(&mut self, file_name: &str) -> io::Result<()> {
+        self.entry.created_files.push(file_name.to_string());
+        self.write()
+    }
+
+    pub fn record_created_files(&mut self, file_names: &[&String]) -> io::Result<()> {
+        self.entry.created_files.extend(file_names.iter().map(|n| n.to_string()));
+        self.write()
+    }
+
+    /// The file is deleted once the operation finishes, unless the data file still references it
+    /// or the operation log keeps it for undoing the operation. Such files are left for gc.
+    pub fn record_obsolete_file(&mut self, file_name: &str) -> io::Result<()> {
+        self.entry.obsolete_files.push(file_name.to_string());
+        self.write()
+    }
+
+    /// Deletes the recorded files that the data file does not reference and closes the journal.
+    /// Called with the data as it was before the operation, it rolls the operation back.
+    pub fn finish(self, data: &RepositoryData) -> io::Result<()> {
+        remove_unreferenced_journal_files(self.paths, &self.entry, Some(data))
+    }
+
+    fn write(&self) -> io::Result<()> {
+        let journal_content = serde_json::to_string_pretty(&self.entry)?;
+        write_atomically(&self.paths.file_path(JOURNAL_FILE_NAME), journal_content.as_bytes())
+    }
+}
+
+/// Whether the data file was written or not, deleting the journal's files that it does not reference
+/// either completes or rolls back the interrupted operation. Must only be called while holding the repository lock.
+pub fn recover_interrupted_operation(paths: &RepositoryPaths) -> io::Result<()> {
+    let journal_file_path = paths.file_path(JOURNAL_FILE_NAME);
 
-    for version in repo_data.iter_version_and_ancestors(version_id) {
-        chain.push(version);
-        if version.content_blob_kind.is_full() {
-            break;
+    if !journal_file_path.exists() {
+        return Ok(());
+    }
+
+    let entry: JournalEntry = serde_json::from_slice(&fs::read(&journal_file_path)?)?;
+
+    let data = match read_data(paths)? {
+        RepositoryDataResult::Initialized(data) => Some(*data),
+        RepositoryDataResult::NotInitialized => None,
+        // Left for a biver version that understands the data.
+        RepositoryDataResult::NewerFormatVersion(_) => return Ok(()),
+    };
+
+    remove_unreferenced_journal_files(paths, &entry, data.as_ref())
+}
+
+/// Files of the last operation log entry are kept, which covers the files the finished operation made obsolete.
+fn remove_unreferenced_journal_files(paths: &RepositoryPaths, entry: &JournalEntry, data: Option<&RepositoryData>) -> io::Result<()> {
+    let last_log_entry = operation_log::read_last_entry(paths)?;
+
+    let referenced_file_names: HashSet<&str> = data
+        .iter()
+        .flat_map(|d| d.versions().iter())
+        .flat_map(|v| [Some(v.content_blob_file_name.as_str()), v.preview_blob_file_name.as_deref()])
+        .flatten()
+        .chain(last_log_entry.iter().flat_map(|e| e.referenced_file_names()))
+        .collect();
+
+    for file_name in entry.created_files.iter().chain(entry.obsolete_files.iter()) {
+        let file_path = paths.file_path(file_name);
+        if !referenced_file_names.contains(file_name.as_str()) && file_path.exists() {
+            fs::remove_file(&file_path)?;
         }
     }
 
+    fs::remove_file(paths.file_path(JOURNAL_FILE_NAME))
+}
+
+pub fn store_version_content(env: &Env, codec: ContentBlobCodec, base_content: &[u8], content_to_store: &[u8], blob_file_path: &Path) -> io::Result<u64> {
+    let blob = delta_codec::codec(env, codec).encode(base_content, content_to_store)?;
+    write_atomically(blob_file_path, &blob)?;
+
+    Ok(blob.len() as u64)
+}
+
+pub fn extract_version_content(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, version_id: VersionId, destination_path: &Path) -> io::Result<()> {
+    let content = read_version_content(env, repo_paths, repo_data, version_id)?;
+    fs::write(destination_path, content)?;
+
+    Ok(())
+}
+
+pub fn read_version_content(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, version_id: VersionId) -> io::Result<Vec<u8>> {
+    let mut chain: Vec<_> = repo_data.iter_blob_chain(version_id).collect();
     chain.reverse();
 
-    let temp_file_path = temp_file::path();
+    let mut content = Vec::new();
 
     for version in chain {
         let blob_file_path = repo_paths.file_path(&version.content_blob_file_name);
+        let blob = fs::read(&blob_file_path)?;
 
-        match version.content_blob_kind {
-            ContentBlobKind::Full => {
-                fs::copy(&blob_file_path, destination_path)?;
-            }
-            ContentBlobKind::Patch => {
-                xdelta3::apply_patch(env, destination_path, &blob_file_path, &temp_file_path)?;
-                fs::rename(&temp_file_path, destination_path)?;
-            }
-        }
+        content = delta_codec::codec(env, version.content_blob_codec).decode(&content, &blob)?;
     }
 
-    Ok(())
+    Ok(content)
 }
 
 pub fn store_version_preview(env: &Env, preview_blob_file_path: &Path, content_to_store_path: &Path) -> io::Result<()> {
@@ -95,6 +277,69 @@ pub fn store_version_preview(env: &Env, preview_blob_file_path: &Path, content_t
     Ok(())
 }
 
+/// Writes through a temporary file in the same directory, so that a crash never leaves a partially written file under the final name.
+pub fn write_atomically(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut temp_file_name = path.file_name().expect("File path must have a file name").to_os_string();
+    temp_file_name.push(".tmp");
+    let temp_file_path = path.with_file_name(temp_file_name);
+
+    let mut temp_file = File::create(&temp_file_path)?;
+    temp_file.write_all(content)?;
+    temp_file.sync_all()?;
+
+    fs::rename(&temp_file_path, path)?;
+    sync_parent_dir(path)
+}
+
+/// Makes a rename in the directory durable. The journal relies on renamed files surviving a crash.
+#[cfg(unix)]
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+/// Directories cannot be opened as files to be synced on Windows.
+#[cfg(windows)]
+fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Falls back to copying and deleting when the destination is on another file system.
+pub fn move_path(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to) {
+        Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+            copy_recursively(from, to)?;
+            if from.is_dir() { fs::remove_dir_all(from) } else { fs::remove_file(from) }
+        }
+        result => result,
+    }
+}
+
+/// Falls back to copying where hard links are not supported, for example across file systems.
+pub fn hard_link_or_copy(from: &Path, to: &Path) -> io::Result<()> {
+    if fs::hard_link(from, to).is_ok() {
+        return Ok(());
+    }
+
+    fs::copy(from, to).map(|_| ())
+}
+
+fn copy_recursively(from: &Path, to: &Path) -> io::Result<()> {
+    if !from.is_dir() {
+        return fs::copy(from, to).map(|_| ());
+    }
+
+    fs::create_dir(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        copy_recursively(&entry.path(), &to.join(entry.file_name()))?;
+    }
+
+    Ok(())
+}
+
 fn rotate_backup(previous: &Path, next: &Path, interval: Duration) -> io::Result<()> {
     if !previous.exists() {
         return Ok(());
@@ -0,0 +1,292 @@
+use crate::hostname::hostname;
+use crate::repository_paths::RepositoryPaths;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+use std::{fs, io, process};
+use uuid::Uuid;
+
+pub const LOCK_FILE_NAME: &str = "lock.json";
+
+/// Held while a stale lock file is removed, so that only one process at a time can remove it.
+pub const TAKEOVER_FILE_NAME: &str = "lock_takeover";
+
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A lock file that cannot be read is assumed to be in the middle of being written, unless it is older than this.
+/// A takeover file this old was left behind by a process that stopped while removing a stale lock.
+const UNREADABLE_LOCK_FILE_MAX_AGE: Duration = Duration::from_secs(10);
+
+#[derive(Serialize, Deserialize)]
+pub struct LockOwner {
+    pub pid: u32,
+    pub hostname: Option<String>,
+    pub since: DateTime<Utc>,
+    /// Identifies the lock, so that its owner never removes a lock file that another process created.
+    #[serde(default)]
+    pub token: Uuid,
+}
+
+/// An advisory lock on the repository directory. Released when dropped.
+pub struct RepositoryLock {
+    lock_file_path: PathBuf,
+    token: Uuid,
+}
+
+impl RepositoryLock {
+    /// Follows the lock file after the repository directory has been moved.
+    pub fn relocate(&mut self, paths: &RepositoryPaths) {
+        self.lock_file_path = paths.file_path(LOCK_FILE_NAME);
+    }
+}
+
+impl Drop for RepositoryLock {
+    fn drop(&mut self) {
+        let owner = read_lock_file(&self.lock_file_path)
+            .ok()
+            .flatten()
+            .and_then(|c| serde_json::from_slice::<LockOwner>(&c).ok());
+
+        if owner.is_some_and(|o| o.token == self.token) {
+            let _ = fs::remove_file(&self.lock_file_path);
+        }
+    }
+}
+
+pub enum LockResult {
+    Acquired(RepositoryLock),
+    Locked(Option<LockOwner>),
+}
+
+/// Waits up to the timeout for the lock to be released. Locks left behind by processes that are no longer running on this host are removed.
+pub fn acquire(paths: &RepositoryPaths, timeout: Duration) -> io::Result<LockResult> {
+    let lock_file_path = paths.file_path(LOCK_FILE_NAME);
+    let takeover_file_path = paths.file_path(TAKEOVER_FILE_NAME);
+    let start = Instant::now();
+    let hostname = hostname();
+
+    loop {
+        let token = Uuid::new_v4();
+        match create_lock_file(&lock_file_path, hostname.clone(), token) {
+            Ok(()) => return Ok(LockResult::Acquired(RepositoryLock { lock_file_path, token })),
+            Err(error) if error.kind() == ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error),
+        }
+
+        let content = read_lock_file(&lock_file_path)?;
+        let owner = content.as_deref().and_then(|c| serde_json::from_slice::<LockOwner>(c).ok());
+
+        let stale = match &owner {
+            Some(owner) => owner.hostname == hostname && !process_running(owner.pid),
+            None => content.is_some() && lock_file_age(&lock_file_path)? > UNREADABLE_LOCK_FILE_MAX_AGE,
+        };
+
+        if let Some(content) = content.filter(|_| stale)
+            && remove_stale_lock_file(&lock_file_path, &takeover_file_path, &content)?
+        {
+            continue;
+        }
+
+        if start.elapsed() >= timeout {
+            return Ok(LockResult::Locked(owner));
+        }
+
+        thread::sleep(RETRY_INTERVAL);
+    }
+}
+
+fn create_lock_file(lock_file_path: &Path, hostname: Option<String>, token: Uuid) -> io::Result<()> {
+    let mut lock_file = OpenOptions::new().write(true).create_new(true).open(lock_file_path)?;
+
+    let owner = LockOwner {
+        pid: process::id(),
+        hostname,
+        since: Utc::now(),
+        token,
+    };
+
+    lock_file.write_all(serde_json::to_string_pretty(&owner)?.as_bytes())?;
+    lock_file.sync_all()
+}
+
+/// None if there is no lock file.
+fn read_lock_file(lock_file_path: &Path) -> io::Result<Option<Vec<u8>>> {
+    match fs::read(lock_file_path) {
+        Ok(content) => Ok(Some(content)),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+fn lock_file_age(lock_file_path: &Path) -> io::Result<Duration> {
+    match fs::metadata(lock_file_path) {
+        Ok(metadata) => Ok(SystemTime::now().duration_since(metadata.modified()?).unwrap_or_default()),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(Duration::ZERO),
+        Err(error) => Err(error),
+    }
+}
+
+/// Lock files are only created when there is none and only removed by their owner or while holding the takeover file,
+/// so the lock file cannot change between checking that it still has the stale content and removing it.
+/// Returns false without removing anything if another process is taking over the lock or already has.
+fn remove_stale_lock_file(lock_file_path: &Path, takeover_file_path: &Path, stale_content: &[u8]) -> io::Result<bool> {
+    match OpenOptions::new().write(true).create_new(true).open(takeover_file_path) {
+        Ok(_) => {}
+        Err(error) if error.kind() == ErrorKind::AlreadyExists => {
+            if lock_file_age(takeover_file_path)? > UNREADABLE_LOCK_FILE_MAX_AGE {
+                remove_file_if_exists(takeover_file_path)?;
+            }
+            return Ok(false);
+        }
+        Err(error) => return Err(error),
+    }
+
+    let still_stale = read_lock_file(lock_file_path)?.is_some_and(|c| c == stale_content);
+    let result = if still_stale { remove_file_if_exists(lock_file_path) } else { Ok(()) };
+
+    remove_file_if_exists(takeover_file_path)?;
+    result.map(|_| still_stale)
+}
+
+fn remove_file_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+/// Errs on the side of reporting the process as running when its status cannot be determined.
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    let proc_dir = Path::new("/proc");
+    if proc_dir.is_dir() {
+        return proc_dir.join(pid.to_string()).exists();
+    }
+
+    let status = Command::new("ps").arg("-p").arg(pid.to_string()).stdout(Stdio::null()).stderr(Stdio::null()).status();
+    status.map(|s| s.success()).unwrap_or(true)
+}
+
+/// Errs on the side of reporting the process as running when its status cannot be determined.
+#[cfg(windows)]
+fn process_running(pid: u32) -> bool {
+    let output = Command::new("tasklist").arg("/FI").arg(format!("PID eq {}", pid)).arg("/NH").stderr(Stdio::null()).output();
+
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout).split_whitespace().any(|word| word == pid.to_string()),
+        Err(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_file;
+    use std::sync::{Arc, Barrier};
+
+    #[test]
+    fn only_one_of_two_racing_acquirers_takes_over_a_stale_lock() {
+        for _ in 0..20 {
+            let versioned_file_path = temp_file::path();
+            let paths = RepositoryPaths::from_versioned_file_path(versioned_file_path).unwrap();
+            fs::create_dir_all(&paths.repository_dir).unwrap();
+
+            let stale_owner = LockOwner {
+                pid: u32::MAX,
+                hostname: hostname(),
+                since: Utc::now(),
+                token: Uuid::new_v4(),
+            };
+            fs::write(paths.file_path(LOCK_FILE_NAME), serde_json::to_string_pretty(&stale_owner).unwrap()).unwrap();
+
+            let paths = Arc::new(paths);
+            let barrier = Arc::new(Barrier::new(2));
+
+            let acquirers: Vec<_> = (0..2)
+                .map(|_| {
+                    let paths = Arc::clone(&paths);
+                    let barrier = Arc::clone(&barrier);
+                    thread::spawn(move || {
+                        barrier.wait();
+                        acquire(&paths, Duration::from_millis(50)).unwrap()
+                    })
+                })
+                .collect();
+
+            let results: Vec<_> = acquirers.into_iter().map(|a| a.join().unwrap()).collect();
+            let acquired_count = results.iter().filter(|r| matches!(r, LockResult::Acquired(_))).count();
+
+            assert_eq!(acquired_count, 1);
+
+            drop(results);
+            fs::remove_dir_all(&paths.repository_dir).unwrap();
+        }
+    }
+
+    fn new_repository_dir() -> RepositoryPaths {
+        let paths = RepositoryPaths::from_versioned_file_path(temp_file::path()).unwrap();
+        fs::create_dir_all(&paths.repository_dir).unwrap();
+        paths
+    }
+
+    fn write_lock_file(paths: &RepositoryPaths, pid: u32) {
+        let owner = LockOwner {
+            pid,
+            hostname: hostname(),
+            since: Utc::now(),
+            token: Uuid::new_v4(),
+        };
+        fs::write(paths.file_path(LOCK_FILE_NAME), serde_json::to_string_pretty(&owner).unwrap()).unwrap();
+    }
+
+    #[test]
+    fn dropping_a_lock_leaves_a_lock_file_created_by_another_process() {
+        let paths = new_repository_dir();
+
+        let LockResult::Acquired(lock) = acquire(&paths, Duration::ZERO).unwrap() else {
+            panic!("Lock must be acquired");
+        };
+        write_lock_file(&paths, process::id());
+        drop(lock);
+
+        assert!(paths.file_path(LOCK_FILE_NAME).exists());
+
+        fs::remove_dir_all(&paths.repository_dir).unwrap();
+    }
+
+    #[test]
+    fn dropping_a_lock_removes_its_lock_file() {
+        let paths = new_repository_dir();
+
+        let LockResult::Acquired(lock) = acquire(&paths, Duration::ZERO).unwrap() else {
+            panic!("Lock must be acquired");
+        };
+        assert!(matches!(acquire(&paths, Duration::ZERO).unwrap(), LockResult::Locked(Some(_))));
+        drop(lock);
+
+        assert!(!paths.file_path(LOCK_FILE_NAME).exists());
+        assert!(matches!(acquire(&paths, Duration::ZERO).unwrap(), LockResult::Acquired(_)));
+
+        fs::remove_dir_all(&paths.repository_dir).unwrap();
+    }
+
+    #[test]
+    fn stale_lock_is_not_taken_over_while_another_process_takes_it_over() {
+        let paths = new_repository_dir();
+        write_lock_file(&paths, u32::MAX);
+        fs::write(paths.file_path(TAKEOVER_FILE_NAME), "").unwrap();
+
+        assert!(matches!(acquire(&paths, Duration::from_millis(50)).unwrap(), LockResult::Locked(Some(_))));
+
+        fs::remove_file(paths.file_path(TAKEOVER_FILE_NAME)).unwrap();
+
+        assert!(matches!(acquire(&paths, Duration::from_millis(50)).unwrap(), LockResult::Acquired(_)));
+
+        fs::remove_dir_all(&paths.repository_dir).unwrap();
+    }
+}
@@ -1,41 +1,39 @@
 use crate::biver_result::BiverResult;
+use crate::bundle::{Bundle, BundleFileSource, OpenBundleResult};
 use crate::env::Env;
 use crate::extensions::CountIsAtLeast;
-use crate::repository_data::{ContentBlobKind, Head, RepositoryData, Version};
-use crate::repository_paths::RepositoryPaths;
+use crate::operation_log::OperationLogEntry;
+use crate::repository_data::{ContentBlobCodec, ContentBlobKind, Head, Remote, RepositoryData, RepositoryState, StorageMode, Version};
+use crate::repository_io::{Journal, RepositoryDataResult};
+use crate::repository_paths::{RepositoryPaths, sibling_path};
 use crate::version_id::VersionId;
-use crate::{hash, image_magick, known_file_types, nickname, repository_io, temp_file};
+use crate::{author, bundle, data_migration, delta_codec, hash, image_magick, known_file_types, nickname, operation_log, repository_io, repository_paths};
 use chrono::Utc;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
-const DEFAULT_BRANCH: &str = "main";
-
 pub enum InitResult {
     Ok,
     AlreadyInitialized,
     InvalidBranchName,
 }
 
-pub fn init(env: &Env, repo_paths: &RepositoryPaths, branch: Option<&str>, description: Option<&str>) -> BiverResult<InitResult> {
+pub fn init(env: &Env, repo_paths: &RepositoryPaths, branch: Option<&str>, storage_mode: StorageMode, description: Option<&str>) -> BiverResult<InitResult> {
     if fs::exists(&repo_paths.data_file)? {
         return Ok(InitResult::AlreadyInitialized);
     }
 
-    if !fs::exists(&repo_paths.repository_dir)? {
-        fs::create_dir(&repo_paths.repository_dir)?;
-    }
-
     let versioned_file = File::open(&repo_paths.versioned_file)?;
     let versioned_file_xxh3_128 = hash::xxh3_128(&versioned_file)?;
     let versioned_file_length = fs::metadata(&repo_paths.versioned_file)?.len();
 
     let new_version_id = VersionId::new();
 
-    let branch = branch.unwrap_or(DEFAULT_BRANCH);
+    let branch = branch.unwrap_or(&env.default_branch);
 
     if !valid_branch_name(branch) {
         return Ok(InitResult::InvalidBranchName);
@@ -47,6 +45,9 @@ pub fn init(env: &Env, repo_paths: &RepositoryPaths, branch: Option<&str>, descr
     let preview_blob_file_name = preview_blob_file_name(env, repo_paths, new_version_id);
     let preview_blob_file_path = preview_blob_file_name.as_ref().map(|n| repo_paths.file_path(n));
 
+    let mut journal = repository_io::begin_operation(repo_paths, "init")?;
+    record_created_version_files(&mut journal, &content_blob_file_name, preview_blob_file_name.as_deref())?;
+
     let new_version = Version {
         id: new_version_id,
         creation_time: Utc::now(),
@@ -54,23 +55,31 @@ pub fn init(env: &Env, repo_paths: &RepositoryPaths, branch: Option<&str>, descr
         versioned_file_length,
         versioned_file_xxh3_128,
         description: description.unwrap_or_default().to_string(),
+        author: author::current_author(env),
+        properties: BTreeMap::new(),
         parent: None,
         content_blob_file_name,
-        content_blob_kind: ContentBlobKind::Full,
+        content_blob_codec: env.full_codec,
+        content_blob_base: None,
         preview_blob_file_name,
     };
 
-    let repo_data = RepositoryData {
-        head: Head::Branch(branch.to_string()),
-        branches: HashMap::from([(branch.to_string(), new_version_id)]),
-        versions: vec![new_version],
-    };
+    let mut repo_data = RepositoryData::new(
+        data_migration::CURRENT_FORMAT_VERSION,
+        storage_mode,
+        Head::Branch(branch.to_string()),
+        HashMap::from([(branch.to_string(), new_version_id)]),
+        vec![new_version],
+    );
 
     if let Some(preview_blob_file_path) = preview_blob_file_path {
         repository_io::store_version_preview(env, &preview_blob_file_path, &repo_paths.versioned_file)?;
     }
-    repository_io::store_version_content_full(&repo_paths.versioned_file, &content_blob_file_path)?;
-    repository_io::write_data(repo_paths, &repo_data)?;
+    let versioned_file_content = fs::read(&repo_paths.versioned_file)?;
+    repository_io::store_version_content(env, env.full_codec, &[], &versioned_file_content, &content_blob_file_path)?;
+    repository_io::write_data(repo_paths, &mut repo_data, "init")?;
+
+    journal.finish(&repo_data)?;
 
     Ok(InitResult::Ok)
 }
@@ -93,7 +102,7 @@ pub fn commit_version(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut R
         return Ok(CommitResult::NothingToCommit);
     }
 
-    let Some(branch) = repo_data.head.branch() else {
+    let Some(branch) = repo_data.head.branch().map(str::to_string) else {
         return Ok(CommitResult::HeadMustBeOnBranch);
     };
 
@@ -102,24 +111,31 @@ pub fn commit_version(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut R
     let content_blob_file_name = content_blob_file_name(new_version_id);
     let content_blob_file_path = repo_paths.file_path(&content_blob_file_name);
 
-    let parent_version_file_path = temp_file::path();
-    repository_io::extract_version_content(env, repo_paths, repo_data, parent_id, &parent_version_file_path)?;
-    repository_io::store_version_content_patch(env, &parent_version_file_path, &repo_paths.versioned_file, &content_blob_file_path)?;
-    fs::remove_file(&parent_version_file_path)?;
+    let preview_blob_file_name = preview_blob_file_name(env, repo_paths, new_version_id);
+    let preview_blob_file_path = preview_blob_file_name.as_ref().map(|n| repo_paths.file_path(n));
 
-    let patch_length = fs::metadata(&content_blob_file_path)?.len();
+    let mut journal = repository_io::begin_operation(repo_paths, "commit")?;
+    record_created_version_files(&mut journal, &content_blob_file_name, preview_blob_file_name.as_deref())?;
 
-    let content_blob_kind;
+    let parent_content = repository_io::read_version_content(env, repo_paths, repo_data, parent_id)?;
+    let versioned_file_content = fs::read(&repo_paths.versioned_file)?;
 
-    if should_convert_patch_to_full(repo_paths, repo_data, parent_id, patch_length)? {
-        content_blob_kind = ContentBlobKind::Full;
-        repository_io::store_version_content_full(&repo_paths.versioned_file, &content_blob_file_path)?;
-    } else {
-        content_blob_kind = ContentBlobKind::Patch;
-    }
+    let content_blob_codec = match repo_data.storage_mode {
+        StorageMode::Forward => {
+            let patch_length = repository_io::store_version_content(env, env.patch_codec, &parent_content, &versioned_file_content, &content_blob_file_path)?;
 
-    let preview_blob_file_name = preview_blob_file_name(env, repo_paths, new_version_id);
-    let preview_blob_file_path = preview_blob_file_name.as_ref().map(|n| repo_paths.file_path(n));
+            if should_convert_patch_to_full(env, repo_paths, repo_data, parent_id, versioned_file_content.len() as u64, patch_length)? {
+                repository_io::store_version_content(env, env.full_codec, &[], &versioned_file_content, &content_blob_file_path)?;
+                env.full_codec
+            } else {
+                env.patch_codec
+            }
+        }
+        StorageMode::Reverse => {
+            repository_io::store_version_content(env, env.full_codec, &[], &versioned_file_content, &content_blob_file_path)?;
+            env.full_codec
+        }
+    };
 
     let new_version = Version {
         id: new_version_id,
@@ -128,42 +144,185 @@ pub fn commit_version(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut R
         versioned_file_length,
         versioned_file_xxh3_128,
         description: description.unwrap_or_default().to_string(),
+        author: author::current_author(env),
+        properties: BTreeMap::new(),
         parent: Some(parent_id),
         content_blob_file_name,
-        content_blob_kind,
+        content_blob_codec,
+        content_blob_base: None,
         preview_blob_file_name,
     };
 
-    repo_data.versions.push(new_version);
-    repo_data.branches.insert(branch.to_string(), new_version_id);
+    repo_data.add_version(new_version);
+    repo_data.branches.insert(branch, new_version_id);
 
-    match content_blob_kind {
-        ContentBlobKind::Full => {
-            repository_io::store_version_content_full(&repo_paths.versioned_file, &content_blob_file_path)?;
-        }
-        ContentBlobKind::Patch => {
-            let parent_version_file_path = temp_file::path();
-            repository_io::extract_version_content(env, repo_paths, repo_data, parent_id, &parent_version_file_path)?;
-            repository_io::store_version_content_patch(env, &parent_version_file_path, &repo_paths.versioned_file, &content_blob_file_path)?;
-            fs::remove_file(&parent_version_file_path)?;
-        }
+    if repo_data.storage_mode == StorageMode::Reverse {
+        store_parent_as_reverse_patch(env, repo_paths, repo_data, &mut journal, new_version_id, &versioned_file_content, &parent_content)?;
     }
 
     if let Some(preview_blob_file_path) = preview_blob_file_path {
         repository_io::store_version_preview(env, &preview_blob_file_path, &repo_paths.versioned_file)?;
     }
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "commit")?;
+
+    journal.finish(repo_data)?;
 
     Ok(CommitResult::Ok)
 }
 
+pub enum CherryPickResult {
+    Ok,
+    UncommittedChanges,
+    HeadMustBeOnBranch,
+    InvalidTarget,
+    SameContentAsHead,
+}
+
+/// Commits the content of the target as a new child of head. The description refers back to the target.
+pub fn cherry_pick(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str) -> BiverResult<CherryPickResult> {
+    if has_uncommitted_changes(repo_paths, repo_data)? {
+        return Ok(CherryPickResult::UncommittedChanges);
+    }
+
+    if repo_data.head.branch().is_none() {
+        return Ok(CherryPickResult::HeadMustBeOnBranch);
+    }
+
+    let Some(source_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(CherryPickResult::InvalidTarget);
+    };
+
+    let source = repo_data.version(source_id).expect("Version resolved from target must exist");
+
+    if source.versioned_file_xxh3_128 == repo_data.head_version().versioned_file_xxh3_128 {
+        return Ok(CherryPickResult::SameContentAsHead);
+    }
+
+    let description = if source.description.is_empty() {
+        format!("Cherry-picked from {}", source_id.bs58())
+    } else {
+        format!("{} (cherry-picked from {})", source.description, source_id.bs58())
+    };
+
+    repository_io::extract_version_content(env, repo_paths, repo_data, source_id, &repo_paths.versioned_file)?;
+
+    match commit_version(env, repo_paths, repo_data, Some(&description))? {
+        CommitResult::Ok => Ok(CherryPickResult::Ok),
+        CommitResult::NothingToCommit | CommitResult::HeadMustBeOnBranch => unreachable!("Checked before extracting the target"),
+    }
+}
+
+pub enum RevertResult {
+    Ok { versioned_file_updated: bool },
+    HeadMustBeOnBranch,
+    InvalidTarget,
+    SameContentAsHead,
+}
+
+/// Commits the exact content of the target as a new child of head. The blobs of the target are hard linked rather than stored again,
+/// except that in reverse storage mode a patch target is stored in full, as the new tip, and head becomes a reverse patch.
+/// The versioned file is only updated when it has no uncommitted changes, which otherwise stay on top of the new version.
+pub fn revert(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str) -> BiverResult<RevertResult> {
+    let Some(branch) = repo_data.head.branch().map(str::to_string) else {
+        return Ok(RevertResult::HeadMustBeOnBranch);
+    };
+
+    let Some(target_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(RevertResult::InvalidTarget);
+    };
+
+    let target_version = repo_data.version(target_id).expect("Version resolved from target must exist");
+    let head_version = repo_data.head_version();
+    let head_id = head_version.id;
+
+    if target_version.versioned_file_xxh3_128 == head_version.versioned_file_xxh3_128 {
+        return Ok(RevertResult::SameContentAsHead);
+    }
+
+    let has_uncommitted_changes = has_uncommitted_changes(repo_paths, repo_data)?;
+
+    let new_version_id = VersionId::new();
+
+    let content_blob_file_name = content_blob_file_name(new_version_id);
+    let preview_blob_file_name = match &target_version.preview_blob_file_name {
+        Some(target_preview_blob_file_name) if fs::exists(repo_paths.file_path(target_preview_blob_file_name))? => Some(preview_blob_file_name_of(new_version_id)),
+        _ => None,
+    };
+
+    let mut journal = repository_io::begin_operation(repo_paths, "revert")?;
+    record_created_version_files(&mut journal, &content_blob_file_name, preview_blob_file_name.as_deref())?;
+
+    // In reverse storage mode head and the target are needed to store head as a reverse patch against the new version.
+    let reverse_patch_contents = match repo_data.storage_mode {
+        StorageMode::Forward => None,
+        StorageMode::Reverse => Some((
+            repository_io::read_version_content(env, repo_paths, repo_data, target_id)?,
+            repository_io::read_version_content(env, repo_paths, repo_data, head_id)?,
+        )),
+    };
+
+    let (content_blob_codec, content_blob_base) = match &reverse_patch_contents {
+        Some((target_content, _)) if target_version.content_blob_kind().is_patch() => {
+            repository_io::store_version_content(env, env.full_codec, &[], target_content, &repo_paths.file_path(&content_blob_file_name))?;
+            (env.full_codec, None)
+        }
+        _ => {
+            repository_io::hard_link_or_copy(
+                &repo_paths.file_path(&target_version.content_blob_file_name),
+                &repo_paths.file_path(&content_blob_file_name),
+            )?;
+            (target_version.content_blob_codec, target_version.patch_base().filter(|base_id| *base_id != head_id))
+        }
+    };
+    if let (Some(target_preview_blob_file_name), Some(preview_blob_file_name)) = (&target_version.preview_blob_file_name, &preview_blob_file_name) {
+        repository_io::hard_link_or_copy(&repo_paths.file_path(target_preview_blob_file_name), &repo_paths.file_path(preview_blob_file_name))?;
+    }
+
+    let new_version = Version {
+        id: new_version_id,
+        creation_time: Utc::now(),
+        nickname: nickname::new_nickname(target_version.versioned_file_xxh3_128),
+        versioned_file_length: target_version.versioned_file_length,
+        versioned_file_xxh3_128: target_version.versioned_file_xxh3_128,
+        description: format!("Revert to {} ({})", target_version.nickname, target_id.bs58()),
+        author: author::current_author(env),
+        properties: BTreeMap::new(),
+        parent: Some(head_id),
+        content_blob_file_name,
+        content_blob_codec,
+        content_blob_base,
+        preview_blob_file_name,
+    };
+
+    repo_data.add_version(new_version);
+    repo_data.branches.insert(branch, new_version_id);
+
+    if let Some((target_content, head_content)) = &reverse_patch_contents {
+        store_parent_as_reverse_patch(env, repo_paths, repo_data, &mut journal, new_version_id, target_content, head_content)?;
+    }
+
+    repository_io::write_data(repo_paths, repo_data, "revert")?;
+
+    journal.finish(repo_data)?;
+
+    if !has_uncommitted_changes {
+        repository_io::extract_version_content(env, repo_paths, repo_data, new_version_id, &repo_paths.versioned_file)?;
+    }
+
+    Ok(RevertResult::Ok {
+        versioned_file_updated: !has_uncommitted_changes,
+    })
+}
+
 pub enum AmendResult {
     Ok,
     NoUncommittedChanges,
     HeadMustBeBranch,
     CannotAmendParent,
     HeadEqualsParent,
+    HeadIsOnOtherBranches,
+    HeadIsTagged,
 }
 
 pub fn amend_head(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, description: Option<&str>) -> BiverResult<AmendResult> {
@@ -179,7 +338,7 @@ pub fn amend_head(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut Repos
         return Ok(AmendResult::NoUncommittedChanges);
     }
 
-    let Some(head_branch) = repo_data.head.branch() else {
+    let Some(head_branch) = repo_data.head.branch().map(str::to_string) else {
         return Ok(AmendResult::HeadMustBeBranch);
     };
 
@@ -187,6 +346,15 @@ pub fn amend_head(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut Repos
         return Ok(AmendResult::CannotAmendParent);
     }
 
+    let head_is_on_other_branches = repo_data.branches.iter().any(|(b, v)| *b != head_branch && *v == head_id) || repo_data.iter_remote_branch_leaves().any(|v| v == head_id);
+    if head_is_on_other_branches {
+        return Ok(AmendResult::HeadIsOnOtherBranches);
+    }
+
+    if repo_data.tags.values().any(|v| *v == head_id) {
+        return Ok(AmendResult::HeadIsTagged);
+    }
+
     if let Some(parent_id) = parent_id
         && repo_data.version(parent_id).unwrap().versioned_file_xxh3_128 == versioned_file_xxh3_128
     {
@@ -195,12 +363,27 @@ pub fn amend_head(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut Repos
 
     let new_version_id = VersionId::new();
 
-    let content_blob_kind = head.content_blob_kind;
-    let content_blob_file_path = repo_paths.file_path(&head.content_blob_file_name);
+    let content_blob_kind = match repo_data.storage_mode {
+        StorageMode::Forward => head.content_blob_kind(),
+        StorageMode::Reverse => ContentBlobKind::Full,
+    };
+    let content_blob_codec = match content_blob_kind {
+        ContentBlobKind::Full => env.full_codec,
+        ContentBlobKind::Patch => env.patch_codec,
+    };
+    let content_blob_file_name = content_blob_file_name(new_version_id);
+    let content_blob_file_path = repo_paths.file_path(&content_blob_file_name);
 
     let preview_blob_file_name = preview_blob_file_name(env, repo_paths, new_version_id);
     let preview_blob_file_path = preview_blob_file_name.as_ref().map(|n| repo_paths.file_path(n));
 
+    let mut journal = repository_io::begin_operation(repo_paths, "amend")?;
+    record_created_version_files(&mut journal, &content_blob_file_name, preview_blob_file_name.as_deref())?;
+    journal.record_obsolete_file(&head.content_blob_file_name)?;
+    if let Some(head_preview_blob_file_name) = &head.preview_blob_file_name {
+        journal.record_obsolete_file(head_preview_blob_file_name)?;
+    }
+
     let description = match description {
         Some(description) => description.to_string(),
         None => head.description.clone(),
@@ -213,34 +396,42 @@ pub fn amend_head(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut Repos
         versioned_file_length,
         versioned_file_xxh3_128,
         description,
+        author: head.author.clone(),
+        properties: head.properties.clone(),
         parent: parent_id,
-        content_blob_file_name: head.content_blob_file_name.clone(),
-        content_blob_kind,
+        content_blob_file_name,
+        content_blob_codec,
+        content_blob_base: None,
         preview_blob_file_name,
     };
 
-    repo_data.branches.insert(head_branch.to_string(), new_version_id);
-    repo_data.versions.retain(|v| v.id != head_id);
-    repo_data.versions.push(new_head);
+    let versioned_file_content = fs::read(&repo_paths.versioned_file)?;
 
     match content_blob_kind {
         ContentBlobKind::Full => {
-            repository_io::store_version_content_full(&repo_paths.versioned_file, &content_blob_file_path)?;
+            repository_io::store_version_content(env, content_blob_codec, &[], &versioned_file_content, &content_blob_file_path)?;
         }
         ContentBlobKind::Patch => {
-            let parent_version_file_path = temp_file::path();
             let parent_id = parent_id.expect("Patch node must have a parent");
-            repository_io::extract_version_content(env, repo_paths, repo_data, parent_id, &parent_version_file_path)?;
-            repository_io::store_version_content_patch(env, &parent_version_file_path, &repo_paths.versioned_file, &content_blob_file_path)?;
-            fs::remove_file(&parent_version_file_path)?;
+            let parent_content = repository_io::read_version_content(env, repo_paths, repo_data, parent_id)?;
+            repository_io::store_version_content(env, content_blob_codec, &parent_content, &versioned_file_content, &content_blob_file_path)?;
         }
     }
 
+    repo_data.add_version(new_head);
+
+    repo_data.branches.insert(head_branch, new_version_id);
+
+    reencode_dependents(env, repo_paths, repo_data, &mut journal, &[head_id], Some((new_version_id, &versioned_file_content)))?;
+    repo_data.remove_versions(&[head_id]);
+
     if let Some(preview_blob_file_path) = preview_blob_file_path {
         repository_io::store_version_preview(env, &preview_blob_file_path, &repo_paths.versioned_file)?;
     }
 
-    repository_io::write_data(repo_paths, &repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "amend")?;
+
+    journal.finish(repo_data)?;
 
     Ok(AmendResult::Ok)
 }
@@ -251,17 +442,61 @@ pub enum RewordResult {
 }
 
 pub fn reword(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str, description: &str) -> BiverResult<RewordResult> {
-    let Some(target_version) = resolve_target_strict_mut(repo_data, target) else {
+    let Some(target_version_id) = resolve_version_id_target(repo_data, target).map(|v| v.id) else {
         return Ok(RewordResult::InvalidTarget);
     };
 
-    target_version.description = description.to_string();
+    repo_data.set_description(target_version_id, description.to_string());
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "reword")?;
 
     Ok(RewordResult::Ok)
 }
 
+pub enum SetPropertyResult {
+    Ok,
+    InvalidKey,
+    InvalidTarget,
+}
+
+pub fn set_property(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, key: &str, value: &str, target: &str) -> BiverResult<SetPropertyResult> {
+    if !valid_property_key(key) {
+        return Ok(SetPropertyResult::InvalidKey);
+    }
+
+    let Some(version_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(SetPropertyResult::InvalidTarget);
+    };
+
+    let properties = repo_data.properties_mut(version_id).expect("Version resolved from target must exist");
+    properties.insert(key.to_string(), value.to_string());
+
+    repository_io::write_data(repo_paths, repo_data, "set property")?;
+
+    Ok(SetPropertyResult::Ok)
+}
+
+pub enum UnsetPropertyResult {
+    Ok,
+    PropertyNotSet,
+    InvalidTarget,
+}
+
+pub fn unset_property(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, key: &str, target: &str) -> BiverResult<UnsetPropertyResult> {
+    let Some(version_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(UnsetPropertyResult::InvalidTarget);
+    };
+
+    let properties = repo_data.properties_mut(version_id).expect("Version resolved from target must exist");
+    if properties.remove(key).is_none() {
+        return Ok(UnsetPropertyResult::PropertyNotSet);
+    }
+
+    repository_io::write_data(repo_paths, repo_data, "unset property")?;
+
+    Ok(UnsetPropertyResult::Ok)
+}
+
 pub fn has_uncommitted_changes(repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<bool> {
     let versioned_file_metadata = fs::metadata(&repo_paths.versioned_file)?;
     let head_version = repo_data.head_version();
@@ -287,10 +522,12 @@ pub enum ResetResult {
     HeadMustBeBranch,
     InvalidTarget,
     CannotLeaveOrphans,
+    CannotEraseOtherBranches,
+    CannotEraseTaggedVersions,
 }
 
-pub fn reset(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str) -> BiverResult<ResetResult> {
-    let Some(branch) = repo_data.head.branch() else {
+pub fn reset(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str) -> BiverResult<ResetResult> {
+    let Some(branch) = repo_data.head.branch().map(str::to_string) else {
         return Ok(ResetResult::HeadMustBeBranch);
     };
 
@@ -318,14 +555,153 @@ pub fn reset(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, targe
 
     let erased_version_ids: Vec<_> = erased_versions.iter().map(|v| v.id).collect();
 
-    repo_data.versions.retain(|v| !erased_version_ids.contains(&v.id));
-    repo_data.branches.insert(branch.to_string(), target_version_id);
+    let erases_other_branches =
+        repo_data.branches.iter().any(|(b, v)| *b != branch && erased_version_ids.contains(v)) || repo_data.iter_remote_branch_leaves().any(|v| erased_version_ids.contains(&v));
+    if erases_other_branches {
+        return Ok(ResetResult::CannotEraseOtherBranches);
+    }
+
+    if repo_data.tags.values().any(|v| erased_version_ids.contains(v)) {
+        return Ok(ResetResult::CannotEraseTaggedVersions);
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "reset")?;
+
+    reencode_dependents(env, repo_paths, repo_data, &mut journal, &erased_version_ids, None)?;
+
+    repo_data.remove_versions(&erased_version_ids);
+    repo_data.branches.insert(branch, target_version_id);
+
+    repository_io::write_data(repo_paths, repo_data, "reset")?;
 
-    repository_io::write_data(repo_paths, &repo_data)?;
+    journal.finish(repo_data)?;
 
     Ok(ResetResult::Ok)
 }
 
+pub enum DropResult {
+    Ok,
+    InvalidTarget,
+    CannotDropRoot,
+    CannotDropHead,
+    VersionIsTagged,
+    VersionIsPushed,
+}
+
+/// Removes a single version from history. Its children are moved under its parent and branches pointing to it move to its parent.
+pub fn drop_version(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, target: &str) -> BiverResult<DropResult> {
+    let Some(version_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(DropResult::InvalidTarget);
+    };
+
+    let Some(parent_id) = repo_data.version(version_id).expect("Version resolved from target must exist").parent else {
+        return Ok(DropResult::CannotDropRoot);
+    };
+
+    if repo_data.head_version().id == version_id {
+        return Ok(DropResult::CannotDropHead);
+    }
+
+    if let Some(result) = history_rewrite_blocker(repo_data, &[version_id]) {
+        return Ok(match result {
+            HistoryRewriteBlocker::Tagged => DropResult::VersionIsTagged,
+            HistoryRewriteBlocker::Pushed => DropResult::VersionIsPushed,
+        });
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "drop")?;
+
+    for branch_leaf_id in repo_data.branches.values_mut().filter(|id| **id == version_id) {
+        *branch_leaf_id = parent_id;
+    }
+
+    remove_from_history(env, repo_paths, repo_data, &mut journal, &[version_id])?;
+
+    repository_io::write_data(repo_paths, repo_data, "drop")?;
+
+    journal.finish(repo_data)?;
+
+    Ok(DropResult::Ok)
+}
+
+pub enum SquashResult {
+    Ok {
+        squashed_version_count: usize,
+    },
+    InvalidTarget,
+    /// The oldest and newest targets are the same version.
+    NothingToSquash,
+    NotAnAncestor,
+    CannotRemoveHead,
+    VersionHasOtherChildren,
+    VersionIsTagged,
+    VersionIsPushed,
+}
+
+/// Folds the versions from the oldest one up to the newest one into the newest one, which keeps its content and takes over the parent of the oldest one.
+/// Descriptions are merged, and branches pointing to the folded versions move to the newest one.
+pub fn squash(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, oldest_target: &str, newest_target: &str) -> BiverResult<SquashResult> {
+    let (Some(oldest_id), Some(newest_id)) = (resolve_target_version_id(repo_data, oldest_target), resolve_target_version_id(repo_data, newest_target)) else {
+        return Ok(SquashResult::InvalidTarget);
+    };
+
+    if oldest_id == newest_id {
+        return Ok(SquashResult::NothingToSquash);
+    }
+
+    if !is_ancestor_or_self(repo_data, oldest_id, newest_id) {
+        return Ok(SquashResult::NotAnAncestor);
+    }
+
+    let mut squashed_versions: Vec<_> = repo_data.iter_version_and_ancestors(newest_id).skip(1).take_while(|v| v.id != oldest_id).collect();
+    squashed_versions.push(repo_data.version(oldest_id).expect("Squash target must exist"));
+    let squashed_version_ids: Vec<_> = squashed_versions.iter().map(|v| v.id).collect();
+
+    if squashed_version_ids.contains(&repo_data.head_version().id) {
+        return Ok(SquashResult::CannotRemoveHead);
+    }
+
+    let has_other_children = squashed_version_ids
+        .iter()
+        .any(|id| repo_data.iter_children(*id).any(|child| child.id != newest_id && !squashed_version_ids.contains(&child.id)));
+    if has_other_children {
+        return Ok(SquashResult::VersionHasOtherChildren);
+    }
+
+    if let Some(result) = history_rewrite_blocker(repo_data, &squashed_version_ids) {
+        return Ok(match result {
+            HistoryRewriteBlocker::Tagged => SquashResult::VersionIsTagged,
+            HistoryRewriteBlocker::Pushed => SquashResult::VersionIsPushed,
+        });
+    }
+
+    let mut descriptions: Vec<&str> = Vec::new();
+    for version in squashed_versions.iter().rev().chain([&repo_data.version(newest_id).expect("Squash target must exist")]) {
+        if !version.description.is_empty() && descriptions.last() != Some(&version.description.as_str()) {
+            descriptions.push(&version.description);
+        }
+    }
+    let description = descriptions.join("; ");
+
+    let mut journal = repository_io::begin_operation(repo_paths, "squash")?;
+
+    for branch_leaf_id in repo_data.branches.values_mut().filter(|id| squashed_version_ids.contains(id)) {
+        *branch_leaf_id = newest_id;
+    }
+
+    repo_data.set_description(newest_id, description);
+
+    remove_from_history(env, repo_paths, repo_data, &mut journal, &squashed_version_ids)?;
+
+    repository_io::write_data(repo_paths, repo_data, "squash")?;
+
+    journal.finish(repo_data)?;
+
+    Ok(SquashResult::Ok {
+        squashed_version_count: squashed_version_ids.len(),
+    })
+}
+
 pub enum CheckOutResult {
     Ok,
     InvalidTarget,
@@ -341,12 +717,12 @@ pub fn check_out(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut Reposi
     };
 
     repo_data.head = new_head;
-    let new_head_version = repo_data.head_version();
+    let new_head_version_id = repo_data.head_version().id;
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "checkout")?;
 
     if !has_uncommitted_changes {
-        repository_io::extract_version_content(env, repo_paths, repo_data, new_head_version.id, &repo_paths.versioned_file)?;
+        repository_io::extract_version_content(env, repo_paths, repo_data, new_head_version_id, &repo_paths.versioned_file)?;
     }
 
     Ok(CheckOutResult::Ok)
@@ -378,6 +754,80 @@ pub fn restore(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryDa
     Ok(RestoreResult::Ok)
 }
 
+pub enum MoveResult {
+    Ok(RepositoryPaths),
+    VersionedFileMissing,
+    DestinationExists,
+    DestinationMissing,
+    DestinationHasRepository,
+}
+
+/// Moves the versioned file together with its repository directory, or its pointer file for repositories in the central store.
+/// With `after`, the versioned file has already been moved to the destination and only the repository follows it.
+pub fn move_versioned_file(repo_paths: &RepositoryPaths, destination: &Path, after: bool) -> BiverResult<MoveResult> {
+    let destination = if destination.is_dir() {
+        destination.join(repo_paths.versioned_file.file_name().expect("Versioned file path must have a file name"))
+    } else {
+        destination.to_path_buf()
+    };
+
+    let destination_sibling_path = sibling_path(&destination);
+    if fs::exists(&destination_sibling_path)? {
+        return Ok(MoveResult::DestinationHasRepository);
+    }
+
+    if after {
+        if !destination.is_file() {
+            return Ok(MoveResult::DestinationMissing);
+        }
+    } else {
+        if !repo_paths.versioned_file.is_file() {
+            return Ok(MoveResult::VersionedFileMissing);
+        }
+        if fs::exists(&destination)? {
+            return Ok(MoveResult::DestinationExists);
+        }
+
+        // The file is moved first, so that an interrupted move leaves a missing versioned file that can be reattached.
+        repository_io::move_path(&repo_paths.versioned_file, &destination)?;
+    }
+
+    repository_io::move_path(&sibling_path(&repo_paths.versioned_file), &destination_sibling_path)?;
+
+    Ok(MoveResult::Ok(RepositoryPaths::from_versioned_file_path(destination)?))
+}
+
+/// Looks for a file next to the missing versioned file whose content matches the head version, as left behind by renaming the versioned file without its repository.
+pub fn find_renamed_versioned_file(repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<Option<PathBuf>> {
+    let head_version = repo_data.head_version();
+
+    let directory = match repo_paths.versioned_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+
+    let mut candidates = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if metadata.is_file() && metadata.len() == head_version.versioned_file_length {
+            candidates.push(repo_paths.versioned_file.with_file_name(entry.file_name()));
+        }
+    }
+    candidates.sort();
+
+    for candidate in candidates {
+        let has_own_repository = fs::exists(sibling_path(&candidate))?;
+
+        if !has_own_repository && hash::xxh3_128(&File::open(&candidate)?)? == head_version.versioned_file_xxh3_128 {
+            return Ok(Some(candidate));
+        }
+    }
+
+    Ok(None)
+}
+
 pub enum VersionResult<'a> {
     Ok(&'a Version),
     InvalidTarget,
@@ -428,11 +878,77 @@ pub fn create_branch(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryDat
         repo_data.head = Head::Branch(name.to_string());
     }
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "create branch")?;
 
     Ok(CreateBranchResult::Ok)
 }
 
+pub enum CreateTagResult {
+    Ok,
+    InvalidTagName,
+    TagAlreadyExists,
+    InvalidTarget,
+}
+
+/// Tags cannot be moved once created, only deleted.
+pub fn create_tag(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &str, target: &str) -> BiverResult<CreateTagResult> {
+    if !valid_tag_name(name) {
+        return Ok(CreateTagResult::InvalidTagName);
+    }
+
+    if repo_data.tags.contains_key(name) {
+        return Ok(CreateTagResult::TagAlreadyExists);
+    }
+
+    let version_id = match resolve_target(repo_data, target) {
+        TargetResult::Invalid => return Ok(CreateTagResult::InvalidTarget),
+        TargetResult::Branch(branch) => repo_data.branches[branch],
+        TargetResult::Version(version) => version.id,
+    };
+
+    repo_data.tags.insert(name.to_string(), version_id);
+
+    repository_io::write_data(repo_paths, repo_data, "create tag")?;
+
+    Ok(CreateTagResult::Ok)
+}
+
+pub enum DeleteTagResult {
+    Ok,
+    TagDoesNotExist,
+    HeadWouldBeErased,
+}
+
+/// Versions that only the tag leads to are erased with it.
+pub fn delete_tag(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &str) -> BiverResult<DeleteTagResult> {
+    if !repo_data.tags.contains_key(name) {
+        return Ok(DeleteTagResult::TagDoesNotExist);
+    }
+
+    let leaf_ids = repo_data
+        .branches
+        .values()
+        .copied()
+        .chain(repo_data.iter_remote_branch_leaves())
+        .chain(repo_data.tags.iter().filter(|(tag, _)| *tag != name).map(|(_, id)| *id));
+    let erased_version_ids = version_ids_unreachable_from(repo_data, leaf_ids);
+    if erased_version_ids.contains(&repo_data.head_version().id) {
+        return Ok(DeleteTagResult::HeadWouldBeErased);
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "delete tag")?;
+
+    repo_data.tags.remove(name);
+
+    erase_versions(env, repo_paths, repo_data, &mut journal, &erased_version_ids)?;
+
+    repository_io::write_data(repo_paths, repo_data, "delete tag")?;
+
+    journal.finish(repo_data)?;
+
+    Ok(DeleteTagResult::Ok)
+}
+
 pub enum RenameBranchResult {
     Ok,
     AnotherBranchExistsWithSameName,
@@ -454,7 +970,7 @@ pub fn rename_branch(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryDat
 
     repo_data.branches.insert(new_name.to_string(), branch_version_id);
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    repository_io::write_data(repo_paths, repo_data, "rename branch")?;
 
     Ok(RenameBranchResult::Ok)
 }
@@ -465,7 +981,7 @@ pub enum DeleteBranchResult {
     CannotDeleteHead,
 }
 
-pub fn delete_branch(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &String) -> BiverResult<DeleteBranchResult> {
+pub fn delete_branch(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &String) -> BiverResult<DeleteBranchResult> {
     if !repo_data.branches.contains_key(name) {
         return Ok(DeleteBranchResult::BranchDoesNotExist);
     }
@@ -474,7 +990,13 @@ pub fn delete_branch(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryDat
 
     let versions_on_other_branches = {
         let mut result = HashSet::new();
-        let leaf_ids = repo_data.branches.iter().filter(|(b, _)| *b != name).map(|(_, v)| *v);
+        let leaf_ids = repo_data
+            .branches
+            .iter()
+            .filter(|(b, _)| *b != name)
+            .map(|(_, v)| *v)
+            .chain(repo_data.iter_remote_branch_leaves())
+            .chain(repo_data.tags.values().copied());
         for leaf_id in leaf_ids {
             for version in repo_data.iter_version_and_ancestors(leaf_id) {
                 if !result.insert(version.id) {
@@ -497,35 +1019,1559 @@ pub fn delete_branch(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryDat
         return Ok(DeleteBranchResult::CannotDeleteHead);
     }
 
+    let mut journal = repository_io::begin_operation(repo_paths, "delete branch")?;
+
+    reencode_dependents(env, repo_paths, repo_data, &mut journal, &erased_version_ids, None)?;
+
     repo_data.branches.remove(name);
-    repo_data.versions.retain(|v| !erased_version_ids.contains(&v.id));
+    repo_data.remove_versions(&erased_version_ids);
+
+    repository_io::write_data(repo_paths, repo_data, "delete branch")?;
 
-    repository_io::write_data(repo_paths, repo_data)?;
+    journal.finish(repo_data)?;
 
     Ok(DeleteBranchResult::Ok)
 }
 
-enum TargetResult<'b, 'v> {
-    Branch(&'b str),
-    Version(&'v Version),
-    Invalid,
+pub enum RebaseResult {
+    Ok { moved_version_count: usize },
+    HeadMustBeOnBranch,
+    InvalidBranch,
+    InvalidTarget,
+    NoVersionsUniqueToBranch,
+    TargetIsOnBranch,
+    AlreadyOnTarget,
 }
 
-fn resolve_target<'b, 'v>(repo_data: &'v RepositoryData, target: &'b str) -> TargetResult<'b, 'v> {
-    if target.is_empty() {
-        return TargetResult::Invalid;
-    }
+/// Moves the versions that only the branch leads to under the target version. Versions shared with other branches, remote-tracking branches or tags stay
+/// where they are. Only the blob of the first moved version is re-encoded, so the content of every version stays the same.
+pub fn rebase(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, branch: Option<&str>, target: &str) -> BiverResult<RebaseResult> {
+    let branch = match branch {
+        Some(branch) => branch.to_string(),
+        None => match repo_data.head.branch() {
+            Some(branch) => branch.to_string(),
+            None => return Ok(RebaseResult::HeadMustBeOnBranch),
+        },
+    };
 
-    // As branch name
-    if repo_data.branches.contains_key(target) {
-        return TargetResult::Branch(target);
+    let Some(&branch_leaf_id) = repo_data.branches.get(&branch) else {
+        return Ok(RebaseResult::InvalidBranch);
+    };
+
+    let Some(new_parent_id) = resolve_target_version_id(repo_data, target) else {
+        return Ok(RebaseResult::InvalidTarget);
+    };
+
+    let versions_on_other_references = {
+        let mut result = HashSet::new();
+        let leaf_ids = repo_data
+            .branches
+            .iter()
+            .filter(|(b, _)| **b != branch)
+            .map(|(_, v)| *v)
+            .chain(repo_data.iter_remote_branch_leaves())
+            .chain(repo_data.tags.values().copied());
+        for leaf_id in leaf_ids {
+            for version in repo_data.iter_version_and_ancestors(leaf_id) {
+                if !result.insert(version.id) {
+                    break;
+                }
+            }
+        }
+        result
+    };
+
+    let moved_version_ids: Vec<_> = repo_data
+        .iter_version_and_ancestors(branch_leaf_id)
+        .map(|v| v.id)
+        .take_while(|id| !versions_on_other_references.contains(id))
+        .collect();
+
+    let Some(&first_moved_id) = moved_version_ids.last() else {
+        return Ok(RebaseResult::NoVersionsUniqueToBranch);
+    };
+
+    if moved_version_ids.contains(&new_parent_id) {
+        return Ok(RebaseResult::TargetIsOnBranch);
+    }
+
+    let first_moved = repo_data.version(first_moved_id).expect("Moved version must exist");
+    let old_parent_id = first_moved.parent.expect("The target is not among the moved versions, so neither is the root they share");
+
+    if old_parent_id == new_parent_id {
+        return Ok(RebaseResult::AlreadyOnTarget);
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "rebase")?;
+
+    if first_moved.patch_base() == Some(old_parent_id) {
+        let content = repository_io::read_version_content(env, repo_paths, repo_data, first_moved_id)?;
+
+        let file_name = unused_content_blob_file_name(repo_paths, &content_blob_file_names(repo_data), first_moved_id)?;
+        let file_path = repo_paths.file_path(&file_name);
+        journal.record_created_file(&file_name)?;
+
+        let new_parent_depends_on_moved_version = repo_data.iter_blob_chain(new_parent_id).any(|v| v.id == first_moved_id);
+
+        let codec = if repo_data.storage_mode == StorageMode::Forward && !new_parent_depends_on_moved_version {
+            let new_parent_content = repository_io::read_version_content(env, repo_paths, repo_data, new_parent_id)?;
+            let patch_length = repository_io::store_version_content(env, env.patch_codec, &new_parent_content, &content, &file_path)?;
+
+            if should_convert_patch_to_full(env, repo_paths, repo_data, new_parent_id, content.len() as u64, patch_length)? {
+                repository_io::store_version_content(env, env.full_codec, &[], &content, &file_path)?;
+                env.full_codec
+            } else {
+                env.patch_codec
+            }
+        } else {
+            repository_io::store_version_content(env, env.full_codec, &[], &content, &file_path)?;
+            env.full_codec
+        };
+
+        let previous_file_name = repo_data.set_content_blob(first_moved_id, file_name, codec, None);
+        journal.record_obsolete_file(&previous_file_name)?;
+    }
+
+    repo_data.set_parent(first_moved_id, Some(new_parent_id));
+
+    repository_io::write_data(repo_paths, repo_data, "rebase")?;
+
+    journal.finish(repo_data)?;
+
+    Ok(RebaseResult::Ok {
+        moved_version_count: moved_version_ids.len(),
+    })
+}
+
+pub struct RepackPolicy {
+    pub max_chain_depth: Option<usize>,
+    pub max_patch_chain_ratio: f64,
+    pub storage_mode: StorageMode,
+}
+
+pub struct StorageStats {
+    pub total_length: u64,
+    pub full_blob_count: usize,
+    pub patch_blob_count: usize,
+    pub max_chain_depth: usize,
+}
+
+pub enum RepackResult {
+    /// The replaced blobs are kept for undoing the repack until gc, and are not part of the after stats.
+    Ok {
+        before: StorageStats,
+        after: StorageStats,
+        kept_for_undo_length: u64,
+    },
+    ContentMismatch(VersionId),
+}
+
+struct RepackedBlob {
+    file_name: String,
+    codec: ContentBlobCodec,
+    base: Option<VersionId>,
+}
+
+#[derive(Copy, Clone, Default)]
+struct PatchChain {
+    depth: usize,
+    length: u64,
+}
+
+pub fn repack(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, policy: &RepackPolicy) -> BiverResult<RepackResult> {
+    let before = storage_stats(repo_paths, repo_data)?;
+
+    let mut repacked_blobs = HashMap::new();
+
+    let mut journal = repository_io::begin_operation(repo_paths, "repack")?;
+
+    let mismatch = match write_repacked_blobs(env, repo_paths, repo_data, &mut journal, policy, &mut repacked_blobs) {
+        Ok(mismatch) => mismatch,
+        Err(error) => {
+            journal.finish(repo_data)?;
+            return Err(error);
+        }
+    };
+
+    if let Some(version_id) = mismatch {
+        journal.finish(repo_data)?;
+        return Ok(RepackResult::ContentMismatch(version_id));
+    }
+
+    let mut previous_file_names = Vec::new();
+    for (version_id, repacked_blob) in repacked_blobs {
+        let previous_file_name = repo_data.set_content_blob(version_id, repacked_blob.file_name, repacked_blob.codec, repacked_blob.base);
+        journal.record_obsolete_file(&previous_file_name)?;
+        previous_file_names.push(previous_file_name);
+    }
+
+    repo_data.storage_mode = policy.storage_mode;
+
+    repository_io::write_data(repo_paths, repo_data, "repack")?;
+
+    journal.finish(repo_data)?;
+
+    let after = storage_stats(repo_paths, repo_data)?;
+
+    let mut kept_for_undo_length = 0;
+    for file_name in previous_file_names {
+        if let Ok(metadata) = fs::metadata(repo_paths.file_path(&file_name)) {
+            kept_for_undo_length += metadata.len();
+        }
+    }
+
+    Ok(RepackResult::Ok {
+        before,
+        after,
+        kept_for_undo_length,
+    })
+}
+
+/// Re-encodes every version, visiting each new patch base before the versions patched against it.
+/// Decoded contents are kept in memory only while there are unvisited versions depending on them in either the existing or the new layout.
+fn write_repacked_blobs(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &RepositoryData,
+    journal: &mut Journal,
+    policy: &RepackPolicy,
+    repacked_blobs: &mut HashMap<VersionId, RepackedBlob>,
+) -> BiverResult<Option<VersionId>> {
+    let new_patch_bases: HashMap<VersionId, Option<VersionId>> = repo_data
+        .versions()
+        .iter()
+        .map(|v| (v.id, repacked_patch_base(repo_data, v, policy.storage_mode)))
+        .collect();
+
+    let mut pending_uses: HashMap<VersionId, usize> = HashMap::new();
+    for version in repo_data.versions().iter() {
+        for base_id in [version.patch_base(), new_patch_bases[&version.id]].into_iter().flatten() {
+            *pending_uses.entry(base_id).or_default() += 1;
+        }
+    }
+
+    let mut contents: HashMap<VersionId, Vec<u8>> = HashMap::new();
+    let mut chains: HashMap<VersionId, PatchChain> = HashMap::new();
+    let file_names_in_use = content_blob_file_names(repo_data);
+
+    for version_id in repack_order(repo_data, policy.storage_mode) {
+        let version = repo_data.version(version_id).expect("Version from the tree must exist");
+
+        let content = match version.patch_base().and_then(|base_id| contents.get(&base_id)) {
+            Some(base_content) => {
+                let blob = fs::read(repo_paths.file_path(&version.content_blob_file_name))?;
+                delta_codec::codec(env, version.content_blob_codec).decode(base_content, &blob)?
+            }
+            None => repository_io::read_version_content(env, repo_paths, repo_data, version_id)?,
+        };
+
+        if content.len() as u64 != version.versioned_file_length || hash::xxh3_128_of_bytes(&content) != version.versioned_file_xxh3_128 {
+            return Ok(Some(version_id));
+        }
+
+        let file_name = unused_content_blob_file_name(repo_paths, &file_names_in_use, version_id)?;
+        let file_path = repo_paths.file_path(&file_name);
+        journal.record_created_file(&file_name)?;
+
+        let mut chain = PatchChain::default();
+        let mut codec = env.full_codec;
+        let mut base = None;
+
+        if let Some(base_id) = new_patch_bases[&version_id] {
+            let base_content = contents.get(&base_id).expect("New patch base must be visited first");
+            let patch_length = repository_io::store_version_content(env, env.patch_codec, base_content, &content, &file_path)?;
+
+            let base_chain = chains[&base_id];
+            chain = PatchChain {
+                depth: base_chain.depth + 1,
+                length: base_chain.length + patch_length,
+            };
+
+            let depth_exceeded = policy.max_chain_depth.is_some_and(|max_depth| chain.depth > max_depth);
+            let ratio_exceeded = chain.length as f64 > content.len() as f64 * policy.max_patch_chain_ratio;
+
+            if depth_exceeded || ratio_exceeded {
+                chain = PatchChain::default();
+            } else {
+                codec = env.patch_codec;
+                base = Some(base_id);
+            }
+        }
+
+        if codec.kind().is_full() {
+            repository_io::store_version_content(env, codec, &[], &content, &file_path)?;
+        }
+
+        repacked_blobs.insert(version_id, RepackedBlob { file_name, codec, base });
+        chains.insert(version_id, chain);
+
+        for base_id in [version.patch_base(), new_patch_bases[&version_id]].into_iter().flatten() {
+            let uses = pending_uses.get_mut(&base_id).expect("Patch base uses must be counted");
+            *uses -= 1;
+            if *uses == 0 {
+                contents.remove(&base_id);
+            }
+        }
+
+        if pending_uses.get(&version_id).is_some_and(|uses| *uses > 0) {
+            contents.insert(version_id, content);
+        }
+    }
+
+    Ok(None)
+}
+
+/// Pre-order from the root for forward storage, so that parents come before children, and the reverse of it for reverse storage.
+fn repack_order(repo_data: &RepositoryData, storage_mode: StorageMode) -> Vec<VersionId> {
+    let root = repo_data.versions().iter().find(|v| v.is_root()).expect("There must be a root version");
+
+    let mut order = Vec::with_capacity(repo_data.versions().len());
+    let mut stack = vec![root.id];
+
+    while let Some(version_id) = stack.pop() {
+        order.push(version_id);
+        stack.extend(repo_data.iter_children(version_id).map(|v| v.id));
+    }
+
+    if storage_mode == StorageMode::Reverse {
+        order.reverse();
+    }
+
+    order
+}
+
+/// In reverse storage, branch tips and leaves are stored in full and other versions are patched against a child, preferring the current one.
+fn repacked_patch_base(repo_data: &RepositoryData, version: &Version, storage_mode: StorageMode) -> Option<VersionId> {
+    match storage_mode {
+        StorageMode::Forward => version.parent,
+        StorageMode::Reverse => {
+            if repo_data.branches.values().any(|id| *id == version.id) {
+                return None;
+            }
+
+            let current_child_base = version
+                .patch_base()
+                .filter(|base_id| repo_data.version(*base_id).is_some_and(|base| base.parent == Some(version.id)));
+
+            current_child_base.or_else(|| repo_data.iter_children(version.id).next().map(|v| v.id))
+        }
+    }
+}
+
+pub fn storage_stats(repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<StorageStats> {
+    let mut stats = StorageStats {
+        total_length: 0,
+        full_blob_count: 0,
+        patch_blob_count: 0,
+        max_chain_depth: 0,
+    };
+
+    let mut counted_file_names = HashSet::new();
+
+    for version in repo_data.versions().iter() {
+        if counted_file_names.insert(version.content_blob_file_name.as_str()) {
+            stats.total_length += fs::metadata(repo_paths.file_path(&version.content_blob_file_name))?.len();
+        }
+
+        if version.content_blob_kind().is_full() {
+            stats.full_blob_count += 1;
+        } else {
+            stats.patch_blob_count += 1;
+        }
+
+        let chain_depth = repo_data.iter_blob_chain(version.id).take_while(|v| v.content_blob_kind().is_patch()).count();
+        stats.max_chain_depth = stats.max_chain_depth.max(chain_depth);
+    }
+
+    Ok(stats)
+}
+
+pub enum VersionProblem {
+    MissingContentBlob(String),
+    MissingPreviewBlob(String),
+    BrokenPatchChain(VersionId),
+    UnreadableContent(String),
+    /// The blob chain uses a VCDIFF feature that only the xdelta3 executable decodes, and it is not installed.
+    NeedsXdelta3(String),
+    LengthMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    HashMismatch,
+}
+
+pub struct VersionCheck {
+    pub version_id: VersionId,
+    pub problems: Vec<VersionProblem>,
+}
+
+pub struct FsckReport {
+    pub graph_valid: bool,
+    pub version_checks: Vec<VersionCheck>,
+}
+
+impl FsckReport {
+    pub fn corrupted_version_count(&self) -> usize {
+        self.version_checks.iter().filter(|c| !c.problems.is_empty()).count()
+    }
+}
+
+pub fn fsck(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<FsckReport> {
+    let mut versions: Vec<_> = repo_data.versions().iter().collect();
+    versions.sort_by_key(|v| v.creation_time);
+
+    let mut version_checks = Vec::with_capacity(versions.len());
+
+    for version in versions {
+        version_checks.push(VersionCheck {
+            version_id: version.id,
+            problems: check_version(env, repo_paths, repo_data, version)?,
+        });
+    }
+
+    Ok(FsckReport {
+        graph_valid: repo_data.valid(),
+        version_checks,
+    })
+}
+
+fn check_version(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, version: &Version) -> BiverResult<Vec<VersionProblem>> {
+    let mut problems = Vec::new();
+
+    if !fs::exists(repo_paths.file_path(&version.content_blob_file_name))? {
+        problems.push(VersionProblem::MissingContentBlob(version.content_blob_file_name.clone()));
+    }
+
+    if let Some(preview_blob_file_name) = &version.preview_blob_file_name
+        && !fs::exists(repo_paths.file_path(preview_blob_file_name))?
+    {
+        problems.push(VersionProblem::MissingPreviewBlob(preview_blob_file_name.clone()));
+    }
+
+    // A chain longer than the number of versions means the patch bases form a cycle.
+    let blob_chain: Vec<_> = repo_data.iter_blob_chain(version.id).take(repo_data.versions().len() + 1).collect();
+
+    if blob_chain.len() > repo_data.versions().len() {
+        problems.push(VersionProblem::BrokenPatchChain(version.id));
+    } else {
+        for chain_version in blob_chain.iter().skip(1) {
+            if !fs::exists(repo_paths.file_path(&chain_version.content_blob_file_name))? {
+                problems.push(VersionProblem::BrokenPatchChain(chain_version.id));
+            }
+        }
+
+        let chain_end = blob_chain.last().expect("Blob chain starts with the version itself");
+        if chain_end.content_blob_kind().is_patch() {
+            problems.push(VersionProblem::BrokenPatchChain(chain_end.id));
+        }
+    }
+
+    if !problems.iter().all(|p| matches!(p, VersionProblem::MissingPreviewBlob(_))) {
+        return Ok(problems);
+    }
+
+    match repository_io::read_version_content(env, repo_paths, repo_data, version.id) {
+        Err(error) if error.kind() == io::ErrorKind::Unsupported => problems.push(VersionProblem::NeedsXdelta3(error.to_string())),
+        Err(error) => problems.push(VersionProblem::UnreadableContent(error.to_string())),
+        Ok(content) => {
+            if content.len() as u64 != version.versioned_file_length {
+                problems.push(VersionProblem::LengthMismatch {
+                    expected: version.versioned_file_length,
+                    actual: content.len() as u64,
+                });
+            } else if hash::xxh3_128_of_bytes(&content) != version.versioned_file_xxh3_128 {
+                problems.push(VersionProblem::HashMismatch);
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+pub struct DataDiff<'a> {
+    pub versions_only_in_current: Vec<&'a Version>,
+    pub versions_only_in_backup: Vec<&'a Version>,
+    pub changed_versions: Vec<VersionChange<'a>>,
+    pub changed_branches: Vec<BranchChange>,
+    pub head_changed: bool,
+}
+
+pub struct VersionChange<'a> {
+    pub current: &'a Version,
+    pub backup: &'a Version,
+}
+
+pub struct BranchChange {
+    pub name: String,
+    pub current: Option<VersionId>,
+    pub backup: Option<VersionId>,
+}
+
+impl DataDiff<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.versions_only_in_current.is_empty()
+            && self.versions_only_in_backup.is_empty()
+            && self.changed_versions.is_empty()
+            && self.changed_branches.is_empty()
+            && !self.head_changed
+    }
+}
+
+pub fn diff_data<'a>(current: &'a RepositoryData, backup: &'a RepositoryData) -> DataDiff<'a> {
+    let mut versions_only_in_current: Vec<_> = current.versions().iter().filter(|v| backup.version(v.id).is_none()).collect();
+    versions_only_in_current.sort_by_key(|v| std::cmp::Reverse(v.creation_time));
+
+    let mut versions_only_in_backup: Vec<_> = backup.versions().iter().filter(|v| current.version(v.id).is_none()).collect();
+    versions_only_in_backup.sort_by_key(|v| std::cmp::Reverse(v.creation_time));
+
+    let changed_versions = current
+        .versions()
+        .iter()
+        .filter_map(|current_version| backup.version(current_version.id).map(|backup_version| (current_version, backup_version)))
+        .filter(|(c, b)| c.description != b.description || c.content_blob_file_name != b.content_blob_file_name)
+        .map(|(current, backup)| VersionChange { current, backup })
+        .collect();
+
+    let mut branch_names: Vec<&String> = current.branches.keys().chain(backup.branches.keys()).collect();
+    branch_names.sort();
+    branch_names.dedup();
+
+    let changed_branches = branch_names
+        .into_iter()
+        .map(|name| BranchChange {
+            name: name.clone(),
+            current: current.branches.get(name).copied(),
+            backup: backup.branches.get(name).copied(),
+        })
+        .filter(|c| c.current != c.backup)
+        .collect();
+
+    let head_changed = match (&current.head, &backup.head) {
+        (Head::Branch(current_branch), Head::Branch(backup_branch)) => current_branch != backup_branch,
+        (Head::Version(current_version_id), Head::Version(backup_version_id)) => current_version_id != backup_version_id,
+        _ => true,
+    };
+
+    DataDiff {
+        versions_only_in_current,
+        versions_only_in_backup,
+        changed_versions,
+        changed_branches,
+        head_changed,
+    }
+}
+
+pub enum RestoreBackupResult {
+    Ok,
+    BackupDoesNotExist,
+    BackupUnreadable,
+    BackupInvalid,
+    MissingBlobs(Vec<String>),
+}
+
+pub fn restore_backup(repo_paths: &RepositoryPaths, number: usize) -> BiverResult<RestoreBackupResult> {
+    let Some(backup) = repository_io::read_data_backup(repo_paths, number)? else {
+        return Ok(RestoreBackupResult::BackupDoesNotExist);
+    };
+
+    let Some(backup_data) = backup.data else {
+        return Ok(RestoreBackupResult::BackupUnreadable);
+    };
+
+    if !backup_data.valid() {
+        return Ok(RestoreBackupResult::BackupInvalid);
+    }
+
+    let mut missing_blob_file_names = Vec::new();
+
+    for version in backup_data.versions().iter() {
+        for file_name in [Some(&version.content_blob_file_name), version.preview_blob_file_name.as_ref()].into_iter().flatten() {
+            if !fs::exists(repo_paths.file_path(file_name))? {
+                missing_blob_file_names.push(file_name.clone());
+            }
+        }
+    }
+
+    if !missing_blob_file_names.is_empty() {
+        return Ok(RestoreBackupResult::MissingBlobs(missing_blob_file_names));
+    }
+
+    // The restore is logged as the difference to the current data. Unreadable current data is replaced without an entry.
+    let mut repo_data = match repository_io::read_data(repo_paths) {
+        Ok(RepositoryDataResult::Initialized(mut repo_data)) => {
+            repo_data.replace_with(backup_data);
+            *repo_data
+        }
+        _ => backup_data,
+    };
+    repository_io::write_data(repo_paths, &mut repo_data, "restore backup")?;
+
+    Ok(RestoreBackupResult::Ok)
+}
+
+pub struct BundleSelection {
+    /// Empty for all branches.
+    pub branches: Vec<String>,
+    /// The version the bundled history starts at, instead of the root.
+    pub since: Option<String>,
+}
+
+pub enum CreateBundleResult {
+    Ok { version_count: usize, branch_count: usize },
+    OutputExists,
+    InvalidBranch(String),
+    InvalidSince,
+    SinceNotOnBranch(String),
+    NothingSelected,
+}
+
+/// Bundles the selected branches, or the part of them from the since version on.
+pub fn create_bundle(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, selection: &BundleSelection, output: &Path) -> BiverResult<CreateBundleResult> {
+    if fs::exists(output)? {
+        return Ok(CreateBundleResult::OutputExists);
+    }
+
+    let explicit_branches = !selection.branches.is_empty();
+    let mut branches: Vec<&str> = if explicit_branches {
+        selection.branches.iter().map(String::as_str).collect()
+    } else {
+        repo_data.branches.keys().map(String::as_str).collect()
+    };
+    branches.sort();
+    branches.dedup();
+
+    if let Some(invalid_branch) = branches.iter().find(|b| !repo_data.branches.contains_key(**b)) {
+        return Ok(CreateBundleResult::InvalidBranch(invalid_branch.to_string()));
+    }
+
+    let since_id = match selection.since.as_deref().map(|since| resolve_target(repo_data, since)) {
+        None => None,
+        Some(TargetResult::Invalid) => return Ok(CreateBundleResult::InvalidSince),
+        Some(TargetResult::Branch(branch)) => Some(repo_data.branches[branch]),
+        Some(TargetResult::Version(version)) => Some(version.id),
+    };
+
+    let mut selected_ids = HashSet::new();
+    let mut bundled_branches = HashMap::new();
+
+    for branch in branches {
+        let leaf_id = repo_data.branches[branch];
+        let mut lineage = Vec::new();
+        let mut reaches_since = since_id.is_none();
+
+        for version in repo_data.iter_version_and_ancestors(leaf_id) {
+            lineage.push(version.id);
+            if Some(version.id) == since_id {
+                reaches_since = true;
+                break;
+            }
+        }
+
+        if !reaches_since {
+            if explicit_branches {
+                return Ok(CreateBundleResult::SinceNotOnBranch(branch.to_string()));
+            }
+            continue;
+        }
+
+        selected_ids.extend(lineage);
+        bundled_branches.insert(branch.to_string(), leaf_id);
+    }
+
+    if bundled_branches.is_empty() {
+        return Ok(CreateBundleResult::NothingSelected);
+    }
+
+    let mut versions = Vec::new();
+    let mut blob_files = Vec::new();
+
+    for version in repo_data.versions().iter().filter(|v| selected_ids.contains(&v.id)) {
+        let mut version = version.clone();
+
+        // Versions patched against a version outside the selection are bundled in full, which also ends the blob chains of the versions patched against them.
+        let patch_base_is_selected = version.patch_base().is_none_or(|base| selected_ids.contains(&base));
+        if patch_base_is_selected {
+            blob_files.push((
+                version.content_blob_file_name.clone(),
+                BundleFileSource::Path(repo_paths.file_path(&version.content_blob_file_name)),
+            ));
+        } else {
+            let content = repository_io::read_version_content(env, repo_paths, repo_data, version.id)?;
+            let blob = delta_codec::codec(env, env.full_codec).encode(&[], &content)?;
+            blob_files.push((version.content_blob_file_name.clone(), BundleFileSource::Content(blob)));
+
+            version.content_blob_codec = env.full_codec;
+            version.content_blob_base = None;
+        }
+
+        if Some(version.id) == since_id {
+            version.parent = None;
+        }
+
+        if let Some(preview_blob_file_name) = version.preview_blob_file_name.take() {
+            let preview_blob_file_path = repo_paths.file_path(&preview_blob_file_name);
+            if fs::exists(&preview_blob_file_path)? {
+                blob_files.push((preview_blob_file_name.clone(), BundleFileSource::Path(preview_blob_file_path)));
+                version.preview_blob_file_name = Some(preview_blob_file_name);
+            }
+        }
+
+        versions.push(version);
+    }
+
+    let head = match &repo_data.head {
+        Head::Branch(branch) if bundled_branches.contains_key(branch) => Head::Branch(branch.clone()),
+        Head::Version(version_id) if selected_ids.contains(version_id) => Head::Version(*version_id),
+        _ => Head::Branch(bundled_branches.keys().min().expect("At least one branch is bundled").clone()),
+    };
+
+    let version_count = versions.len();
+    let branch_count = bundled_branches.len();
+
+    let mut bundle_data = RepositoryData::new(data_migration::CURRENT_FORMAT_VERSION, repo_data.storage_mode, head, bundled_branches, versions);
+    bundle_data.tags = repo_data.tags.iter().filter(|(_, v)| selected_ids.contains(*v)).map(|(t, v)| (t.clone(), *v)).collect();
+
+    let mut files = vec![(BUNDLE_DATA_FILE_NAME.to_string(), BundleFileSource::Content(serde_json::to_vec(&bundle_data)?))];
+    files.extend(blob_files);
+
+    let versioned_file_name = repo_paths.versioned_file.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    bundle::write_bundle(output, &versioned_file_name, &files)?;
+
+    Ok(CreateBundleResult::Ok { version_count, branch_count })
+}
+
+const BUNDLE_DATA_FILE_NAME: &str = "data.json";
+
+pub struct VerifiedBundle {
+    bundle: Bundle,
+    data: RepositoryData,
+}
+
+pub enum VerifyBundleResult {
+    Ok(Box<VerifiedBundle>),
+    NotABundle,
+    NewerBundleFormatVersion(u32),
+    CorruptedFiles(Vec<String>),
+    NewerFormatVersion(u32),
+    InvalidData,
+    MissingFiles(Vec<String>),
+}
+
+/// Checks the checksums of all files in the bundle and the validity of its repository data, before anything is imported.
+pub fn verify_bundle(bundle_path: &Path) -> BiverResult<VerifyBundleResult> {
+    let bundle = match Bundle::open(bundle_path)? {
+        OpenBundleResult::Ok(bundle) => bundle,
+        OpenBundleResult::NotABundle => return Ok(VerifyBundleResult::NotABundle),
+        OpenBundleResult::NewerFormatVersion(format_version) => return Ok(VerifyBundleResult::NewerBundleFormatVersion(format_version)),
+    };
+
+    let corrupted_files = bundle.corrupted_files()?;
+    if !corrupted_files.is_empty() {
+        return Ok(VerifyBundleResult::CorruptedFiles(corrupted_files));
+    }
+
+    if !bundle.contains(BUNDLE_DATA_FILE_NAME) {
+        return Ok(VerifyBundleResult::MissingFiles(vec![BUNDLE_DATA_FILE_NAME.to_string()]));
+    }
+
+    let data = match repository_io::parse_data(&bundle.read_file(BUNDLE_DATA_FILE_NAME)?) {
+        Ok(RepositoryDataResult::Initialized(data)) => *data,
+        Ok(RepositoryDataResult::NewerFormatVersion(format_version)) => return Ok(VerifyBundleResult::NewerFormatVersion(format_version)),
+        Ok(RepositoryDataResult::NotInitialized) | Err(_) => return Ok(VerifyBundleResult::InvalidData),
+    };
+
+    if !data.valid() {
+        return Ok(VerifyBundleResult::InvalidData);
+    }
+
+    let missing_files: Vec<_> = data
+        .versions()
+        .iter()
+        .flat_map(|v| [Some(&v.content_blob_file_name), v.preview_blob_file_name.as_ref()])
+        .flatten()
+        .filter(|file_name| !bundle.contains(file_name))
+        .cloned()
+        .collect();
+
+    if !missing_files.is_empty() {
+        return Ok(VerifyBundleResult::MissingFiles(missing_files));
+    }
+
+    Ok(VerifyBundleResult::Ok(Box::new(VerifiedBundle { bundle, data })))
+}
+
+/// Imports a verified bundle into an empty repository directory and restores the versioned file at head.
+pub fn import_bundle(env: &Env, repo_paths: &RepositoryPaths, verified_bundle: VerifiedBundle) -> BiverResult<()> {
+    let VerifiedBundle { bundle, mut data } = verified_bundle;
+
+    let file_names: Vec<_> = data
+        .versions()
+        .iter()
+        .flat_map(|v| [Some(&v.content_blob_file_name), v.preview_blob_file_name.as_ref()])
+        .flatten()
+        .collect();
+
+    let mut journal = repository_io::begin_operation(repo_paths, "import bundle")?;
+    journal.record_created_files(&file_names)?;
+
+    for file_name in file_names {
+        bundle.extract_file(file_name, &repo_paths.file_path(file_name))?;
+    }
+
+    repository_io::write_data(repo_paths, &mut data, "import bundle")?;
+    journal.finish(&data)?;
+
+    repository_io::extract_version_content(env, repo_paths, &data, data.head_version().id, &repo_paths.versioned_file)?;
+
+    Ok(())
+}
+
+/// Creates a repository with all versions, branches and the head of the source repository in an empty repository directory,
+/// records the source as the origin remote and restores the versioned file at head.
+/// Blobs are never modified in place, so unless copying is requested they are shared with the source through hard links.
+pub fn clone_repository(env: &Env, source_paths: &RepositoryPaths, source_data: &RepositoryData, repo_paths: &RepositoryPaths, copy: bool) -> BiverResult<usize> {
+    let mut versions = Vec::with_capacity(source_data.versions().len());
+    let mut file_names = Vec::new();
+
+    for version in source_data.versions() {
+        let mut version = version.clone();
+        file_names.push(version.content_blob_file_name.clone());
+
+        if let Some(preview_blob_file_name) = version.preview_blob_file_name.take()
+            && fs::exists(source_paths.file_path(&preview_blob_file_name))?
+        {
+            file_names.push(preview_blob_file_name.clone());
+            version.preview_blob_file_name = Some(preview_blob_file_name);
+        }
+
+        versions.push(version);
+    }
+
+    let mut data = RepositoryData::new(
+        data_migration::CURRENT_FORMAT_VERSION,
+        source_data.storage_mode,
+        source_data.head.clone(),
+        source_data.branches.clone(),
+        versions,
+    );
+    data.remotes.insert(
+        ORIGIN_REMOTE_NAME.to_string(),
+        Remote {
+            path: std::path::absolute(&source_paths.versioned_file)?,
+            branches: source_data.branches.clone(),
+        },
+    );
+    data.tags = source_data.tags.clone();
+
+    let mut journal = repository_io::begin_operation(repo_paths, "clone")?;
+    journal.record_created_files(&file_names.iter().collect::<Vec<_>>())?;
+
+    for file_name in &file_names {
+        let source_file_path = source_paths.file_path(file_name);
+        let file_path = repo_paths.file_path(file_name);
+        if copy {
+            fs::copy(source_file_path, file_path)?;
+        } else {
+            repository_io::hard_link_or_copy(&source_file_path, &file_path)?;
+        }
+    }
+
+    repository_io::write_data(repo_paths, &mut data, "clone")?;
+    journal.finish(&data)?;
+
+    repository_io::extract_version_content(env, repo_paths, &data, data.head_version().id, &repo_paths.versioned_file)?;
+
+    Ok(data.versions().len())
+}
+
+pub const ORIGIN_REMOTE_NAME: &str = "origin";
+
+pub enum AddRemoteResult {
+    Ok,
+    InvalidName,
+    RemoteExists,
+    NotInitialized,
+    SameRepository,
+}
+
+/// The remote is given by its versioned file or by its sibling repository directory, and recorded by the absolute path of its versioned file.
+pub fn add_remote(repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &str, remote_path: &Path) -> BiverResult<AddRemoteResult> {
+    if !valid_branch_name(name) {
+        return Ok(AddRemoteResult::InvalidName);
+    }
+
+    if repo_data.remotes.contains_key(name) {
+        return Ok(AddRemoteResult::RemoteExists);
+    }
+
+    let remote_versioned_file_path = std::path::absolute(repository_paths::versioned_file_path_of_repository(remote_path))?;
+
+    let remote_paths = RepositoryPaths::from_versioned_file_path(remote_versioned_file_path.clone())?;
+    if let RepositoryDataResult::NotInitialized = repository_io::read_data(&remote_paths)? {
+        return Ok(AddRemoteResult::NotInitialized);
+    }
+
+    if fs::canonicalize(&remote_paths.repository_dir)? == fs::canonicalize(&repo_paths.repository_dir)? {
+        return Ok(AddRemoteResult::SameRepository);
+    }
+
+    repo_data.remotes.insert(
+        name.to_string(),
+        Remote {
+            path: remote_versioned_file_path,
+            branches: HashMap::new(),
+        },
+    );
+
+    repository_io::write_data(repo_paths, repo_data, "add remote")?;
+
+    Ok(AddRemoteResult::Ok)
+}
+
+pub enum RemoveRemoteResult {
+    Ok,
+    RemoteDoesNotExist,
+    HeadWouldBeErased,
+}
+
+/// Versions that only the remote-tracking branches of the removed remote lead to are erased.
+pub fn remove_remote(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, name: &str) -> BiverResult<RemoveRemoteResult> {
+    if !repo_data.remotes.contains_key(name) {
+        return Ok(RemoveRemoteResult::RemoteDoesNotExist);
+    }
+
+    let leaf_ids = repo_data
+        .branches
+        .values()
+        .chain(
+            repo_data
+                .remotes
+                .iter()
+                .filter(|(remote, _)| *remote != name)
+                .flat_map(|(_, remote)| remote.branches.values()),
+        )
+        .chain(repo_data.tags.values())
+        .copied();
+    let erased_version_ids = version_ids_unreachable_from(repo_data, leaf_ids);
+    if erased_version_ids.contains(&repo_data.head_version().id) {
+        return Ok(RemoveRemoteResult::HeadWouldBeErased);
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "remove remote")?;
+
+    repo_data.remotes.remove(name);
+
+    erase_versions(env, repo_paths, repo_data, &mut journal, &erased_version_ids)?;
+
+    repository_io::write_data(repo_paths, repo_data, "remove remote")?;
+
+    journal.finish(repo_data)?;
+
+    Ok(RemoveRemoteResult::Ok)
+}
+
+pub enum BranchSyncStatus {
+    Created,
+    FastForwarded,
+    UpToDate,
+    /// The destination branch already contains all versions of the source branch and more.
+    DestinationAhead,
+    Diverged,
+    /// The branches have no version in common.
+    Unrelated,
+    /// The branch is checked out in the destination and its versioned file has uncommitted changes.
+    BlockedByUncommittedChanges,
+}
+
+pub struct BranchSync {
+    pub branch: String,
+    pub status: BranchSyncStatus,
+}
+
+pub enum PushResult {
+    Ok { version_count: usize, branches: Vec<BranchSync> },
+    InvalidBranch(String),
+    HeadWouldBeErased,
+}
+
+/// Sends the versions of the branches, or of all branches when none are given, to the remote and fast-forwards the remote branches.
+/// Remote branches that cannot be fast-forwarded are left unchanged. If the checked-out branch of the remote moves, its versioned file is updated.
+pub fn push(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &mut RepositoryData,
+    remote_name: &str,
+    remote_paths: &RepositoryPaths,
+    remote_data: &mut RepositoryData,
+    branches: &[String],
+) -> BiverResult<PushResult> {
+    let mut branches: Vec<String> = if branches.is_empty() {
+        repo_data.branches.keys().cloned().collect()
+    } else {
+        branches.to_vec()
+    };
+    branches.sort();
+    branches.dedup();
+
+    if let Some(invalid_branch) = branches.iter().find(|b| !repo_data.branches.contains_key(*b)) {
+        return Ok(PushResult::InvalidBranch(invalid_branch.clone()));
+    }
+
+    let remote_head_branch = remote_data.head.branch().map(str::to_string);
+    let remote_has_uncommitted_changes = fs::exists(&remote_paths.versioned_file)? && has_uncommitted_changes(remote_paths, remote_data)?;
+
+    let mut branch_syncs = Vec::new();
+    for branch in branches {
+        let leaf_id = repo_data.branches[&branch];
+        let mut status = branch_sync_status(repo_data, remote_data, leaf_id, remote_data.branches.get(&branch).copied());
+
+        if let BranchSyncStatus::FastForwarded = status
+            && remote_head_branch.as_ref() == Some(&branch)
+            && remote_has_uncommitted_changes
+        {
+            status = BranchSyncStatus::BlockedByUncommittedChanges;
+        }
+
+        branch_syncs.push((BranchSync { branch, status }, leaf_id));
+    }
+
+    let remote = repo_data.remotes.get_mut(remote_name).expect("Pushed remote must exist");
+    for (branch_sync, leaf_id) in &branch_syncs {
+        if let BranchSyncStatus::Created | BranchSyncStatus::FastForwarded | BranchSyncStatus::UpToDate = branch_sync.status {
+            remote.branches.insert(branch_sync.branch.clone(), *leaf_id);
+        }
+    }
+
+    let erased_version_ids = unreachable_version_ids(repo_data);
+    if erased_version_ids.contains(&repo_data.head_version().id) {
+        return Ok(PushResult::HeadWouldBeErased);
+    }
+
+    let mut remote_journal = repository_io::begin_operation(remote_paths, "push")?;
+    let mut version_count = 0;
+    let mut remote_head_moved = false;
+
+    for (branch_sync, leaf_id) in &branch_syncs {
+        if let BranchSyncStatus::Created | BranchSyncStatus::FastForwarded = branch_sync.status {
+            version_count += transfer_versions(env, repo_paths, repo_data, remote_paths, remote_data, &mut remote_journal, *leaf_id)?;
+            remote_data.branches.insert(branch_sync.branch.clone(), *leaf_id);
+            remote_head_moved |= remote_head_branch.as_ref() == Some(&branch_sync.branch);
+        }
+    }
+
+    repository_io::write_data(remote_paths, remote_data, "push")?;
+    remote_journal.finish(remote_data)?;
+
+    if remote_head_moved && fs::exists(&remote_paths.versioned_file)? {
+        repository_io::extract_version_content(env, remote_paths, remote_data, remote_data.head_version().id, &remote_paths.versioned_file)?;
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "push")?;
+    erase_versions(env, repo_paths, repo_data, &mut journal, &erased_version_ids)?;
+    repository_io::write_data(repo_paths, repo_data, "push")?;
+    journal.finish(repo_data)?;
+
+    Ok(PushResult::Ok {
+        version_count,
+        branches: branch_syncs.into_iter().map(|(branch_sync, _)| branch_sync).collect(),
+    })
+}
+
+pub enum PullResult {
+    Ok { version_count: usize, branches: Vec<BranchSync> },
+    HeadWouldBeErased,
+}
+
+/// Fetches the versions of all remote branches and records them as remote-tracking branches, then fast-forwards or creates the local branches.
+/// Local branches that cannot be fast-forwarded are left unchanged, but the remote versions are kept on the remote-tracking branches.
+pub fn pull(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &mut RepositoryData,
+    remote_name: &str,
+    remote_paths: &RepositoryPaths,
+    remote_data: &RepositoryData,
+) -> BiverResult<PullResult> {
+    let mut branches: Vec<_> = remote_data.branches.iter().map(|(branch, leaf_id)| (branch.clone(), *leaf_id)).collect();
+    branches.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let head_branch = repo_data.head.branch().map(str::to_string);
+    let has_uncommitted_changes = fs::exists(&repo_paths.versioned_file)? && has_uncommitted_changes(repo_paths, repo_data)?;
+
+    let mut branch_syncs = Vec::new();
+    for (branch, leaf_id) in branches {
+        let mut status = branch_sync_status(remote_data, repo_data, leaf_id, repo_data.branches.get(&branch).copied());
+
+        if let BranchSyncStatus::FastForwarded = status
+            && head_branch.as_ref() == Some(&branch)
+            && has_uncommitted_changes
+        {
+            status = BranchSyncStatus::BlockedByUncommittedChanges;
+        }
+
+        branch_syncs.push((BranchSync { branch, status }, leaf_id));
+    }
+
+    let fetched_branches: Vec<_> = branch_syncs.iter().filter(|(b, _)| !matches!(b.status, BranchSyncStatus::Unrelated)).collect();
+
+    // Local branches only move forward and tags and the branches of other remotes do not move, so a detached head stays reachable
+    // unless it is only on remote-tracking branches of this remote that move away from it.
+    let head_id = repo_data.head_version().id;
+    let other_remote_leaf_ids = repo_data
+        .remotes
+        .iter()
+        .filter(|(name, _)| name.as_str() != remote_name)
+        .flat_map(|(_, remote)| remote.branches.values());
+    let head_stays_reachable = repo_data
+        .branches
+        .values()
+        .chain(repo_data.tags.values())
+        .chain(other_remote_leaf_ids)
+        .any(|leaf_id| is_ancestor_or_self(repo_data, head_id, *leaf_id))
+        || fetched_branches.iter().any(|(_, leaf_id)| is_ancestor_or_self(remote_data, head_id, *leaf_id));
+    if !head_stays_reachable {
+        return Ok(PullResult::HeadWouldBeErased);
+    }
+
+    let mut journal = repository_io::begin_operation(repo_paths, "pull")?;
+    let mut version_count = 0;
+    let mut remote_branches = HashMap::new();
+
+    for (branch_sync, leaf_id) in fetched_branches {
+        version_count += transfer_versions(env, remote_paths, remote_data, repo_paths, repo_data, &mut journal, *leaf_id)?;
+        remote_branches.insert(branch_sync.branch.clone(), *leaf_id);
+
+        if let BranchSyncStatus::Created | BranchSyncStatus::FastForwarded = branch_sync.status {
+            repo_data.branches.insert(branch_sync.branch.clone(), *leaf_id);
+        }
+    }
+
+    repo_data.remotes.get_mut(remote_name).expect("Pulled remote must exist").branches = remote_branches;
+
+    let erased_version_ids = unreachable_version_ids(repo_data);
+    erase_versions(env, repo_paths, repo_data, &mut journal, &erased_version_ids)?;
+
+    repository_io::write_data(repo_paths, repo_data, "pull")?;
+    journal.finish(repo_data)?;
+
+    let head_moved = branch_syncs
+        .iter()
+        .any(|(b, _)| matches!(b.status, BranchSyncStatus::FastForwarded) && head_branch.as_ref() == Some(&b.branch));
+    if head_moved && fs::exists(&repo_paths.versioned_file)? {
+        repository_io::extract_version_content(env, repo_paths, repo_data, repo_data.head_version().id, &repo_paths.versioned_file)?;
+    }
+
+    Ok(PullResult::Ok {
+        version_count,
+        branches: branch_syncs.into_iter().map(|(branch_sync, _)| branch_sync).collect(),
+    })
+}
+
+/// How the destination branch relates to the leaf of the source branch, before any versions are transferred.
+fn branch_sync_status(source_data: &RepositoryData, destination_data: &RepositoryData, source_leaf_id: VersionId, destination_leaf_id: Option<VersionId>) -> BranchSyncStatus {
+    let shares_history = source_data.iter_version_and_ancestors(source_leaf_id).any(|v| destination_data.version(v.id).is_some());
+
+    match destination_leaf_id {
+        _ if !shares_history => BranchSyncStatus::Unrelated,
+        None => BranchSyncStatus::Created,
+        Some(destination_leaf_id) if destination_leaf_id == source_leaf_id => BranchSyncStatus::UpToDate,
+        Some(destination_leaf_id) if is_ancestor_or_self(source_data, destination_leaf_id, source_leaf_id) => BranchSyncStatus::FastForwarded,
+        Some(destination_leaf_id) if is_ancestor_or_self(destination_data, source_leaf_id, destination_leaf_id) => BranchSyncStatus::DestinationAhead,
+        Some(_) => BranchSyncStatus::Diverged,
+    }
+}
+
+fn is_ancestor_or_self(repo_data: &RepositoryData, ancestor_id: VersionId, version_id: VersionId) -> bool {
+    repo_data.iter_version_and_ancestors(version_id).any(|v| v.id == ancestor_id)
+}
+
+/// Copies the versions leading up to the leaf that the destination does not have yet, together with their blobs.
+/// Versions patched against a version that the destination will not have are stored in full.
+fn transfer_versions(
+    env: &Env,
+    source_paths: &RepositoryPaths,
+    source_data: &RepositoryData,
+    destination_paths: &RepositoryPaths,
+    destination_data: &mut RepositoryData,
+    journal: &mut Journal,
+    leaf_id: VersionId,
+) -> BiverResult<usize> {
+    let missing_ids: Vec<_> = source_data
+        .iter_version_and_ancestors(leaf_id)
+        .map(|v| v.id)
+        .take_while(|id| destination_data.version(*id).is_none())
+        .collect();
+    let missing_id_set: HashSet<_> = missing_ids.iter().copied().collect();
+    let file_names_in_use = content_blob_file_names(destination_data);
+
+    for version_id in missing_ids.iter().rev() {
+        let mut version = source_data.version(*version_id).expect("Transferred version must exist").clone();
+
+        let content_blob_file_name = unused_content_blob_file_name(destination_paths, &file_names_in_use, version.id)?;
+        let content_blob_file_path = destination_paths.file_path(&content_blob_file_name);
+        journal.record_created_file(&content_blob_file_name)?;
+
+        let patch_base_is_transferred = version
+            .patch_base()
+            .is_none_or(|base| missing_id_set.contains(&base) || destination_data.version(base).is_some());
+        if patch_base_is_transferred {
+            fs::copy(source_paths.file_path(&version.content_blob_file_name), &content_blob_file_path)?;
+        } else {
+            let content = repository_io::read_version_content(env, source_paths, source_data, version.id)?;
+            repository_io::store_version_content(env, env.full_codec, &[], &content, &content_blob_file_path)?;
+
+            version.content_blob_codec = env.full_codec;
+            version.content_blob_base = None;
+        }
+        version.content_blob_file_name = content_blob_file_name;
+
+        if let Some(preview_blob_file_name) = version.preview_blob_file_name.take() {
+            let source_preview_blob_file_path = source_paths.file_path(&preview_blob_file_name);
+            if fs::exists(&source_preview_blob_file_path)? {
+                journal.record_created_file(&preview_blob_file_name)?;
+                fs::copy(source_preview_blob_file_path, destination_paths.file_path(&preview_blob_file_name))?;
+                version.preview_blob_file_name = Some(preview_blob_file_name);
+            }
+        }
+
+        destination_data.add_version(version);
+    }
+
+    Ok(missing_ids.len())
+}
+
+/// Versions that no local or remote-tracking branch or tag leads to anymore.
+fn unreachable_version_ids(repo_data: &RepositoryData) -> Vec<VersionId> {
+    version_ids_unreachable_from(repo_data, repo_data.iter_referenced_version_ids())
+}
+
+/// Versions that are not ancestors of any of the leaves, for checking what removing a reference would erase before removing it.
+fn version_ids_unreachable_from(repo_data: &RepositoryData, leaf_ids: impl Iterator<Item = VersionId>) -> Vec<VersionId> {
+    let mut reachable_version_ids = HashSet::new();
+
+    for leaf_id in leaf_ids {
+        for version in repo_data.iter_version_and_ancestors(leaf_id) {
+            if !reachable_version_ids.insert(version.id) {
+                break;
+            }
+        }
+    }
+
+    repo_data.versions().iter().map(|v| v.id).filter(|id| !reachable_version_ids.contains(id)).collect()
+}
+
+/// Removes the versions and deletes their blobs once the operation finishes. Blobs of the remaining versions that depend on them are stored in full.
+fn erase_versions(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, journal: &mut Journal, version_ids: &[VersionId]) -> BiverResult<()> {
+    reencode_dependents(env, repo_paths, repo_data, journal, version_ids, None)?;
+
+    for version_id in version_ids {
+        let version = repo_data.version(*version_id).expect("Erased version must exist");
+        journal.record_obsolete_file(&version.content_blob_file_name)?;
+        if let Some(preview_blob_file_name) = &version.preview_blob_file_name {
+            journal.record_obsolete_file(preview_blob_file_name)?;
+        }
+    }
+
+    repo_data.remove_versions(version_ids);
+
+    Ok(())
+}
+
+enum HistoryRewriteBlocker {
+    Tagged,
+    Pushed,
+}
+
+/// Tagged versions and versions that remote-tracking branches lead to must keep their place in history.
+fn history_rewrite_blocker(repo_data: &RepositoryData, version_ids: &[VersionId]) -> Option<HistoryRewriteBlocker> {
+    if repo_data.tags.values().any(|id| version_ids.contains(id)) {
+        return Some(HistoryRewriteBlocker::Tagged);
+    }
+
+    let pushed = repo_data
+        .iter_remote_branch_leaves()
+        .any(|leaf_id| repo_data.iter_version_and_ancestors(leaf_id).any(|v| version_ids.contains(&v.id)));
+    if pushed {
+        return Some(HistoryRewriteBlocker::Pushed);
+    }
+
+    None
+}
+
+/// Removes a chain of versions, newest first, from the middle of history. Their remaining children are moved under the parent of the oldest one and blobs
+/// depending on them are re-encoded against it, so the content of every remaining version stays the same. No branch may point to the removed versions.
+fn remove_from_history(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData, journal: &mut Journal, version_ids: &[VersionId]) -> BiverResult<()> {
+    let oldest_id = *version_ids.last().expect("At least one version must be removed");
+    let new_parent_id = repo_data.version(oldest_id).expect("Removed version must exist").parent;
+
+    let new_parent_content = match new_parent_id {
+        Some(new_parent_id) => Some(repository_io::read_version_content(env, repo_paths, repo_data, new_parent_id)?),
+        None => None,
+    };
+
+    reencode_dependents(env, repo_paths, repo_data, journal, version_ids, new_parent_id.zip(new_parent_content.as_deref()))?;
+
+    let child_ids: Vec<_> = version_ids
+        .iter()
+        .flat_map(|id| repo_data.iter_children(*id))
+        .map(|child| child.id)
+        .filter(|id| !version_ids.contains(id))
+        .collect();
+
+    for child_id in child_ids {
+        repo_data.set_parent(child_id, new_parent_id);
+    }
+
+    erase_versions(env, repo_paths, repo_data, journal, version_ids)
+}
+
+pub enum UndoResult {
+    Ok { operation: String, versioned_file_left_unchanged: bool },
+    NothingToUndo,
+    RepositoryChanged,
+    BlobsDeleted,
+}
+
+/// Restores the repository data to how it was before the most recent operation that has not been undone yet.
+pub fn undo(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData) -> BiverResult<UndoResult> {
+    let entries = operation_log::read_entries(repo_paths)?;
+    let (undoable, _) = operation_log::undo_redo_stacks(&entries);
+
+    let Some(entry) = undoable.last() else {
+        return Ok(UndoResult::NothingToUndo);
+    };
+
+    let change = LogEntryChange {
+        state_before: &entry.after,
+        state_after: &entry.before,
+        removed_versions: &entry.added_versions,
+        added_versions: &entry.removed_versions,
+        replaced_versions: entry.changed_versions.iter().map(|c| (&c.after, &c.before)).collect(),
+    };
+
+    Ok(match apply_log_entry_change(env, repo_paths, repo_data, entry, &change, operation_log::UNDO_OPERATION)? {
+        AppliedLogEntry::Ok { versioned_file_left_unchanged } => UndoResult::Ok {
+            operation: entry.operation.clone(),
+            versioned_file_left_unchanged,
+        },
+        AppliedLogEntry::RepositoryChanged => UndoResult::RepositoryChanged,
+        AppliedLogEntry::BlobsDeleted => UndoResult::BlobsDeleted,
+    })
+}
+
+pub enum RedoResult {
+    Ok { operation: String, versioned_file_left_unchanged: bool },
+    NothingToRedo,
+    RepositoryChanged,
+    BlobsDeleted,
+}
+
+/// Applies the most recently undone operation again, as long as no other operation changed the repository since.
+pub fn redo(env: &Env, repo_paths: &RepositoryPaths, repo_data: &mut RepositoryData) -> BiverResult<RedoResult> {
+    let entries = operation_log::read_entries(repo_paths)?;
+    let (_, redoable) = operation_log::undo_redo_stacks(&entries);
+
+    let Some(entry) = redoable.last() else {
+        return Ok(RedoResult::NothingToRedo);
+    };
+
+    let change = LogEntryChange {
+        state_before: &entry.before,
+        state_after: &entry.after,
+        removed_versions: &entry.removed_versions,
+        added_versions: &entry.added_versions,
+        replaced_versions: entry.changed_versions.iter().map(|c| (&c.before, &c.after)).collect(),
+    };
+
+    Ok(match apply_log_entry_change(env, repo_paths, repo_data, entry, &change, operation_log::REDO_OPERATION)? {
+        AppliedLogEntry::Ok { versioned_file_left_unchanged } => RedoResult::Ok {
+            operation: entry.operation.clone(),
+            versioned_file_left_unchanged,
+        },
+        AppliedLogEntry::RepositoryChanged => RedoResult::RepositoryChanged,
+        AppliedLogEntry::BlobsDeleted => RedoResult::BlobsDeleted,
+    })
+}
+
+/// The changes of an operation log entry in the direction they are applied, backwards for undo and forwards for redo.
+struct LogEntryChange<'a> {
+    state_before: &'a RepositoryState,
+    state_after: &'a RepositoryState,
+    removed_versions: &'a [Version],
+    added_versions: &'a [Version],
+    /// Pairs of the version as it is now and as it is replaced.
+    replaced_versions: Vec<(&'a Version, &'a Version)>,
+}
+
+enum AppliedLogEntry {
+    Ok { versioned_file_left_unchanged: bool },
+    RepositoryChanged,
+    BlobsDeleted,
+}
+
+/// The versioned file follows head unless it has uncommitted changes.
+fn apply_log_entry_change(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &mut RepositoryData,
+    entry: &OperationLogEntry,
+    change: &LogEntryChange,
+    operation: &str,
+) -> BiverResult<AppliedLogEntry> {
+    let repository_matches = RepositoryState::of(repo_data) == *change.state_before
+        && change.removed_versions.iter().all(|v| repo_data.version(v.id) == Some(v))
+        && change.added_versions.iter().all(|v| repo_data.version(v.id).is_none())
+        && change.replaced_versions.iter().all(|(current, _)| repo_data.version(current.id) == Some(*current));
+    if !repository_matches {
+        return Ok(AppliedLogEntry::RepositoryChanged);
+    }
+
+    let restored_versions: Vec<_> = change
+        .added_versions
+        .iter()
+        .chain(change.replaced_versions.iter().map(|(_, replacement)| *replacement))
+        .collect();
+    for version in &restored_versions {
+        if !fs::exists(repo_paths.file_path(&version.content_blob_file_name))? {
+            return Ok(AppliedLogEntry::BlobsDeleted);
+        }
+    }
+
+    let versioned_file_follows_head = fs::exists(&repo_paths.versioned_file)? && !has_uncommitted_changes(repo_paths, repo_data)?;
+    let previous_head_xxh3_128 = repo_data.head_version().versioned_file_xxh3_128;
+
+    let removed_version_ids: Vec<_> = change.removed_versions.iter().map(|v| v.id).collect();
+    repo_data.remove_versions(&removed_version_ids);
+
+    for version in restored_versions {
+        let mut version = version.clone();
+        if let Some(preview_blob_file_name) = &version.preview_blob_file_name
+            && !fs::exists(repo_paths.file_path(preview_blob_file_name))?
+        {
+            version.preview_blob_file_name = None;
+        }
+
+        if repo_data.version(version.id).is_some() {
+            repo_data.replace_version(version);
+        } else {
+            repo_data.add_version(version);
+        }
+    }
+
+    repo_data.storage_mode = change.state_after.storage_mode;
+    repo_data.head = change.state_after.head.clone();
+    repo_data.branches = change.state_after.branches.clone();
+    repo_data.tags = change.state_after.tags.clone();
+    repo_data.remotes = change.state_after.remotes.clone();
+
+    repository_io::write_logged_data(repo_paths, repo_data, operation, Some(entry.number))?;
+
+    let head_version = repo_data.head_version();
+    let head_content_changed = head_version.versioned_file_xxh3_128 != previous_head_xxh3_128;
+
+    if head_content_changed && versioned_file_follows_head {
+        repository_io::extract_version_content(env, repo_paths, repo_data, head_version.id, &repo_paths.versioned_file)?;
+    }
+
+    Ok(AppliedLogEntry::Ok {
+        versioned_file_left_unchanged: head_content_changed && !versioned_file_follows_head,
+    })
+}
+
+pub struct OrphanedFile {
+    pub file_name: String,
+    pub length: u64,
+    /// Referenced by an operation log entry that can still be undone or redone, which deleting the file prevents.
+    pub kept_for_undo: bool,
+}
+
+pub fn orphaned_files(repo_paths: &RepositoryPaths, repo_data: &RepositoryData) -> BiverResult<Vec<OrphanedFile>> {
+    let referenced_file_names: HashSet<&str> = repo_data
+        .versions()
+        .iter()
+        .flat_map(|v| [Some(v.content_blob_file_name.as_str()), v.preview_blob_file_name.as_deref()])
+        .flatten()
+        .collect();
+
+    let log_entries = operation_log::read_entries(repo_paths)?;
+    let (undoable, redoable) = operation_log::undo_redo_stacks(&log_entries);
+    let kept_for_undo_file_names: HashSet<&str> = undoable.iter().chain(redoable.iter()).flat_map(|e| e.referenced_file_names()).collect();
+
+    let mut orphaned_files = Vec::new();
+
+    for entry in fs::read_dir(&repo_paths.repository_dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+
+        if !metadata.is_file() {
+            continue;
+        }
+
+        let Some(file_name) = entry.file_name().to_str().map(str::to_string) else {
+            continue;
+        };
+
+        if repository_io::is_metadata_file(repo_paths, &file_name) || referenced_file_names.contains(file_name.as_str()) {
+            continue;
+        }
+
+        orphaned_files.push(OrphanedFile {
+            kept_for_undo: kept_for_undo_file_names.contains(file_name.as_str()),
+            file_name,
+            length: metadata.len(),
+        });
+    }
+
+    orphaned_files.sort_by(|a, b| a.file_name.cmp(&b.file_name));
+
+    Ok(orphaned_files)
+}
+
+pub fn delete_orphaned_files(repo_paths: &RepositoryPaths, orphaned_files: &[OrphanedFile]) -> BiverResult<()> {
+    for orphaned_file in orphaned_files {
+        fs::remove_file(repo_paths.file_path(&orphaned_file.file_name))?;
+    }
+
+    Ok(())
+}
+
+enum TargetResult<'b, 'v> {
+    Branch(&'b str),
+    Version(&'v Version),
+    Invalid,
+}
+
+fn resolve_target<'b, 'v>(repo_data: &'v RepositoryData, target: &'b str) -> TargetResult<'b, 'v> {
+    if target.is_empty() {
+        return TargetResult::Invalid;
+    }
+
+    // As branch name
+    if repo_data.branches.contains_key(target) {
+        return TargetResult::Branch(target);
+    }
+
+    // As tag name
+    if let Some(version) = repo_data.tags.get(target).and_then(|id| repo_data.version(*id)) {
+        return TargetResult::Version(version);
+    }
+
+    // As remote-tracking branch name
+    if let Some((remote_name, branch)) = target.split_once('/')
+        && let Some(version) = repo_data
+            .remotes
+            .get(remote_name)
+            .and_then(|r| r.branches.get(branch))
+            .and_then(|id| repo_data.version(*id))
+    {
+        return TargetResult::Version(version);
     }
 
     // As version ID
     let target_as_version_id = VersionId::from_bs58(target);
 
     if let Some(target_as_version_id) = target_as_version_id {
-        let version = repo_data.versions.iter().find(|v| v.id == target_as_version_id);
+        let version = repo_data.version(target_as_version_id);
         if let Some(version) = version {
             return TargetResult::Version(version);
         }
@@ -536,7 +2582,7 @@ fn resolve_target<'b, 'v>(repo_data: &'v RepositoryData, target: &'b str) -> Tar
         return TargetResult::Version(repo_data.head_version());
     }
 
-    if target.chars().nth(0) == Some('~')
+    if target.starts_with('~')
         && let Ok(offset) = usize::from_str(&target[1..])
     {
         let target_version = repo_data.iter_head_and_ancestors().nth(offset);
@@ -547,8 +2593,8 @@ fn resolve_target<'b, 'v>(repo_data: &'v RepositoryData, target: &'b str) -> Tar
     }
 
     // As version nickname
-    let mut versions: Vec<_> = repo_data.versions.iter().collect();
-    versions.sort_by(|a, b| b.creation_time.cmp(&a.creation_time));
+    let mut versions: Vec<_> = repo_data.versions().iter().collect();
+    versions.sort_by_key(|v| std::cmp::Reverse(v.creation_time));
 
     let version = versions.iter().find(|v| nickname_matches(&v.nickname, target));
 
@@ -559,21 +2605,12 @@ fn resolve_target<'b, 'v>(repo_data: &'v RepositoryData, target: &'b str) -> Tar
     TargetResult::Invalid
 }
 
-fn resolve_target_strict_mut<'v>(repo_data: &'v mut RepositoryData, target: &str) -> Option<&'v mut Version> {
-    if target.is_empty() {
-        return None;
-    }
-
-    let target_as_version_id = VersionId::from_bs58(target);
-
-    if let Some(target_as_version_id) = target_as_version_id {
-        let version = repo_data.versions.iter_mut().find(|v| v.id == target_as_version_id);
-        if let Some(version) = version {
-            return Some(version);
-        }
+fn resolve_target_version_id(repo_data: &RepositoryData, target: &str) -> Option<VersionId> {
+    match resolve_target(repo_data, target) {
+        TargetResult::Invalid => None,
+        TargetResult::Branch(branch) => Some(repo_data.branches[branch]),
+        TargetResult::Version(version) => Some(version.id),
     }
-
-    None
 }
 
 fn resolve_version_id_target<'v>(repo_data: &'v RepositoryData, target: &str) -> Option<&'v Version> {
@@ -584,7 +2621,7 @@ fn resolve_version_id_target<'v>(repo_data: &'v RepositoryData, target: &str) ->
     let target_as_version_id = VersionId::from_bs58(target);
 
     if let Some(target_as_version_id) = target_as_version_id {
-        let version = repo_data.versions.iter().find(|v| v.id == target_as_version_id);
+        let version = repo_data.version(target_as_version_id);
         if let Some(version) = version {
             return Some(version);
         }
@@ -623,11 +2660,11 @@ fn nickname_matches(nickname: &str, input: &str) -> bool {
             return false;
         }
 
-        let input_initials_first = input.chars().nth(0).unwrap();
+        let input_initials_first = input.chars().next().unwrap();
         let input_initials_second = input.chars().nth(1).unwrap();
 
         let index_of_dash = nickname.find('-').unwrap();
-        let nickname_initials_first = nickname.chars().nth(0).unwrap();
+        let nickname_initials_first = nickname.chars().next().unwrap();
         let nickname_initials_second = nickname.chars().nth(index_of_dash + 1).unwrap();
 
         input_initials_first.eq_ignore_ascii_case(&nickname_initials_first) && input_initials_second.eq_ignore_ascii_case(&nickname_initials_second)
@@ -640,13 +2677,141 @@ fn content_blob_file_name(version_id: VersionId) -> String {
     version_id.to_file_name() + "_content"
 }
 
-fn preview_blob_file_name(env: &Env, repo_paths: &RepositoryPaths, version_id: VersionId) -> Option<String> {
-    if can_create_preview(env, repo_paths) {
-        let file_name = version_id.to_file_name() + "_preview";
-        Some(file_name)
-    } else {
-        None
+fn preview_blob_file_name_of(version_id: VersionId) -> String {
+    version_id.to_file_name() + "_preview"
+}
+
+/// A content blob file name for a version whose blob is being rewritten, so that the existing blob stays intact until the data file is updated.
+/// The names in use are collected once by callers that name many blobs, since collecting them is linear in the number of versions.
+fn unused_content_blob_file_name(repo_paths: &RepositoryPaths, file_names_in_use: &HashSet<String>, version_id: VersionId) -> BiverResult<String> {
+    let base_file_name = content_blob_file_name(version_id);
+    let mut file_name = base_file_name.clone();
+    let mut suffix = 1;
+
+    while fs::exists(repo_paths.file_path(&file_name))? || file_names_in_use.contains(&file_name) {
+        file_name = format!("{}_{}", base_file_name, suffix);
+        suffix += 1;
+    }
+
+    Ok(file_name)
+}
+
+fn content_blob_file_names(repo_data: &RepositoryData) -> HashSet<String> {
+    repo_data.versions().iter().map(|v| v.content_blob_file_name.clone()).collect()
+}
+
+fn record_created_version_files(journal: &mut Journal, content_blob_file_name: &str, preview_blob_file_name: Option<&str>) -> BiverResult<()> {
+    journal.record_created_file(content_blob_file_name)?;
+
+    if let Some(preview_blob_file_name) = preview_blob_file_name {
+        journal.record_created_file(preview_blob_file_name)?;
+    }
+
+    Ok(())
+}
+
+/// Re-encodes the blobs of the versions whose patches apply to any of the removed versions, either as patches against the replacement version or in full.
+/// Versions that the replacement itself depends on are stored in full to keep blob chains acyclic.
+/// The replaced blobs are recorded as obsolete, so that they are deleted once the data file is updated.
+fn reencode_dependents(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &mut RepositoryData,
+    journal: &mut Journal,
+    removed_version_ids: &[VersionId],
+    replacement: Option<(VersionId, &[u8])>,
+) -> BiverResult<()> {
+    let dependent_ids: Vec<_> = removed_version_ids
+        .iter()
+        .flat_map(|removed_id| repo_data.iter_dependents(*removed_id))
+        .filter(|v| !removed_version_ids.contains(&v.id))
+        .map(|v| v.id)
+        .collect();
+    let file_names_in_use = content_blob_file_names(repo_data);
+
+    for dependent_id in dependent_ids {
+        let content = repository_io::read_version_content(env, repo_paths, repo_data, dependent_id)?;
+
+        let file_name = unused_content_blob_file_name(repo_paths, &file_names_in_use, dependent_id)?;
+        let file_path = repo_paths.file_path(&file_name);
+        journal.record_created_file(&file_name)?;
+
+        let (codec, base_id) = match replacement {
+            Some((replacement_id, replacement_content)) if !repo_data.iter_blob_chain(replacement_id).any(|v| v.id == dependent_id) => {
+                repository_io::store_version_content(env, env.patch_codec, replacement_content, &content, &file_path)?;
+                (env.patch_codec, Some(replacement_id))
+            }
+            _ => {
+                repository_io::store_version_content(env, env.full_codec, &[], &content, &file_path)?;
+                (env.full_codec, None)
+            }
+        };
+
+        let previous_file_name = repo_data.set_content_blob(dependent_id, file_name, codec, base_id);
+        journal.record_obsolete_file(&previous_file_name)?;
+    }
+
+    Ok(())
+}
+
+/// In reverse storage mode, the previous branch tip is re-encoded as a patch against the newly committed child, unless it is still a tip of another branch
+/// or the patch would make some chain depending on it exceed the patch chain ratio.
+fn store_parent_as_reverse_patch(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &mut RepositoryData,
+    journal: &mut Journal,
+    child_id: VersionId,
+    child_content: &[u8],
+    parent_content: &[u8],
+) -> BiverResult<()> {
+    let child = repo_data.version(child_id).expect("Child version must exist");
+    let parent_id = child.parent.expect("Child version must have a parent");
+    let parent = repo_data.version(parent_id).expect("Parent version must exist");
+
+    if parent.content_blob_kind().is_patch() || repo_data.branches.values().any(|id| *id == parent_id) {
+        return Ok(());
+    }
+
+    let file_name = unused_content_blob_file_name(repo_paths, &content_blob_file_names(repo_data), parent_id)?;
+    let file_path = repo_paths.file_path(&file_name);
+    journal.record_created_file(&file_name)?;
+
+    let patch_length = repository_io::store_version_content(env, env.patch_codec, child_content, parent_content, &file_path)?;
+
+    if !reverse_patch_fits_patch_chains(env, repo_paths, repo_data, parent_id, patch_length)? {
+        fs::remove_file(&file_path)?;
+        return Ok(());
+    }
+
+    let previous_file_name = repo_data.set_content_blob(parent_id, file_name, env.patch_codec, Some(child_id));
+    journal.record_obsolete_file(&previous_file_name)?;
+
+    Ok(())
+}
+
+/// Checks that turning a full version into a patch of the given length keeps every patch chain passing through it within the patch chain ratio.
+/// Only the version and the versions depending on it, directly or through other patches, have such chains.
+fn reverse_patch_fits_patch_chains(env: &Env, repo_paths: &RepositoryPaths, repo_data: &RepositoryData, version_id: VersionId, patch_length: u64) -> BiverResult<bool> {
+    let version = repo_data.version(version_id).expect("Re-encoded version must exist");
+    let mut pending = vec![(version, patch_length)];
+
+    while let Some((version, patch_chain_length)) = pending.pop() {
+        if patch_chain_length as f64 > version.versioned_file_length as f64 * env.max_patch_chain_ratio {
+            return Ok(false);
+        }
+
+        for dependent in repo_data.iter_dependents(version.id) {
+            let dependent_patch_length = fs::metadata(repo_paths.file_path(&dependent.content_blob_file_name))?.len();
+            pending.push((dependent, patch_chain_length + dependent_patch_length));
+        }
     }
+
+    Ok(true)
+}
+
+fn preview_blob_file_name(env: &Env, repo_paths: &RepositoryPaths, version_id: VersionId) -> Option<String> {
+    can_create_preview(env, repo_paths).then(|| preview_blob_file_name_of(version_id))
 }
 
 fn can_create_preview(env: &Env, repo_paths: &RepositoryPaths) -> bool {
@@ -665,16 +2830,1074 @@ fn valid_branch_name(branch_name: &str) -> bool {
     branch_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
-fn should_convert_patch_to_full(repo_paths: &RepositoryPaths, repo_data: &RepositoryData, parent_id: VersionId, new_patch_length: u64) -> BiverResult<bool> {
-    let versioned_file_length = fs::metadata(&repo_paths.versioned_file)?.len();
+/// Tags also allow dots, for release-like names such as v2.1.
+fn valid_tag_name(tag_name: &str) -> bool {
+    !tag_name.is_empty() && tag_name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+/// Keys may not contain `=`, which separates keys from values in property filters.
+fn valid_property_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(|c| !c.is_whitespace() && !c.is_control() && c != '=')
+}
 
+/// Whether the patch chain ending in a new patch of the content would be too long compared to the length of the content.
+fn should_convert_patch_to_full(
+    env: &Env,
+    repo_paths: &RepositoryPaths,
+    repo_data: &RepositoryData,
+    parent_id: VersionId,
+    content_length: u64,
+    new_patch_length: u64,
+) -> BiverResult<bool> {
     let mut patch_chain_length = new_patch_length;
 
-    let preceding_patch_chain = repo_data.iter_version_and_ancestors(parent_id).take_while(|v| v.content_blob_kind.is_patch());
+    let preceding_patch_chain = repo_data.iter_blob_chain(parent_id).take_while(|v| v.content_blob_kind().is_patch());
 
     for patch_version in preceding_patch_chain {
         patch_chain_length += fs::metadata(repo_paths.file_path(&patch_version.content_blob_file_name))?.len();
     }
 
-    Ok(patch_chain_length > (versioned_file_length * 0.65 as u64))
+    Ok(patch_chain_length as f64 > content_length as f64 * env.max_patch_chain_ratio)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::temp_file;
+    use crate::test_data::pseudo_random_bytes;
+
+    /// A repository in a temporary directory, which is deleted when the repository is dropped.
+    struct TestRepository {
+        env: Env,
+        paths: RepositoryPaths,
+        data: RepositoryData,
+        dir: PathBuf,
+    }
+
+    impl TestRepository {
+        /// The root version has the content of seed 0.
+        fn new(storage_mode: StorageMode) -> Self {
+            let (dir, paths) = new_repository_dir();
+            fs::write(&paths.versioned_file, content(0)).unwrap();
+
+            let env = Env::new(Config::default());
+            assert!(matches!(init(&env, &paths, None, storage_mode, None).unwrap(), InitResult::Ok));
+            let data = read_data(&paths);
+
+            TestRepository { env, paths, data, dir }
+        }
+
+        /// Commits the content of the seed, which is also the description of the new version.
+        fn commit(&mut self, seed: u64) -> VersionId {
+            fs::write(&self.paths.versioned_file, content(seed)).unwrap();
+            assert!(matches!(
+                commit_version(&self.env, &self.paths, &mut self.data, Some(&seed.to_string())).unwrap(),
+                CommitResult::Ok
+            ));
+            self.data.head_version().id
+        }
+
+        /// Creates the branch at head and checks it out.
+        fn create_branch(&mut self, name: &str) {
+            assert!(matches!(create_branch(&self.paths, &mut self.data, name, true).unwrap(), CreateBranchResult::Ok));
+        }
+
+        fn check_out(&mut self, target: &str) {
+            assert!(matches!(check_out(&self.env, &self.paths, &mut self.data, target).unwrap(), CheckOutResult::Ok));
+        }
+
+        /// Clones the repository into a new temporary directory, with this repository as its origin remote.
+        fn clone_repository(&self) -> TestRepository {
+            let (dir, paths) = new_repository_dir();
+            clone_repository(&self.env, &self.paths, &self.data, &paths, true).unwrap();
+            let data = read_data(&paths);
+
+            TestRepository {
+                env: Env::new(Config::default()),
+                paths,
+                data,
+                dir,
+            }
+        }
+
+        /// Imports the bundle into a new temporary directory.
+        fn import_bundle(bundle_path: &Path) -> TestRepository {
+            let VerifyBundleResult::Ok(verified_bundle) = verify_bundle(bundle_path).unwrap() else {
+                panic!("Bundle must be valid");
+            };
+            let (dir, paths) = new_repository_dir();
+            let env = Env::new(Config::default());
+            import_bundle(&env, &paths, *verified_bundle).unwrap();
+            let data = read_data(&paths);
+
+            TestRepository { env, paths, data, dir }
+        }
+
+        fn push(&mut self, origin: &mut TestRepository, branches: &[String]) -> PushResult {
+            push(&self.env, &self.paths, &mut self.data, ORIGIN_REMOTE_NAME, &origin.paths, &mut origin.data, branches).unwrap()
+        }
+
+        fn pull(&mut self, origin: &TestRepository) -> PullResult {
+            pull(&self.env, &self.paths, &mut self.data, ORIGIN_REMOTE_NAME, &origin.paths, &origin.data).unwrap()
+        }
+
+        fn version_content(&self, version_id: VersionId) -> Vec<u8> {
+            repository_io::read_version_content(&self.env, &self.paths, &self.data, version_id).unwrap()
+        }
+
+        /// Fails unless every version can be extracted with the content it was committed with and the data file matches the data in memory.
+        fn assert_consistent(&self) {
+            let report = fsck(&self.env, &self.paths, &self.data).unwrap();
+            assert_eq!(report.corrupted_version_count(), 0);
+            assert_eq!(serde_json::to_value(read_data(&self.paths)).unwrap(), serde_json::to_value(&self.data).unwrap());
+        }
+    }
+
+    impl Drop for TestRepository {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    /// A temporary directory with an empty repository directory for file.bin in it.
+    fn new_repository_dir() -> (PathBuf, RepositoryPaths) {
+        let dir = temp_file::path();
+        fs::create_dir_all(&dir).unwrap();
+
+        let paths = RepositoryPaths::from_versioned_file_path(dir.join("file.bin")).unwrap();
+        fs::create_dir(&paths.repository_dir).unwrap();
+
+        (dir, paths)
+    }
+
+    /// Contents of different seeds share most of their bytes, so that they are stored as small patches of each other.
+    fn content(seed: u64) -> Vec<u8> {
+        let mut content = pseudo_random_bytes(50_000, 1);
+        let position = (seed as usize * 4001) % content.len();
+        content.splice(position..position, pseudo_random_bytes(1000, seed + 100));
+        content
+    }
+
+    fn read_data(paths: &RepositoryPaths) -> RepositoryData {
+        match repository_io::read_data(paths).unwrap() {
+            RepositoryDataResult::Initialized(data) => *data,
+            _ => panic!("Repository must be initialized"),
+        }
+    }
+
+    #[test]
+    fn undoes_two_blob_removing_operations_in_a_row() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let v2 = repo.commit(2);
+        let v3 = repo.commit(3);
+
+        assert!(matches!(reset(&repo.env, &repo.paths, &mut repo.data, &v2.bs58()).unwrap(), ResetResult::Ok));
+        assert!(matches!(reset(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(), ResetResult::Ok));
+        assert!(repo.data.version(v2).is_none() && repo.data.version(v3).is_none());
+
+        let orphans = orphaned_files(&repo.paths, &repo.data).unwrap();
+        assert_eq!(orphans.len(), 2);
+        assert!(orphans.iter().all(|f| f.kept_for_undo));
+
+        assert!(matches!(undo(&repo.env, &repo.paths, &mut repo.data).unwrap(), UndoResult::Ok { .. }));
+        assert!(matches!(undo(&repo.env, &repo.paths, &mut repo.data).unwrap(), UndoResult::Ok { .. }));
+
+        assert_eq!(repo.data.head_version().id, v3);
+        assert_eq!(repo.version_content(v2), content(2));
+        assert_eq!(repo.version_content(v3), content(3));
+        assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(3));
+        assert!(orphaned_files(&repo.paths, &repo.data).unwrap().is_empty());
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn undo_fails_once_gc_deleted_the_blobs_it_needs() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        repo.commit(2);
+
+        assert!(matches!(reset(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(), ResetResult::Ok));
+        delete_orphaned_files(&repo.paths, &orphaned_files(&repo.paths, &repo.data).unwrap()).unwrap();
+
+        assert!(matches!(undo(&repo.env, &repo.paths, &mut repo.data).unwrap(), UndoResult::BlobsDeleted));
+        assert_eq!(repo.data.head_version().id, v1);
+    }
+
+    #[test]
+    fn cherry_pick_commits_the_target_content_as_a_child_of_head() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let (mut repo, [_, v2, _, v4]) = diverged_repository(storage_mode);
+
+            let result = cherry_pick(&repo.env, &repo.paths, &mut repo.data, &v2.bs58()).unwrap();
+
+            assert!(matches!(result, CherryPickResult::Ok));
+            let head = repo.data.head_version();
+            assert_eq!(head.parent, Some(v4));
+            assert_eq!(head.description, format!("2 (cherry-picked from {})", v2.bs58()));
+            assert_eq!(repo.data.branches["main"], head.id);
+            assert_eq!(repo.version_content(head.id), content(2));
+            assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(2));
+            assert_eq!(repo.version_content(v4), content(4));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn cherry_pick_rejects_a_dirty_versioned_file_a_detached_head_and_invalid_targets() {
+        let (mut repo, [v1, v2, _, v4]) = diverged_repository(StorageMode::Forward);
+
+        assert!(matches!(
+            cherry_pick(&repo.env, &repo.paths, &mut repo.data, "none").unwrap(),
+            CherryPickResult::InvalidTarget
+        ));
+        assert!(matches!(
+            cherry_pick(&repo.env, &repo.paths, &mut repo.data, &v4.bs58()).unwrap(),
+            CherryPickResult::SameContentAsHead
+        ));
+        fs::write(&repo.paths.versioned_file, content(9)).unwrap();
+        assert!(matches!(
+            cherry_pick(&repo.env, &repo.paths, &mut repo.data, &v2.bs58()).unwrap(),
+            CherryPickResult::UncommittedChanges
+        ));
+        fs::write(&repo.paths.versioned_file, content(4)).unwrap();
+        repo.check_out(&v1.bs58());
+        assert!(matches!(
+            cherry_pick(&repo.env, &repo.paths, &mut repo.data, &v2.bs58()).unwrap(),
+            CherryPickResult::HeadMustBeOnBranch
+        ));
+        assert_eq!(repo.data.versions().len(), 5);
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn revert_commits_the_target_content_and_leaves_a_dirty_versioned_file_untouched() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let mut repo = TestRepository::new(storage_mode);
+            let v1 = repo.commit(1);
+            let v2 = repo.commit(2);
+            fs::write(&repo.paths.versioned_file, content(9)).unwrap();
+
+            let result = revert(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap();
+
+            assert!(matches!(result, RevertResult::Ok { versioned_file_updated: false }));
+            let head = repo.data.head_version();
+            assert_eq!(head.parent, Some(v2));
+            assert_eq!(head.versioned_file_xxh3_128, repo.data.version(v1).unwrap().versioned_file_xxh3_128);
+            assert_eq!(repo.version_content(head.id), content(1));
+            assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(9));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn revert_updates_a_clean_versioned_file() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        repo.commit(2);
+
+        let result = revert(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap();
+
+        assert!(matches!(result, RevertResult::Ok { versioned_file_updated: true }));
+        assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(1));
+        assert!(matches!(
+            revert(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(),
+            RevertResult::SameContentAsHead
+        ));
+        assert!(matches!(
+            revert(&repo.env, &repo.paths, &mut repo.data, "no-such-version").unwrap(),
+            RevertResult::InvalidTarget
+        ));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn revert_in_reverse_mode_stores_the_new_tip_in_full_and_head_as_a_reverse_patch() {
+        let mut repo = TestRepository::new(StorageMode::Reverse);
+        let v1 = repo.commit(1);
+        let v2 = repo.commit(2);
+        assert!(repo.data.version(v1).unwrap().content_blob_kind().is_patch());
+
+        assert!(matches!(revert(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(), RevertResult::Ok { .. }));
+
+        let head = repo.data.head_version();
+        assert_eq!(head.content_blob_kind(), ContentBlobKind::Full);
+        assert_eq!(repo.data.version(v2).unwrap().patch_base(), Some(head.id));
+        assert_eq!(repo.version_content(v2), content(2));
+        repo.assert_consistent();
+    }
+
+    /// main: 0 - 1 - 4, exp: 1 - 2 - 3, with main checked out.
+    fn diverged_repository(storage_mode: StorageMode) -> (TestRepository, [VersionId; 4]) {
+        let mut repo = TestRepository::new(storage_mode);
+        let v1 = repo.commit(1);
+        repo.create_branch("exp");
+        let v2 = repo.commit(2);
+        let v3 = repo.commit(3);
+        repo.check_out("main");
+        let v4 = repo.commit(4);
+        (repo, [v1, v2, v3, v4])
+    }
+
+    #[test]
+    fn rebase_moves_the_branch_and_keeps_the_content_of_every_version() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let (mut repo, [_, v2, v3, v4]) = diverged_repository(storage_mode);
+
+            let result = rebase(&repo.env, &repo.paths, &mut repo.data, Some("exp"), "main").unwrap();
+
+            assert!(matches!(result, RebaseResult::Ok { moved_version_count: 2 }));
+            assert_eq!(repo.data.version(v2).unwrap().parent, Some(v4));
+            assert_eq!(repo.data.branches["exp"], v3);
+            assert_eq!(repo.version_content(v2), content(2));
+            assert_eq!(repo.version_content(v3), content(3));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn rebase_does_not_need_the_versioned_file() {
+        let (mut repo, [_, v2, _, v4]) = diverged_repository(StorageMode::Forward);
+        fs::remove_file(&repo.paths.versioned_file).unwrap();
+
+        let result = rebase(&repo.env, &repo.paths, &mut repo.data, Some("exp"), "main").unwrap();
+
+        assert!(matches!(result, RebaseResult::Ok { .. }));
+        assert_eq!(repo.data.version(v2).unwrap().parent, Some(v4));
+        assert_eq!(repo.version_content(v2), content(2));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn rebase_rejects_targets_it_cannot_move_the_branch_onto() {
+        let (mut repo, [v1, _, v3, _]) = diverged_repository(StorageMode::Forward);
+
+        assert!(matches!(
+            rebase(&repo.env, &repo.paths, &mut repo.data, Some("none"), "main").unwrap(),
+            RebaseResult::InvalidBranch
+        ));
+        assert!(matches!(
+            rebase(&repo.env, &repo.paths, &mut repo.data, Some("exp"), "none").unwrap(),
+            RebaseResult::InvalidTarget
+        ));
+        assert!(matches!(
+            rebase(&repo.env, &repo.paths, &mut repo.data, Some("exp"), &v3.bs58()).unwrap(),
+            RebaseResult::TargetIsOnBranch
+        ));
+        assert!(matches!(
+            rebase(&repo.env, &repo.paths, &mut repo.data, Some("exp"), &v1.bs58()).unwrap(),
+            RebaseResult::AlreadyOnTarget
+        ));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn drop_moves_children_and_branches_under_the_parent_and_keeps_their_content() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let mut repo = TestRepository::new(storage_mode);
+            let v1 = repo.commit(1);
+            repo.create_branch("exp");
+            let v2 = repo.commit(2);
+            repo.create_branch("other");
+            let v3 = repo.commit(3);
+            repo.check_out("exp");
+
+            let result = drop_version(&repo.env, &repo.paths, &mut repo.data, &v3.bs58()).unwrap();
+            assert!(matches!(result, DropResult::Ok));
+            assert!(repo.data.version(v3).is_none());
+            assert_eq!(repo.data.branches["other"], v2);
+
+            let root_id = repo.data.version(v1).unwrap().parent;
+            let result = drop_version(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap();
+            assert!(matches!(result, DropResult::Ok));
+            assert!(repo.data.version(v1).is_none());
+            assert_eq!(repo.data.version(v2).unwrap().parent, root_id);
+            assert_eq!(Some(repo.data.branches["main"]), root_id);
+            assert_eq!(repo.version_content(v2), content(2));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn drop_rejects_the_root_head_and_tagged_versions() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let v2 = repo.commit(2);
+        let root_id = repo.data.version(v1).unwrap().parent.unwrap();
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "release", &v1.bs58()).unwrap(), CreateTagResult::Ok));
+
+        assert!(matches!(drop_version(&repo.env, &repo.paths, &mut repo.data, "none").unwrap(), DropResult::InvalidTarget));
+        assert!(matches!(
+            drop_version(&repo.env, &repo.paths, &mut repo.data, &root_id.bs58()).unwrap(),
+            DropResult::CannotDropRoot
+        ));
+        assert!(matches!(
+            drop_version(&repo.env, &repo.paths, &mut repo.data, &v2.bs58()).unwrap(),
+            DropResult::CannotDropHead
+        ));
+        assert!(matches!(
+            drop_version(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(),
+            DropResult::VersionIsTagged
+        ));
+        assert_eq!(repo.data.versions().len(), 3);
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn squash_folds_the_range_into_the_newest_version() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let mut repo = TestRepository::new(storage_mode);
+            let v1 = repo.commit(1);
+            let v2 = repo.commit(2);
+            let v3 = repo.commit(3);
+            let v4 = repo.commit(4);
+            let root_id = repo.data.version(v1).unwrap().parent;
+
+            let result = squash(&repo.env, &repo.paths, &mut repo.data, &v1.bs58(), &v3.bs58()).unwrap();
+
+            assert!(matches!(result, SquashResult::Ok { squashed_version_count: 2 }));
+            assert!(repo.data.version(v1).is_none() && repo.data.version(v2).is_none());
+            let newest = repo.data.version(v3).unwrap();
+            assert_eq!(newest.parent, root_id);
+            assert_eq!(newest.description, "1; 2; 3");
+            assert_eq!(repo.data.version(v4).unwrap().parent, Some(v3));
+            assert_eq!(repo.version_content(v3), content(3));
+            assert_eq!(repo.version_content(v4), content(4));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn squash_of_a_single_version_has_nothing_to_squash() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        repo.commit(2);
+        let data_before = serde_json::to_value(&repo.data).unwrap();
+
+        let result = squash(&repo.env, &repo.paths, &mut repo.data, &v1.bs58(), &v1.bs58()).unwrap();
+
+        assert!(matches!(result, SquashResult::NothingToSquash));
+        assert_eq!(serde_json::to_value(&repo.data).unwrap(), data_before);
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn squash_rejects_ranges_it_cannot_fold() {
+        let (mut repo, [v1, v2, v3, v4]) = diverged_repository(StorageMode::Forward);
+        let root_id = repo.data.version(v1).unwrap().parent.unwrap();
+
+        assert!(matches!(
+            squash(&repo.env, &repo.paths, &mut repo.data, &v3.bs58(), &v2.bs58()).unwrap(),
+            SquashResult::NotAnAncestor
+        ));
+        assert!(matches!(
+            squash(&repo.env, &repo.paths, &mut repo.data, &v1.bs58(), &v3.bs58()).unwrap(),
+            SquashResult::VersionHasOtherChildren
+        ));
+        repo.check_out("exp");
+        assert!(matches!(
+            squash(&repo.env, &repo.paths, &mut repo.data, &v2.bs58(), &v4.bs58()).unwrap(),
+            SquashResult::NotAnAncestor
+        ));
+        assert!(matches!(
+            squash(&repo.env, &repo.paths, &mut repo.data, &root_id.bs58(), &v1.bs58()).unwrap(),
+            SquashResult::Ok { squashed_version_count: 1 }
+        ));
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "release", &v2.bs58()).unwrap(), CreateTagResult::Ok));
+        assert!(matches!(
+            squash(&repo.env, &repo.paths, &mut repo.data, &v2.bs58(), &v3.bs58()).unwrap(),
+            SquashResult::VersionIsTagged
+        ));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn create_tag_rejects_invalid_names_existing_tags_and_invalid_targets() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "release", "main").unwrap(), CreateTagResult::Ok));
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "", &v1.bs58()).unwrap(), CreateTagResult::InvalidTagName));
+        assert!(matches!(
+            create_tag(&repo.paths, &mut repo.data, "release", &v1.bs58()).unwrap(),
+            CreateTagResult::TagAlreadyExists
+        ));
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "other", "none").unwrap(), CreateTagResult::InvalidTarget));
+        assert_eq!(repo.data.tags, HashMap::from([("release".to_string(), v1)]));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn delete_tag_keeps_the_tag_when_it_is_all_that_reaches_head() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        repo.commit(1);
+        repo.create_branch("exp");
+        let v2 = repo.commit(2);
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "keep", "exp").unwrap(), CreateTagResult::Ok));
+        repo.check_out(&v2.bs58());
+        assert!(matches!(
+            delete_branch(&repo.env, &repo.paths, &mut repo.data, &"exp".to_string()).unwrap(),
+            DeleteBranchResult::Ok
+        ));
+        let data_before = serde_json::to_value(&repo.data).unwrap();
+
+        let result = delete_tag(&repo.env, &repo.paths, &mut repo.data, "keep").unwrap();
+
+        assert!(matches!(result, DeleteTagResult::HeadWouldBeErased));
+        assert_eq!(serde_json::to_value(&repo.data).unwrap(), data_before);
+        repo.assert_consistent();
+
+        repo.check_out("main");
+        assert!(matches!(delete_tag(&repo.env, &repo.paths, &mut repo.data, "keep").unwrap(), DeleteTagResult::Ok));
+        assert!(repo.data.tags.is_empty());
+        assert!(repo.data.version(v2).is_none());
+        assert!(matches!(
+            delete_tag(&repo.env, &repo.paths, &mut repo.data, "keep").unwrap(),
+            DeleteTagResult::TagDoesNotExist
+        ));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn push_fast_forwards_and_creates_remote_branches_and_updates_the_remote_versioned_file() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let mut origin = TestRepository::new(storage_mode);
+            origin.commit(1);
+            let mut repo = origin.clone_repository();
+            let v2 = repo.commit(2);
+            repo.create_branch("exp");
+            let v3 = repo.commit(3);
+
+            let result = repo.push(&mut origin, &[]);
+
+            let PushResult::Ok { version_count: 2, branches } = result else {
+                panic!("Push must transfer both versions");
+            };
+            assert!(matches!(
+                branches.iter().map(|b| (b.branch.as_str(), &b.status)).collect::<Vec<_>>()[..],
+                [("exp", BranchSyncStatus::Created), ("main", BranchSyncStatus::FastForwarded)]
+            ));
+            assert_eq!(origin.data.branches["main"], v2);
+            assert_eq!(origin.data.branches["exp"], v3);
+            assert_eq!(repo.data.remotes[ORIGIN_REMOTE_NAME].branches, origin.data.branches);
+            assert_eq!(fs::read(&origin.paths.versioned_file).unwrap(), content(2));
+            assert_eq!(origin.version_content(v3), content(3));
+            origin.assert_consistent();
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn push_leaves_diverged_remote_branches_unchanged_and_rejects_invalid_branches() {
+        let mut origin = TestRepository::new(StorageMode::Forward);
+        let mut repo = origin.clone_repository();
+        let v1 = origin.commit(1);
+        repo.commit(2);
+
+        assert!(matches!(repo.push(&mut origin, &["none".to_string()]), PushResult::InvalidBranch(branch) if branch == "none"));
+
+        let PushResult::Ok { branches, .. } = repo.push(&mut origin, &["main".to_string()]) else {
+            panic!("Push must succeed");
+        };
+        assert!(matches!(
+            branches[..],
+            [BranchSync {
+                status: BranchSyncStatus::Diverged,
+                ..
+            }]
+        ));
+        assert_eq!(origin.data.branches["main"], v1);
+        assert_eq!(fs::read(&origin.paths.versioned_file).unwrap(), content(1));
+        origin.assert_consistent();
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn remove_remote_keeps_the_remote_when_it_is_all_that_reaches_head() {
+        let mut origin = TestRepository::new(StorageMode::Forward);
+        origin.create_branch("exp");
+        let v1 = origin.commit(1);
+        let mut repo = origin.clone_repository();
+        repo.check_out(&v1.bs58());
+        assert!(matches!(
+            delete_branch(&repo.env, &repo.paths, &mut repo.data, &"exp".to_string()).unwrap(),
+            DeleteBranchResult::Ok
+        ));
+        let data_before = serde_json::to_value(&repo.data).unwrap();
+
+        let result = remove_remote(&repo.env, &repo.paths, &mut repo.data, ORIGIN_REMOTE_NAME).unwrap();
+
+        assert!(matches!(result, RemoveRemoteResult::HeadWouldBeErased));
+        assert_eq!(serde_json::to_value(&repo.data).unwrap(), data_before);
+        repo.assert_consistent();
+
+        repo.check_out("main");
+        let result = remove_remote(&repo.env, &repo.paths, &mut repo.data, ORIGIN_REMOTE_NAME).unwrap();
+        assert!(matches!(result, RemoveRemoteResult::Ok));
+        assert!(repo.data.remotes.is_empty());
+        assert!(repo.data.version(v1).is_none());
+        assert!(matches!(
+            remove_remote(&repo.env, &repo.paths, &mut repo.data, ORIGIN_REMOTE_NAME).unwrap(),
+            RemoveRemoteResult::RemoteDoesNotExist
+        ));
+        repo.assert_consistent();
+    }
+
+    fn branch_sync_statuses(result: &PullResult) -> Vec<(&str, &BranchSyncStatus)> {
+        match result {
+            PullResult::Ok { branches, .. } => branches.iter().map(|b| (b.branch.as_str(), &b.status)).collect(),
+            PullResult::HeadWouldBeErased => panic!("Pull must succeed"),
+        }
+    }
+
+    #[test]
+    fn pull_fast_forwards_and_creates_branches_and_updates_a_clean_versioned_file() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let mut origin = TestRepository::new(storage_mode);
+            origin.commit(1);
+            let mut repo = origin.clone_repository();
+            let v2 = origin.commit(2);
+            origin.create_branch("exp");
+            let v3 = origin.commit(3);
+
+            let result = repo.pull(&origin);
+
+            assert!(matches!(result, PullResult::Ok { version_count: 2, .. }));
+            assert!(matches!(
+                branch_sync_statuses(&result)[..],
+                [("exp", BranchSyncStatus::Created), ("main", BranchSyncStatus::FastForwarded)]
+            ));
+            assert_eq!(repo.data.branches["main"], v2);
+            assert_eq!(repo.data.branches["exp"], v3);
+            assert_eq!(repo.data.remotes[ORIGIN_REMOTE_NAME].branches, origin.data.branches);
+            assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(2));
+            assert_eq!(repo.version_content(v3), content(3));
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn pull_leaves_diverged_branches_and_a_dirty_checked_out_branch_unchanged() {
+        let mut origin = TestRepository::new(StorageMode::Forward);
+        origin.create_branch("exp");
+        origin.check_out("main");
+        let mut repo = origin.clone_repository();
+        let v1 = origin.commit(1);
+        origin.check_out("exp");
+        let v2 = origin.commit(2);
+        repo.check_out("exp");
+        let v3 = repo.commit(3);
+        repo.check_out("main");
+        fs::write(&repo.paths.versioned_file, content(9)).unwrap();
+
+        let result = repo.pull(&origin);
+
+        assert!(matches!(
+            branch_sync_statuses(&result)[..],
+            [("exp", BranchSyncStatus::Diverged), ("main", BranchSyncStatus::BlockedByUncommittedChanges)]
+        ));
+        assert_eq!(repo.data.branches["exp"], v3);
+        assert_ne!(repo.data.branches["main"], v1);
+        assert_eq!(repo.data.remotes[ORIGIN_REMOTE_NAME].branches["exp"], v2);
+        assert_eq!(repo.data.remotes[ORIGIN_REMOTE_NAME].branches["main"], v1);
+        assert_eq!(fs::read(&repo.paths.versioned_file).unwrap(), content(9));
+        assert_eq!(repo.version_content(v2), content(2));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn pull_keeps_a_detached_head_that_a_tag_reaches() {
+        let mut origin = TestRepository::new(StorageMode::Forward);
+        origin.commit(1);
+        origin.create_branch("exp");
+        let v2 = origin.commit(2);
+        let mut repo = origin.clone_repository();
+        repo.check_out(&v2.bs58());
+        assert!(matches!(
+            delete_branch(&repo.env, &repo.paths, &mut repo.data, &"exp".to_string()).unwrap(),
+            DeleteBranchResult::Ok
+        ));
+        origin.check_out("main");
+        assert!(matches!(
+            delete_branch(&origin.env, &origin.paths, &mut origin.data, &"exp".to_string()).unwrap(),
+            DeleteBranchResult::Ok
+        ));
+
+        assert!(matches!(repo.pull(&origin), PullResult::HeadWouldBeErased));
+        assert!(repo.data.remotes[ORIGIN_REMOTE_NAME].branches.contains_key("exp"));
+
+        assert!(matches!(create_tag(&repo.paths, &mut repo.data, "keep", &v2.bs58()).unwrap(), CreateTagResult::Ok));
+        assert!(matches!(repo.pull(&origin), PullResult::Ok { .. }));
+
+        assert_eq!(repo.data.head_version().id, v2);
+        assert!(!repo.data.remotes[ORIGIN_REMOTE_NAME].branches.contains_key("exp"));
+        assert_eq!(repo.version_content(v2), content(2));
+        repo.assert_consistent();
+    }
+
+    /// Fails unless branch tips are stored in full and every other version is a patch against one of its children.
+    fn assert_reverse_layout(repo: &TestRepository) {
+        for version in repo.data.versions() {
+            if repo.data.branches.values().any(|id| *id == version.id) {
+                assert!(version.content_blob_kind().is_full());
+            } else {
+                let base_id = version.patch_base().expect("Versions other than branch tips must be patches");
+                assert_eq!(repo.data.version(base_id).unwrap().parent, Some(version.id));
+            }
+        }
+    }
+
+    #[test]
+    fn reverse_mode_stores_branch_tips_in_full_and_other_versions_as_patches_of_a_child() {
+        let (mut repo, [v1, v2, v3, v4]) = diverged_repository(StorageMode::Reverse);
+
+        assert_reverse_layout(&repo);
+
+        repo.check_out("exp");
+        let v5 = repo.commit(5);
+        assert_reverse_layout(&repo);
+        for (version_id, seed) in [(v1, 1), (v2, 2), (v3, 3), (v4, 4), (v5, 5)] {
+            assert_eq!(repo.version_content(version_id), content(seed));
+        }
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn repack_converts_between_forward_and_reverse_storage() {
+        let (mut repo, [v1, v2, v3, v4]) = diverged_repository(StorageMode::Forward);
+
+        for storage_mode in [StorageMode::Reverse, StorageMode::Forward] {
+            let policy = RepackPolicy {
+                max_chain_depth: None,
+                max_patch_chain_ratio: repo.env.max_patch_chain_ratio,
+                storage_mode,
+            };
+            assert!(matches!(repack(&repo.env, &repo.paths, &mut repo.data, &policy).unwrap(), RepackResult::Ok { .. }));
+
+            assert_eq!(repo.data.storage_mode, storage_mode);
+            if storage_mode == StorageMode::Reverse {
+                assert_reverse_layout(&repo);
+            } else {
+                assert!(repo.data.versions().iter().all(|v| v.is_root() || v.patch_base() == v.parent));
+            }
+            for (version_id, seed) in [(v1, 1), (v2, 2), (v3, 3), (v4, 4)] {
+                assert_eq!(repo.version_content(version_id), content(seed));
+            }
+            repo.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn repack_limits_chain_depth_and_keeps_the_replaced_blobs_until_undo() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let version_ids: Vec<_> = (1..=6).map(|seed| repo.commit(seed)).collect();
+        let policy = RepackPolicy {
+            max_chain_depth: Some(2),
+            max_patch_chain_ratio: repo.env.max_patch_chain_ratio,
+            storage_mode: StorageMode::Forward,
+        };
+
+        let RepackResult::Ok {
+            before,
+            after,
+            kept_for_undo_length,
+        } = repack(&repo.env, &repo.paths, &mut repo.data, &policy).unwrap()
+        else {
+            panic!("Repack must succeed");
+        };
+
+        assert_eq!(before.max_chain_depth, 6);
+        assert!(after.max_chain_depth <= 2);
+        assert!(after.full_blob_count > before.full_blob_count);
+        assert!(kept_for_undo_length > 0);
+        for (version_id, seed) in version_ids.iter().zip(1..) {
+            assert_eq!(repo.version_content(*version_id), content(seed));
+        }
+        let orphans = orphaned_files(&repo.paths, &repo.data).unwrap();
+        assert!(!orphans.is_empty() && orphans.iter().all(|f| f.kept_for_undo));
+        repo.assert_consistent();
+
+        assert!(matches!(undo(&repo.env, &repo.paths, &mut repo.data).unwrap(), UndoResult::Ok { .. }));
+        assert_eq!(storage_stats(&repo.paths, &repo.data).unwrap().max_chain_depth, 6);
+        for (version_id, seed) in version_ids.iter().zip(1..) {
+            assert_eq!(repo.version_content(*version_id), content(seed));
+        }
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn gc_reports_only_files_that_nothing_references() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        repo.commit(2);
+        fs::write(repo.paths.file_path("stray"), b"stray").unwrap();
+        assert!(orphaned_files(&repo.paths, &repo.data).unwrap().iter().map(|f| f.file_name.as_str()).eq(["stray"]));
+
+        assert!(matches!(reset(&repo.env, &repo.paths, &mut repo.data, &v1.bs58()).unwrap(), ResetResult::Ok));
+        let orphans = orphaned_files(&repo.paths, &repo.data).unwrap();
+
+        assert_eq!(orphans.len(), 2);
+        let stray = orphans.iter().find(|f| f.file_name == "stray").unwrap();
+        assert!(!stray.kept_for_undo);
+        assert_eq!(stray.length, 5);
+        assert!(orphans.iter().filter(|f| f.file_name != "stray").all(|f| f.kept_for_undo));
+
+        delete_orphaned_files(&repo.paths, &orphans).unwrap();
+
+        assert!(orphaned_files(&repo.paths, &repo.data).unwrap().is_empty());
+        assert!(!fs::exists(repo.paths.file_path("stray")).unwrap());
+        assert_eq!(repo.version_content(v1), content(1));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn bundle_of_all_branches_imports_every_version_branch_and_tag() {
+        for storage_mode in [StorageMode::Forward, StorageMode::Reverse] {
+            let (mut repo, version_ids) = diverged_repository(storage_mode);
+            assert!(matches!(
+                create_tag(&repo.paths, &mut repo.data, "release", &version_ids[1].bs58()).unwrap(),
+                CreateTagResult::Ok
+            ));
+            let bundle_path = repo.dir.join("repo.bundle");
+            let selection = BundleSelection { branches: vec![], since: None };
+
+            let result = create_bundle(&repo.env, &repo.paths, &repo.data, &selection, &bundle_path).unwrap();
+            assert!(matches!(
+                result,
+                CreateBundleResult::Ok {
+                    version_count: 5,
+                    branch_count: 2
+                }
+            ));
+            let imported = TestRepository::import_bundle(&bundle_path);
+
+            assert_eq!(imported.data.branches, repo.data.branches);
+            assert_eq!(imported.data.tags, repo.data.tags);
+            assert_eq!(imported.data.head, repo.data.head);
+            assert_eq!(imported.data.storage_mode, storage_mode);
+            for (version_id, seed) in version_ids.into_iter().zip(1..) {
+                assert_eq!(imported.version_content(version_id), content(seed));
+            }
+            assert_eq!(fs::read(&imported.paths.versioned_file).unwrap(), content(4));
+            imported.assert_consistent();
+        }
+    }
+
+    #[test]
+    fn bundle_since_a_version_starts_the_history_there_and_stores_it_in_full() {
+        let (repo, [v1, v2, v3, _]) = diverged_repository(StorageMode::Forward);
+        let bundle_path = repo.dir.join("repo.bundle");
+        let selection = BundleSelection {
+            branches: vec!["exp".to_string()],
+            since: Some(v2.bs58()),
+        };
+
+        let result = create_bundle(&repo.env, &repo.paths, &repo.data, &selection, &bundle_path).unwrap();
+        assert!(matches!(
+            result,
+            CreateBundleResult::Ok {
+                version_count: 2,
+                branch_count: 1
+            }
+        ));
+        let imported = TestRepository::import_bundle(&bundle_path);
+
+        assert!(imported.data.version(v1).is_none());
+        let first = imported.data.version(v2).unwrap();
+        assert!(first.is_root() && first.content_blob_kind().is_full());
+        assert_eq!(imported.data.branches, HashMap::from([("exp".to_string(), v3)]));
+        assert_eq!(imported.version_content(v2), content(2));
+        assert_eq!(imported.version_content(v3), content(3));
+        imported.assert_consistent();
+
+        let selection = BundleSelection {
+            branches: vec!["main".to_string()],
+            since: Some(v2.bs58()),
+        };
+        let result = create_bundle(&repo.env, &repo.paths, &repo.data, &selection, &repo.dir.join("other.bundle")).unwrap();
+        assert!(matches!(result, CreateBundleResult::SinceNotOnBranch(branch) if branch == "main"));
+    }
+
+    #[test]
+    fn verify_bundle_rejects_corrupted_bundles_and_other_files() {
+        let (repo, _) = diverged_repository(StorageMode::Forward);
+        let bundle_path = repo.dir.join("repo.bundle");
+        let selection = BundleSelection { branches: vec![], since: None };
+        assert!(matches!(
+            create_bundle(&repo.env, &repo.paths, &repo.data, &selection, &bundle_path).unwrap(),
+            CreateBundleResult::Ok { .. }
+        ));
+
+        let mut bundle = fs::read(&bundle_path).unwrap();
+        let middle = bundle.len() / 2;
+        bundle[middle] ^= 0xFF;
+        fs::write(&bundle_path, bundle).unwrap();
+
+        assert!(matches!(verify_bundle(&bundle_path).unwrap(), VerifyBundleResult::CorruptedFiles(files) if !files.is_empty()));
+        assert!(matches!(verify_bundle(&repo.paths.versioned_file).unwrap(), VerifyBundleResult::NotABundle));
+    }
+
+    #[test]
+    fn data_written_before_format_versions_is_migrated_and_written_in_the_current_format() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let current_data = serde_json::to_value(&repo.data).unwrap();
+
+        let mut unversioned_data = current_data.clone();
+        let data = unversioned_data.as_object_mut().unwrap();
+        for key in ["format_version", "storage_mode", "remotes", "tags"] {
+            data.remove(key);
+        }
+        for version in data["versions"].as_array_mut().unwrap() {
+            let version = version.as_object_mut().unwrap();
+            let kind = if version["content_blob_codec"] == "Xdelta3" { "Patch" } else { "Full" };
+            version.insert("content_blob_kind".to_string(), kind.into());
+            for key in ["content_blob_codec", "author", "properties"] {
+                version.remove(key);
+            }
+        }
+        fs::write(&repo.paths.data_file, serde_json::to_vec(&unversioned_data).unwrap()).unwrap();
+
+        repo.data = read_data(&repo.paths);
+
+        assert_eq!(serde_json::to_value(&repo.data).unwrap(), current_data);
+        let v2 = repo.commit(2);
+        let written_data: serde_json::Value = serde_json::from_slice(&fs::read(&repo.paths.data_file).unwrap()).unwrap();
+        assert_eq!(written_data["format_version"], data_migration::CURRENT_FORMAT_VERSION);
+        assert_eq!(repo.version_content(v1), content(1));
+        assert_eq!(repo.version_content(v2), content(2));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn data_of_a_newer_format_version_is_not_read() {
+        let repo = TestRepository::new(StorageMode::Forward);
+        let mut data = serde_json::to_value(&repo.data).unwrap();
+        data["format_version"] = (data_migration::CURRENT_FORMAT_VERSION + 1).into();
+        fs::write(&repo.paths.data_file, serde_json::to_vec(&data).unwrap()).unwrap();
+
+        let result = repository_io::read_data(&repo.paths).unwrap();
+
+        assert!(matches!(result, RepositoryDataResult::NewerFormatVersion(v) if v == data_migration::CURRENT_FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn restore_backup_replaces_the_data_and_can_be_undone() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let root_id = repo.data.head_version().id;
+        let v1 = repo.commit(1);
+        repo.commit(2);
+        let data_before = serde_json::to_value(&repo.data).unwrap();
+
+        assert!(matches!(restore_backup(&repo.paths, 1).unwrap(), RestoreBackupResult::Ok));
+
+        repo.data = read_data(&repo.paths);
+        assert_eq!(repo.data.versions().len(), 1);
+        assert_eq!(repo.data.branches["main"], root_id);
+        repo.assert_consistent();
+
+        assert!(matches!(undo(&repo.env, &repo.paths, &mut repo.data).unwrap(), UndoResult::Ok { .. }));
+        assert_eq!(serde_json::to_value(&repo.data).unwrap(), data_before);
+        assert_eq!(repo.version_content(v1), content(1));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn restore_backup_rejects_missing_unreadable_and_incomplete_backups() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let root_blob_file_name = repo.data.head_version().content_blob_file_name.clone();
+        repo.commit(1);
+
+        assert!(matches!(restore_backup(&repo.paths, 0).unwrap(), RestoreBackupResult::BackupDoesNotExist));
+        assert!(matches!(restore_backup(&repo.paths, 2).unwrap(), RestoreBackupResult::BackupDoesNotExist));
+
+        let root_blob = fs::read(repo.paths.file_path(&root_blob_file_name)).unwrap();
+        fs::remove_file(repo.paths.file_path(&root_blob_file_name)).unwrap();
+        assert!(matches!(restore_backup(&repo.paths, 1).unwrap(), RestoreBackupResult::MissingBlobs(file_names) if file_names == [root_blob_file_name.clone()]));
+        fs::write(repo.paths.file_path(&root_blob_file_name), root_blob).unwrap();
+
+        fs::write(repo.paths.file_path("data_backup1.json"), b"garbage").unwrap();
+        assert!(matches!(restore_backup(&repo.paths, 1).unwrap(), RestoreBackupResult::BackupUnreadable));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn recovery_rolls_back_an_operation_interrupted_before_writing_the_data_file() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let mut journal = repository_io::begin_operation(&repo.paths, "commit").unwrap();
+        journal.record_created_file("new_blob").unwrap();
+        fs::write(repo.paths.file_path("new_blob"), b"new").unwrap();
+        drop(journal);
+
+        repository_io::recover_interrupted_operation(&repo.paths).unwrap();
+
+        assert!(!fs::exists(repo.paths.file_path("new_blob")).unwrap());
+        assert!(!fs::exists(repo.paths.file_path("journal.json")).unwrap());
+        assert!(orphaned_files(&repo.paths, &repo.data).unwrap().is_empty());
+        assert_eq!(repo.version_content(v1), content(1));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn recovery_completes_an_operation_interrupted_after_writing_the_data_file() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let v1_blob_file_name = repo.data.version(v1).unwrap().content_blob_file_name.clone();
+        let mut journal = repository_io::begin_operation(&repo.paths, "repack").unwrap();
+        journal.record_created_file(&v1_blob_file_name).unwrap();
+        journal.record_obsolete_file("replaced_blob").unwrap();
+        fs::write(repo.paths.file_path("replaced_blob"), b"replaced").unwrap();
+        drop(journal);
+
+        repository_io::recover_interrupted_operation(&repo.paths).unwrap();
+
+        assert!(fs::exists(repo.paths.file_path(&v1_blob_file_name)).unwrap());
+        assert!(!fs::exists(repo.paths.file_path("replaced_blob")).unwrap());
+        assert!(!fs::exists(repo.paths.file_path("journal.json")).unwrap());
+        assert_eq!(repo.version_content(v1), content(1));
+        repo.assert_consistent();
+    }
+
+    #[test]
+    fn fsck_reports_missing_and_corrupted_blobs_and_the_versions_that_depend_on_them() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        let v2 = repo.commit(2);
+        let v3 = repo.commit(3);
+        let root = repo.data.version(repo.data.version(v1).unwrap().parent.unwrap()).unwrap();
+        let root_blob_path = repo.paths.file_path(&root.content_blob_file_name);
+        let mut root_blob = fs::read(&root_blob_path).unwrap();
+        root_blob[30_000] ^= 0xFF;
+        fs::write(&root_blob_path, root_blob).unwrap();
+        let v2_blob_file_name = repo.data.version(v2).unwrap().content_blob_file_name.clone();
+        fs::remove_file(repo.paths.file_path(&v2_blob_file_name)).unwrap();
+
+        let report = fsck(&repo.env, &repo.paths, &repo.data).unwrap();
+        let problems = |version_id| &report.version_checks.iter().find(|c| c.version_id == version_id).unwrap().problems[..];
+
+        assert!(report.graph_valid);
+        assert_eq!(report.corrupted_version_count(), 4);
+        assert!(matches!(problems(root.id), [VersionProblem::HashMismatch]));
+        assert!(matches!(problems(v2), [VersionProblem::MissingContentBlob(file_name)] if *file_name == v2_blob_file_name));
+        assert!(matches!(problems(v3), [VersionProblem::BrokenPatchChain(id)] if *id == v2));
+    }
+
+    #[test]
+    fn fsck_reports_patches_that_need_a_missing_xdelta3_executable() {
+        let mut repo = TestRepository::new(StorageMode::Forward);
+        let v1 = repo.commit(1);
+        repo.env.xdelta3_path = Some("/nonexistent/xdelta3".into());
+
+        let version = repo.data.version(v1).unwrap();
+        assert_eq!(version.content_blob_codec, ContentBlobCodec::Xdelta3);
+        // A VCDIFF header announcing DJW secondary compression, which only the executable decodes.
+        fs::write(repo.paths.file_path(&version.content_blob_file_name), [0xD6, 0xC3, 0xC4, 0x00, 0x01, 0x01]).unwrap();
+
+        let report = fsck(&repo.env, &repo.paths, &repo.data).unwrap();
+        let check = report.version_checks.iter().find(|c| c.version_id == v1).unwrap();
+
+        assert!(matches!(check.problems[..], [VersionProblem::NeedsXdelta3(_)]));
+    }
 }
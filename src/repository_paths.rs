@@ -1,5 +1,8 @@
+use serde::{Deserialize, Serialize};
 use std::ffi::OsString;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+use uuid::Uuid;
 
 pub struct RepositoryPaths {
     pub versioned_file: PathBuf,
@@ -7,29 +10,119 @@ pub struct RepositoryPaths {
     pub data_file: PathBuf,
 }
 
+/// Sibling repositories live in a directory next to the versioned file. Central repositories live in the central store,
+/// and only a small pointer file is kept next to the versioned file, in place of the directory.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RepositoryLayout {
+    Sibling,
+    Central,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RepositoryPointer {
+    repository_id: Uuid,
+}
+
 impl RepositoryPaths {
-    pub fn from_versioned_file_path(versioned_file_path: PathBuf) -> Self {
-        let extension = match versioned_file_path.extension() {
-            Some(extension) => {
-                let mut extension = OsString::from(extension);
-                extension.push(".biver");
-                extension
-            }
-            None => OsString::from("biver"),
-        };
+    pub fn from_versioned_file_path(versioned_file_path: PathBuf) -> io::Result<Self> {
+        let sibling_path = sibling_path(&versioned_file_path);
+
+        if sibling_path.is_file() {
+            let pointer = read_pointer(&sibling_path)?;
+            let repository_dir = central_repository_dir(pointer.repository_id)?;
+            return Ok(Self::new(versioned_file_path, repository_dir));
+        }
+
+        Ok(Self::new(versioned_file_path, sibling_path))
+    }
 
-        let repository_dir_path = versioned_file_path.with_extension(extension);
+    /// Creates an empty repository directory in the central store and the pointer file that leads to it.
+    pub fn create_central(versioned_file_path: PathBuf) -> io::Result<Self> {
+        let pointer_file = sibling_path(&versioned_file_path);
+        let pointer = RepositoryPointer { repository_id: Uuid::new_v4() };
+        let repository_dir = central_repository_dir(pointer.repository_id)?;
 
-        let data_file_path = repository_dir_path.join("data.json");
+        fs::create_dir_all(&repository_dir)?;
+        fs::write(&pointer_file, serde_json::to_string_pretty(&pointer)?)?;
+
+        Ok(Self::new(versioned_file_path, repository_dir))
+    }
+
+    fn new(versioned_file: PathBuf, repository_dir: PathBuf) -> Self {
+        let data_file = repository_dir.join("data.json");
 
         RepositoryPaths {
-            versioned_file: versioned_file_path,
-            repository_dir: repository_dir_path,
-            data_file: data_file_path,
+            versioned_file,
+            repository_dir,
+            data_file,
         }
     }
 
     pub fn file_path(&self, file_name: &str) -> PathBuf {
-        self.repository_dir.join(&file_name)
+        self.repository_dir.join(file_name)
+    }
+}
+
+/// The sibling repository directory, or the pointer file of a central repository.
+pub fn sibling_path(versioned_file_path: &Path) -> PathBuf {
+    let extension = match versioned_file_path.extension() {
+        Some(extension) => {
+            let mut extension = OsString::from(extension);
+            extension.push(".biver");
+            extension
+        }
+        None => OsString::from("biver"),
+    };
+
+    versioned_file_path.with_extension(extension)
+}
+
+/// Repositories given to remote and clone commands may be named by their versioned file or by their sibling repository directory.
+pub fn versioned_file_path_of_repository(path: &Path) -> PathBuf {
+    if path.is_dir()
+        && let Some(versioned_file_path) = versioned_file_path_of_sibling(path)
+    {
+        return versioned_file_path;
     }
+
+    path.to_path_buf()
+}
+
+/// The versioned file of a sibling repository directory, the reverse of `sibling_path`.
+fn versioned_file_path_of_sibling(repository_dir: &Path) -> Option<PathBuf> {
+    let file_name = repository_dir.file_name()?.to_str()?;
+    let versioned_file_name = file_name.strip_suffix(".biver").filter(|n| !n.is_empty())?;
+    Some(repository_dir.with_file_name(versioned_file_name))
+}
+
+fn read_pointer(pointer_file_path: &Path) -> io::Result<RepositoryPointer> {
+    let content = fs::read(pointer_file_path)?;
+
+    serde_json::from_slice(&content).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid repository pointer file {}: {}", pointer_file_path.display(), e),
+        )
+    })
+}
+
+fn central_repository_dir(repository_id: Uuid) -> io::Result<PathBuf> {
+    match central_store_dir() {
+        Some(central_store_dir) => Ok(central_store_dir.join(repository_id.to_string())),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "cannot determine the central store directory")),
+    }
+}
+
+/// The biver directory in the XDG data directory (or the local application data directory on Windows).
+fn central_store_dir() -> Option<PathBuf> {
+    let data_dir = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|p| p.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+    };
+
+    data_dir.map(|dir| dir.join("biver"))
 }
@@ -0,0 +1,25 @@
+//! Content for codec tests.
+
+/// Deterministic pseudo-random bytes, so that failures are reproducible.
+pub fn pseudo_random_bytes(length: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1;
+    (0..length)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// The source with some bytes changed, a block inserted and a block removed.
+pub fn edited(source: &[u8]) -> Vec<u8> {
+    let mut target = source.to_vec();
+    for i in (0..target.len()).step_by(997) {
+        target[i] = target[i].wrapping_add(1);
+    }
+    target.splice(target.len() / 3..target.len() / 3, pseudo_random_bytes(5000, 7));
+    target.drain(target.len() / 2..target.len() / 2 + target.len() / 10);
+    target
+}
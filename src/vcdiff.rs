@@ -0,0 +1,786 @@
+//! In-process VCDIFF (RFC 3284) encoder and decoder.
+//!
+//! The decoder understands the subset of the format produced by `xdelta3 -e`: the default code table,
+//! the xdelta3 application header, the xdelta3 Adler-32 window checksum and xdelta3's LZMA secondary compression,
+//! which xdelta3 builds with liblzma use by default. Deltas that use xdelta3's DJW or FGK secondary compression
+//! or a custom code table are reported as `io::ErrorKind::Unsupported`.
+
+use crate::block_matching;
+use std::borrow::Cow;
+use std::io;
+
+const MAGIC: [u8; 4] = [0xD6, 0xC3, 0xC4, 0x00];
+
+const HDR_DECOMPRESS: u8 = 0x01;
+const HDR_CODETABLE: u8 = 0x02;
+const HDR_APPHEADER: u8 = 0x04;
+
+const WIN_SOURCE: u8 = 0x01;
+const WIN_TARGET: u8 = 0x02;
+const WIN_ADLER32: u8 = 0x04;
+
+const VCD_DATACOMP: u8 = 0x01;
+const VCD_INSTCOMP: u8 = 0x02;
+const VCD_ADDRCOMP: u8 = 0x04;
+
+/// Secondary compressor ids that xdelta3 writes after the header indicator.
+const DJW_ID: u8 = 1;
+const LZMA_ID: u8 = 2;
+const FGK_ID: u8 = 16;
+
+const NEAR_CACHE_SIZE: usize = 4;
+const SAME_CACHE_SIZE: usize = 3;
+
+const MODE_SELF: u8 = 0;
+const MODE_HERE: u8 = 1;
+
+const TARGET_WINDOW_SIZE: usize = 1 << 23;
+
+/// Windows written by xdelta3 and by this encoder are far smaller. Larger windows are taken as corruption rather than allocated.
+const MAX_DECODED_WINDOW_SIZE: usize = 1 << 27;
+
+pub fn encode(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let instructions = find_instructions(source, target);
+
+    let mut delta = Vec::with_capacity(target.len() / 4 + 64);
+    delta.extend_from_slice(&MAGIC);
+    delta.push(0); // Hdr_Indicator
+
+    let mut instructions = instructions.into_iter().peekable();
+    let mut window_start = 0;
+
+    loop {
+        let window_end = (window_start + TARGET_WINDOW_SIZE).min(target.len());
+
+        let mut window = WindowEncoder::new(source.len());
+
+        while window.target_length < window_end - window_start {
+            let Some(instruction) = instructions.peek_mut() else {
+                break;
+            };
+
+            let room = window_end - window_start - window.target_length;
+
+            match instruction {
+                Instruction::Add { start, length } => {
+                    let taken = (*length).min(room);
+                    window.add(&target[*start..*start + taken]);
+                    *start += taken;
+                    *length -= taken;
+                }
+                Instruction::Copy { address, length } => {
+                    let taken = (*length).min(room);
+                    window.copy(*address, taken);
+                    *address += taken;
+                    *length -= taken;
+                }
+            }
+
+            if instruction.is_empty() {
+                instructions.next();
+            }
+        }
+
+        window.write(&mut delta);
+
+        window_start = window_end;
+        if window_start >= target.len() {
+            break;
+        }
+    }
+
+    delta
+}
+
+pub fn decode(source: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let mut reader = Reader::new(delta);
+
+    if reader.bytes(4)? != MAGIC {
+        return Err(invalid_data("not a VCDIFF delta"));
+    }
+
+    let header_indicator = reader.byte()?;
+
+    let secondary_compressor = if header_indicator & HDR_DECOMPRESS != 0 {
+        match reader.byte()? {
+            LZMA_ID => Some(LZMA_ID),
+            DJW_ID => return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF secondary compression DJW is not supported")),
+            FGK_ID => return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF secondary compression FGK is not supported")),
+            _ => return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF secondary compressor is unknown")),
+        }
+    } else {
+        None
+    };
+
+    if header_indicator & HDR_CODETABLE != 0 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "VCDIFF custom code tables are not supported"));
+    }
+
+    if header_indicator & HDR_APPHEADER != 0 {
+        let app_header_length = reader.size()?;
+        reader.bytes(app_header_length)?;
+    }
+
+    let code_table = default_code_table();
+    let mut target = Vec::new();
+
+    while !reader.is_at_end() {
+        decode_window(&mut reader, &code_table, secondary_compressor, source, &mut target)?;
+    }
+
+    Ok(target)
+}
+
+fn decode_window(reader: &mut Reader, code_table: &[CodeTableEntry; 256], secondary_compressor: Option<u8>, source: &[u8], target: &mut Vec<u8>) -> io::Result<()> {
+    let window_indicator = reader.byte()?;
+
+    let segment: &[u8] = if window_indicator & (WIN_SOURCE | WIN_TARGET) != 0 {
+        let segment_length = reader.size()?;
+        let segment_position = reader.size()?;
+
+        let segment_origin: &[u8] = if window_indicator & WIN_SOURCE != 0 { source } else { target };
+
+        let segment_end = segment_position.checked_add(segment_length).ok_or_else(|| invalid_data("VCDIFF segment out of range"))?;
+        if segment_end > segment_origin.len() {
+            return Err(invalid_data("VCDIFF segment out of range"));
+        }
+
+        &segment_origin[segment_position..segment_end]
+    } else {
+        &[]
+    };
+
+    let _delta_encoding_length = reader.size()?;
+    let target_window_length = reader.size()?;
+    if target_window_length > MAX_DECODED_WINDOW_SIZE {
+        return Err(invalid_data("VCDIFF window is too large"));
+    }
+
+    let delta_indicator = reader.byte()?;
+    if delta_indicator & !(VCD_DATACOMP | VCD_INSTCOMP | VCD_ADDRCOMP) != 0 || (delta_indicator != 0 && secondary_compressor.is_none()) {
+        return Err(invalid_data("VCDIFF window compresses sections without a secondary compressor"));
+    }
+
+    let data_length = reader.size()?;
+    let instructions_length = reader.size()?;
+    let addresses_length = reader.size()?;
+
+    let checksum = if window_indicator & WIN_ADLER32 != 0 {
+        let bytes = reader.bytes(4)?;
+        Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    } else {
+        None
+    };
+
+    let data = section(reader.bytes(data_length)?, delta_indicator & VCD_DATACOMP != 0)?;
+    let instructions = section(reader.bytes(instructions_length)?, delta_indicator & VCD_INSTCOMP != 0)?;
+    let addresses = section(reader.bytes(addresses_length)?, delta_indicator & VCD_ADDRCOMP != 0)?;
+    let mut data = Reader::new(&data);
+    let mut instructions = Reader::new(&instructions);
+    let mut addresses = Reader::new(&addresses);
+
+    let mut cache = AddressCache::new();
+    let mut window: Vec<u8> = Vec::with_capacity(target_window_length);
+
+    while !instructions.is_at_end() {
+        let entry = &code_table[instructions.byte()? as usize];
+
+        for (kind, size, mode) in [(entry.kind1, entry.size1, entry.mode1), (entry.kind2, entry.size2, entry.mode2)] {
+            if kind == InstructionKind::Noop {
+                continue;
+            }
+
+            let size = if size == 0 { instructions.size()? } else { size as usize };
+
+            if size > target_window_length - window.len() {
+                return Err(invalid_data("VCDIFF instruction exceeds the window"));
+            }
+
+            match kind {
+                InstructionKind::Noop => {}
+                InstructionKind::Add => {
+                    window.extend_from_slice(data.bytes(size)?);
+                }
+                InstructionKind::Run => {
+                    let byte = data.byte()?;
+                    window.resize(window.len() + size, byte);
+                }
+                InstructionKind::Copy => {
+                    let here = segment.len() + window.len();
+                    let address = cache.decode(&mut addresses, here, mode)?;
+
+                    if address >= here {
+                        return Err(invalid_data("VCDIFF copy address out of range"));
+                    }
+
+                    for offset in 0..size {
+                        let position = address + offset;
+                        let byte = if position < segment.len() {
+                            segment[position]
+                        } else {
+                            *window.get(position - segment.len()).ok_or_else(|| invalid_data("VCDIFF copy address out of range"))?
+                        };
+                        window.push(byte);
+                    }
+                }
+            }
+        }
+    }
+
+    if window.len() != target_window_length {
+        return Err(invalid_data("VCDIFF window length mismatch"));
+    }
+
+    if let Some(checksum) = checksum
+        && adler32(&window) != checksum
+    {
+        return Err(invalid_data("VCDIFF window checksum mismatch"));
+    }
+
+    target.extend_from_slice(&window);
+
+    Ok(())
+}
+
+/// xdelta3 writes a compressed section as its decompressed length followed by an xz stream without a check.
+fn section(bytes: &[u8], compressed: bool) -> io::Result<Cow<'_, [u8]>> {
+    if !compressed {
+        return Ok(Cow::Borrowed(bytes));
+    }
+
+    let mut reader = Reader::new(bytes);
+    let decompressed_length = reader.size()?;
+    if decompressed_length > MAX_DECODED_WINDOW_SIZE {
+        return Err(invalid_data("VCDIFF section is too large"));
+    }
+
+    let mut decompressed = Vec::with_capacity(decompressed_length);
+    lzma_rs::xz_decompress(&mut reader.rest(), &mut decompressed).map_err(|_| invalid_data("VCDIFF section cannot be decompressed"))?;
+
+    if decompressed.len() != decompressed_length {
+        return Err(invalid_data("VCDIFF section length mismatch"));
+    }
+
+    Ok(Cow::Owned(decompressed))
+}
+
+enum Instruction {
+    Add { start: usize, length: usize },
+    Copy { address: usize, length: usize },
+}
+
+impl Instruction {
+    fn is_empty(&self) -> bool {
+        match self {
+            Instruction::Add { length, .. } => *length == 0,
+            Instruction::Copy { length, .. } => *length == 0,
+        }
+    }
+}
+
+fn find_instructions(source: &[u8], target: &[u8]) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut pending_add_start = 0;
+
+    for block_match in block_matching::find_matches(source, target) {
+        if block_match.target_start > pending_add_start {
+            instructions.push(Instruction::Add {
+                start: pending_add_start,
+                length: block_match.target_start - pending_add_start,
+            });
+        }
+
+        instructions.push(Instruction::Copy {
+            address: block_match.source_start,
+            length: block_match.length,
+        });
+
+        pending_add_start = block_match.target_start + block_match.length;
+    }
+
+    if pending_add_start < target.len() {
+        instructions.push(Instruction::Add {
+            start: pending_add_start,
+            length: target.len() - pending_add_start,
+        });
+    }
+
+    instructions
+}
+
+struct WindowEncoder {
+    source_length: usize,
+    target_length: usize,
+    data: Vec<u8>,
+    instructions: Vec<u8>,
+    addresses: Vec<u8>,
+}
+
+impl WindowEncoder {
+    fn new(source_length: usize) -> Self {
+        Self {
+            source_length,
+            target_length: 0,
+            data: Vec::new(),
+            instructions: Vec::new(),
+            addresses: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, bytes: &[u8]) {
+        // Code table entries 2..=18 are ADD instructions of size 1..=17, entry 1 is ADD with an explicit size.
+        if bytes.len() <= 17 {
+            self.instructions.push(1 + bytes.len() as u8);
+        } else {
+            self.instructions.push(1);
+            write_size(&mut self.instructions, bytes.len());
+        }
+
+        self.data.extend_from_slice(bytes);
+        self.target_length += bytes.len();
+    }
+
+    fn copy(&mut self, address: usize, length: usize) {
+        let here = self.source_length + self.target_length;
+        let here_offset = here - address;
+
+        let mode = if here_offset < address { MODE_HERE } else { MODE_SELF };
+
+        // Code table entries 19 + 16 * mode are COPY instructions with an explicit size, followed by sizes 4..=18.
+        let first_entry = 19 + 16 * mode;
+        if (4..=18).contains(&length) {
+            self.instructions.push(first_entry + length as u8 - 3);
+        } else {
+            self.instructions.push(first_entry);
+            write_size(&mut self.instructions, length);
+        }
+
+        match mode {
+            MODE_HERE => write_size(&mut self.addresses, here_offset),
+            _ => write_size(&mut self.addresses, address),
+        }
+
+        self.target_length += length;
+    }
+
+    fn write(self, delta: &mut Vec<u8>) {
+        let has_source = self.source_length > 0;
+
+        let mut encoding = Vec::new();
+        write_size(&mut encoding, self.target_length);
+        encoding.push(0); // Delta_Indicator
+        write_size(&mut encoding, self.data.len());
+        write_size(&mut encoding, self.instructions.len());
+        write_size(&mut encoding, self.addresses.len());
+        encoding.extend_from_slice(&self.data);
+        encoding.extend_from_slice(&self.instructions);
+        encoding.extend_from_slice(&self.addresses);
+
+        if has_source {
+            delta.push(WIN_SOURCE);
+            write_size(delta, self.source_length);
+            write_size(delta, 0);
+        } else {
+            delta.push(0);
+        }
+
+        write_size(delta, encoding.len());
+        delta.extend_from_slice(&encoding);
+    }
+}
+
+fn write_size(buffer: &mut Vec<u8>, value: usize) {
+    let mut value = value as u64;
+    let mut bytes = [0u8; 10];
+    let mut i = bytes.len() - 1;
+
+    bytes[i] = (value & 0x7F) as u8;
+    value >>= 7;
+
+    while value > 0 {
+        i -= 1;
+        bytes[i] = (value & 0x7F) as u8 | 0x80;
+        value >>= 7;
+    }
+
+    buffer.extend_from_slice(&bytes[i..]);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, position: 0 }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.bytes.len()
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        let byte = *self.bytes.get(self.position).ok_or_else(unexpected_end)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn bytes(&mut self, length: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(length).filter(|&end| end <= self.bytes.len()).ok_or_else(unexpected_end)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        let bytes = &self.bytes[self.position.min(self.bytes.len())..];
+        self.position = self.bytes.len();
+        bytes
+    }
+
+    fn size(&mut self) -> io::Result<usize> {
+        let mut value: u64 = 0;
+
+        loop {
+            let byte = self.byte()?;
+
+            if value > (u64::MAX >> 7) {
+                return Err(invalid_data("VCDIFF integer overflow"));
+            }
+
+            value = (value << 7) | (byte & 0x7F) as u64;
+
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+
+        usize::try_from(value).map_err(|_| invalid_data("VCDIFF integer overflow"))
+    }
+}
+
+struct AddressCache {
+    near: [usize; NEAR_CACHE_SIZE],
+    next_near_slot: usize,
+    same: [usize; SAME_CACHE_SIZE * 256],
+}
+
+impl AddressCache {
+    fn new() -> Self {
+        Self {
+            near: [0; NEAR_CACHE_SIZE],
+            next_near_slot: 0,
+            same: [0; SAME_CACHE_SIZE * 256],
+        }
+    }
+
+    fn decode(&mut self, addresses: &mut Reader, here: usize, mode: u8) -> io::Result<usize> {
+        let mode = mode as usize;
+
+        let address = if mode == MODE_SELF as usize {
+            addresses.size()?
+        } else if mode == MODE_HERE as usize {
+            here.checked_sub(addresses.size()?).ok_or_else(|| invalid_data("VCDIFF copy address out of range"))?
+        } else if mode < 2 + NEAR_CACHE_SIZE {
+            self.near[mode - 2]
+                .checked_add(addresses.size()?)
+                .ok_or_else(|| invalid_data("VCDIFF copy address out of range"))?
+        } else {
+            let same_index = (mode - 2 - NEAR_CACHE_SIZE) * 256 + addresses.byte()? as usize;
+            *self.same.get(same_index).ok_or_else(|| invalid_data("VCDIFF invalid address mode"))?
+        };
+
+        self.near[self.next_near_slot] = address;
+        self.next_near_slot = (self.next_near_slot + 1) % NEAR_CACHE_SIZE;
+        self.same[address % (SAME_CACHE_SIZE * 256)] = address;
+
+        Ok(address)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum InstructionKind {
+    Noop,
+    Add,
+    Run,
+    Copy,
+}
+
+#[derive(Copy, Clone)]
+struct CodeTableEntry {
+    kind1: InstructionKind,
+    size1: u8,
+    mode1: u8,
+    kind2: InstructionKind,
+    size2: u8,
+    mode2: u8,
+}
+
+/// Builds the default code table from RFC 3284, section 5.6.
+fn default_code_table() -> [CodeTableEntry; 256] {
+    use InstructionKind::{Add, Copy, Noop, Run};
+
+    let single = |kind, size, mode| CodeTableEntry {
+        kind1: kind,
+        size1: size,
+        mode1: mode,
+        kind2: Noop,
+        size2: 0,
+        mode2: 0,
+    };
+
+    let mut table = [single(Noop, 0, 0); 256];
+    let mut i = 0;
+
+    table[i] = single(Run, 0, 0);
+    i += 1;
+
+    for size in 0..=17 {
+        table[i] = single(Add, size, 0);
+        i += 1;
+    }
+
+    for mode in 0..=8 {
+        table[i] = single(Copy, 0, mode);
+        i += 1;
+
+        for size in 4..=18 {
+            table[i] = single(Copy, size, mode);
+            i += 1;
+        }
+    }
+
+    let pair = |kind1, size1, mode1, kind2, size2, mode2| CodeTableEntry {
+        kind1,
+        size1,
+        mode1,
+        kind2,
+        size2,
+        mode2,
+    };
+
+    for mode in 0..=5 {
+        for add_size in 1..=4 {
+            for copy_size in 4..=6 {
+                table[i] = pair(Add, add_size, 0, Copy, copy_size, mode);
+                i += 1;
+            }
+        }
+    }
+
+    for mode in 6..=8 {
+        for add_size in 1..=4 {
+            table[i] = pair(Add, add_size, 0, Copy, 4, mode);
+            i += 1;
+        }
+    }
+
+    for mode in 0..=8 {
+        table[i] = pair(Copy, 4, mode, Add, 1, 0);
+        i += 1;
+    }
+
+    debug_assert_eq!(i, 256);
+
+    table
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MOD_ADLER: u32 = 65521;
+
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+
+    for chunk in bytes.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+
+    (b << 16) | a
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn unexpected_end() -> io::Error {
+    io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of VCDIFF delta")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_data::{edited, pseudo_random_bytes};
+
+    const SOURCE: &[u8] = b"The quick brown fox jumps over the lazy dog.";
+    const TARGET: &[u8] = b"The quick brown cat jumps over the lazy dog.";
+
+    /// A delta from SOURCE to TARGET laid out as `xdelta3 -e -S none -s old new` writes it: an application header
+    /// naming the files, and a single source window with an Adler-32 checksum.
+    #[rustfmt::skip]
+    const XDELTA3_DELTA: &[u8] = &[
+        0xD6, 0xC3, 0xC4, 0x00, // magic
+        HDR_APPHEADER,
+        9, b'n', b'e', b'w', b'/', b'/', b'o', b'l', b'd', b'/', // application header
+        WIN_SOURCE | WIN_ADLER32,
+        44, 0, // source segment length and position
+        18, // delta encoding length
+        44, // target window length
+        0, // delta indicator
+        3, 4, 2, // data, instructions and addresses lengths
+        0x69, 0xAE, 0x0F, 0xF3, // Adler-32 of the target window
+        b'c', b'a', b't', // data
+        32, 4, 19, 25, // COPY 16 (mode 0), ADD 3, COPY (mode 0) of size 25
+        0, 19, // addresses
+    ];
+
+    /// The same delta with every section compressed by xdelta3's LZMA secondary compressor (`-S lzma`): each section is
+    /// its decompressed length followed by an xz stream with an LZMA2 filter and no check, as liblzma writes it for xdelta3.
+    #[rustfmt::skip]
+    const XDELTA3_LZMA_DELTA: &[u8] = &[
+        0xD6, 0xC3, 0xC4, 0x00, // magic
+        HDR_DECOMPRESS | HDR_APPHEADER,
+        LZMA_ID,
+        9, b'n', b'e', b'w', b'/', b'/', b'o', b'l', b'd', b'/', // application header
+        WIN_SOURCE | WIN_ADLER32,
+        44, 0, // source segment length and position
+        0x81, 0x28, // delta encoding length
+        44, // target window length
+        VCD_DATACOMP | VCD_INSTCOMP | VCD_ADDRCOMP,
+        53, 53, 53, // data, instructions and addresses lengths
+        0x69, 0xAE, 0x0F, 0xF3, // Adler-32 of the target window
+        // data
+        0x03, 0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, 0x00, 0x00, 0xFF, 0x12, 0xD9, 0x41, 0x02, 0x00, 0x21, 0x01, 0x14,
+        0x00, 0x00, 0x00, 0xFF, 0xE7, 0xEC, 0x09, 0x01, 0x00, 0x02, 0x63, 0x61, 0x74, 0x00, 0x00, 0x00, 0x01, 0x13,
+        0x03, 0x03, 0xA5, 0x60, 0xD8, 0x06, 0x72, 0x9E, 0x7A, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x59, 0x5A,
+        // instructions
+        0x04, 0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, 0x00, 0x00, 0xFF, 0x12, 0xD9, 0x41, 0x02, 0x00, 0x21, 0x01, 0x14,
+        0x00, 0x00, 0x00, 0xFF, 0xE7, 0xEC, 0x09, 0x01, 0x00, 0x03, 0x20, 0x04, 0x13, 0x19, 0x00, 0x00, 0x01, 0x14,
+        0x04, 0x67, 0xA6, 0x45, 0x09, 0x06, 0x72, 0x9E, 0x7A, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x59, 0x5A,
+        // addresses
+        0x02, 0xFD, 0x37, 0x7A, 0x58, 0x5A, 0x00, 0x00, 0x00, 0xFF, 0x12, 0xD9, 0x41, 0x02, 0x00, 0x21, 0x01, 0x14,
+        0x00, 0x00, 0x00, 0xFF, 0xE7, 0xEC, 0x09, 0x01, 0x00, 0x01, 0x00, 0x13, 0x00, 0x00, 0x00, 0x00, 0x01, 0x12,
+        0x02, 0xD4, 0xA4, 0x7C, 0xB6, 0x06, 0x72, 0x9E, 0x7A, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x59, 0x5A,
+    ];
+
+    fn assert_round_trip(source: &[u8], target: &[u8]) {
+        let delta = encode(source, target);
+        assert_eq!(decode(source, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn round_trips_edited_content() {
+        let source = pseudo_random_bytes(200_000, 1);
+        assert_round_trip(&source, &edited(&source));
+    }
+
+    #[test]
+    fn round_trips_unrelated_content() {
+        assert_round_trip(&pseudo_random_bytes(10_000, 2), &pseudo_random_bytes(12_000, 3));
+    }
+
+    #[test]
+    fn round_trips_empty_source_and_target() {
+        let content = pseudo_random_bytes(1000, 4);
+        assert_round_trip(&[], &content);
+        assert_round_trip(&content, &[]);
+        assert_round_trip(&[], &[]);
+    }
+
+    #[test]
+    fn round_trips_content_spanning_several_windows() {
+        let source = pseudo_random_bytes(TARGET_WINDOW_SIZE + TARGET_WINDOW_SIZE / 2, 5);
+        assert_round_trip(&source, &edited(&source));
+    }
+
+    #[test]
+    fn decodes_delta_in_xdelta3_layout() {
+        assert_eq!(decode(SOURCE, XDELTA3_DELTA).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn decodes_delta_with_lzma_secondary_compression() {
+        assert_eq!(decode(SOURCE, XDELTA3_LZMA_DELTA).unwrap(), TARGET);
+    }
+
+    #[test]
+    fn rejects_corrupted_lzma_section() {
+        let mut delta = XDELTA3_LZMA_DELTA.to_vec();
+        let data_position = delta.len() - 3 * 53 + 28;
+        delta[data_position] = b'b';
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut delta = XDELTA3_LZMA_DELTA.to_vec();
+        let data_length_position = delta.len() - 3 * 53;
+        delta[data_length_position] = 4;
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn djw_and_fgk_secondary_compression_are_unsupported() {
+        for compressor_id in [DJW_ID, FGK_ID] {
+            let mut delta = XDELTA3_LZMA_DELTA.to_vec();
+            delta[5] = compressor_id;
+            assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::Unsupported);
+        }
+    }
+
+    #[test]
+    fn rejects_compressed_sections_without_a_secondary_compressor() {
+        let mut delta = XDELTA3_DELTA.to_vec();
+        let delta_indicator_position = MAGIC.len() + 1 + 10 + 5;
+        delta[delta_indicator_position] = VCD_DATACOMP;
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn custom_code_table_is_unsupported() {
+        let mut delta = XDELTA3_DELTA.to_vec();
+        delta[4] |= HDR_CODETABLE;
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let mut delta = XDELTA3_DELTA.to_vec();
+        let data_position = delta.len() - 9;
+        delta[data_position] = b'b';
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_delta_without_magic() {
+        assert_eq!(decode(SOURCE, &XDELTA3_DELTA[1..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert_eq!(decode(SOURCE, &[]).unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_truncated_delta() {
+        let source = pseudo_random_bytes(10_000, 6);
+        let delta = encode(&source, &edited(&source));
+
+        for length in [6, 7, delta.len() / 2, delta.len() - 1] {
+            assert!(decode(&source, &delta[..length]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_instructions_beyond_the_window() {
+        // A window claiming a huge target length whose RUN instruction would fill it.
+        let delta = [
+            0xD6, 0xC3, 0xC4, 0x00, 0, 0, 12, 0x8F, 0xFF, 0xFF, 0xFF, 0x7F, 0, 1, 6, 0, b'x', 0, 0x8F, 0xFF, 0xFF, 0xFF, 0x7F,
+        ];
+        assert_eq!(decode(&[], &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A window whose instructions produce more bytes than its target length.
+        let mut delta = XDELTA3_DELTA.to_vec();
+        let target_window_length_position = MAGIC.len() + 1 + 10 + 4;
+        delta[target_window_length_position] = 40;
+        assert_eq!(decode(SOURCE, &delta).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
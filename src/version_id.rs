@@ -8,7 +8,7 @@ impl VersionId {
     pub fn new() -> Self {
         Self(Uuid::new_v4())
     }
-    pub fn to_file_name(&self) -> String {
+    pub fn to_file_name(self) -> String {
         self.0.to_string()
     }
     pub fn bs58(&self) -> String {
@@ -15,13 +15,13 @@ pub fn show_preview(image_path: &Path) -> BiverResult<()> {
 }
 
 pub fn show_comparison(image_path1: &Path, description1: &str, image_path2: &Path, description2: &str) -> BiverResult<()> {
-    let image1 = egui_image_from_file(&image_path1)?;
-    let image2 = egui_image_from_file(&image_path2)?;
+    let image1 = egui_image_from_file(image_path1)?;
+    let image2 = egui_image_from_file(image_path2)?;
 
     eframe::run_native(
-        &description1,
+        description1,
         egui_options(),
-        Box::new(|cc| Ok(Box::new(ComparerApp::new(cc, image1, &description1, image2, description2)))),
+        Box::new(|cc| Ok(Box::new(ComparerApp::new(cc, image1, description1, image2, description2)))),
     )?;
 
     Ok(())
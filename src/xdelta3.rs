@@ -14,18 +14,6 @@ pub fn ready(env: &impl XDelta3Env) -> bool {
     }
 }
 
-pub fn create_patch(env: &impl XDelta3Env, old: &Path, new: &Path, patch: &Path) -> io::Result<()> {
-    let status = xdelta3_command(env)
-        .arg("-e") // compress
-        .arg("-s") // source
-        .arg(old)
-        .arg(new)
-        .arg(patch)
-        .status();
-
-    map_xdelta3_status(status)
-}
-
 pub fn apply_patch(env: &impl XDelta3Env, old: &Path, patch: &Path, new: &Path) -> io::Result<()> {
     if fs::exists(new)? {
         fs::remove_file(new)?;
@@ -43,13 +31,7 @@ pub fn apply_patch(env: &impl XDelta3Env, old: &Path, patch: &Path, new: &Path)
 }
 
 fn map_xdelta3_status(status_result: io::Result<ExitStatus>) -> io::Result<()> {
-    status_result.and_then(|status| {
-        if status.success() {
-            Ok(())
-        } else {
-            Err(io::Error::new(io::ErrorKind::Other, "xdelta3 failed."))
-        }
-    })
+    status_result.and_then(|status| if status.success() { Ok(()) } else { Err(io::Error::other("xdelta3 failed.")) })
 }
 
 fn xdelta3_command(env: &impl XDelta3Env) -> Command {